@@ -0,0 +1,153 @@
+use image::{ImageBuffer, RgbImage};
+use rand::Rng;
+
+use crate::basic::{camera::Camera, vec::Color};
+use crate::denoise::Framebuffer;
+use crate::hittable::{bvh::BvhNode, Hittable};
+use crate::render::RenderSettings;
+
+/// Auxiliary outputs. These are data rather than pictures, so they are encoded
+/// linearly and never pass through tone mapping or gamma.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Aov {
+    // Shading normal, [-1, 1] mapped to [0, 255].
+    Normal,
+    // Surface color at the first hit.
+    Albedo,
+    // Hit distance, normalized by the farthest hit in the image.
+    Depth,
+}
+
+impl Aov {
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Depth => "depth",
+        }
+    }
+}
+
+pub fn render_aov(world: &BvhNode, cam: Camera, settings: &RenderSettings, aov: Aov) -> RgbImage {
+    let (values, max_depth) = first_hit_values(world, cam, settings, aov);
+
+    let mut img: RgbImage = ImageBuffer::new(settings.image_width, settings.image_height);
+    for (pixel, value) in img.pixels_mut().zip(values) {
+        let encoded = match (aov, value) {
+            (Aov::Normal, Some(n)) => (n + 1.) * 0.5,
+            (Aov::Normal, None) => Color::new(0.5, 0.5, 0.5),
+            (Aov::Depth, Some(d)) if max_depth > 0. => d / max_depth,
+            (_, Some(c)) => c,
+            // Background is as far away as it gets.
+            (Aov::Depth, None) => Color::new(1., 1., 1.),
+            (_, None) => Color::new(0., 0., 0.),
+        };
+        *pixel = image::Rgb([
+            (encoded.x.clamp(0., 1.) * 255.).round() as u8,
+            (encoded.y.clamp(0., 1.) * 255.).round() as u8,
+            (encoded.z.clamp(0., 1.) * 255.).round() as u8,
+        ]);
+    }
+    img
+}
+
+/// Unencoded AOV values, background as zero, e.g. as denoiser features.
+pub fn aov_framebuffer(
+    world: &BvhNode,
+    cam: Camera,
+    settings: &RenderSettings,
+    aov: Aov,
+) -> Framebuffer {
+    let (values, _) = first_hit_values(world, cam, settings, aov);
+    Framebuffer {
+        width: settings.image_width,
+        height: settings.image_height,
+        pixels: values
+            .into_iter()
+            .map(|value| value.unwrap_or_else(|| Color::new(0., 0., 0.)))
+            .collect(),
+    }
+}
+
+/// One sample per pixel in image order, plus the farthest hit distance.
+fn first_hit_values(
+    world: &BvhNode,
+    cam: Camera,
+    settings: &RenderSettings,
+    aov: Aov,
+) -> (Vec<Option<Color>>, f64) {
+    let image_width = settings.image_width;
+    let image_height = settings.image_height;
+
+    let mut values = Vec::<Option<Color>>::new();
+    let mut max_depth: f64 = 0.;
+    let mut rng = rand::thread_rng();
+    for y in (0..image_height).rev() {
+        for x in 0..image_width {
+            let u = (x as f64 + rng.gen::<f64>()) / (image_width - 1) as f64;
+            let v = (y as f64 + rng.gen::<f64>()) / (image_height - 1) as f64;
+            let r = cam.get_ray(u, v, &mut rng);
+            let value = world.hit(r, 0.001, f64::MAX).map(|rec| match aov {
+                Aov::Normal => rec.normal,
+                Aov::Albedo => match rec.mat_ptr.scatter(r, &rec, &mut rng) {
+                    Some(srec) => srec.attenuation,
+                    None => Color::new(0., 0., 0.),
+                },
+                Aov::Depth => {
+                    let distance = rec.distance_from(r.orig);
+                    max_depth = max_depth.max(distance);
+                    Color::new(distance, distance, distance)
+                }
+            });
+            values.push(value);
+        }
+    }
+    (values, max_depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::camera::DEFAULT_VUP;
+    use crate::basic::vec::Point3;
+    use crate::hittable::{aarect::XYRect, HittableList};
+    use crate::material::Lambertian;
+    use crate::render::ToneMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn normals_skip_tone_mapping() {
+        let mut list = HittableList::default();
+        list.add(Arc::new(XYRect::new(
+            -10.,
+            10.,
+            -10.,
+            10.,
+            -1.,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )));
+        let world = BvhNode::new_list(&list, 0., 1.);
+        let cam = Camera::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            1.,
+            0.,
+            1.,
+            0.,
+            1.,
+        );
+        let settings = RenderSettings {
+            image_width: 8,
+            image_height: 8,
+            tone_map: ToneMap::Aces,
+            ..RenderSettings::default()
+        };
+        let img = render_aov(&world, cam, &settings, Aov::Normal);
+        for pixel in img.pixels() {
+            assert_eq!(pixel.0, [128, 128, 255]);
+        }
+    }
+}
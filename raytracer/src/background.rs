@@ -0,0 +1,307 @@
+use std::f64::consts::PI;
+
+use rand::{Rng, RngCore};
+
+use crate::basic::ray::Ray;
+use crate::basic::vec::{Color, Point3, Vec3};
+use crate::hittable::{bvh::aabb::AABB, HitRecord, Hittable};
+use crate::texture::Texture;
+
+/// Radiance arriving from infinitely far away, seen by rays that miss the world.
+pub trait Background: Sync {
+    fn sample(&self, dir: Vec3) -> Color;
+}
+
+/// A solid color.
+impl Background for Color {
+    fn sample(&self, _dir: Vec3) -> Color {
+        *self
+    }
+}
+
+/// Blends from `bottom` to `top` with the direction's height, like the sky in
+/// the first book.
+#[derive(Clone, Copy)]
+pub struct GradientBackground {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl Background for GradientBackground {
+    fn sample(&self, dir: Vec3) -> Color {
+        let t = 0.5 * (dir.to_unit().y + 1.);
+        self.bottom * (1. - t) + self.top * t
+    }
+}
+
+/// Equirectangular map, mapped like the texture of a sphere seen from inside.
+#[derive(Clone)]
+pub struct EnvMapBackground<T: Texture> {
+    pub texture: T,
+}
+
+impl<T: Texture> Background for EnvMapBackground<T> {
+    fn sample(&self, dir: Vec3) -> Color {
+        let d = dir.to_unit();
+        let theta = (-d.y).acos();
+        let phi = f64::atan2(-d.z, d.x) + PI;
+        self.texture.value(phi / (2. * PI), theta / PI, d)
+    }
+}
+
+/// Six faces in the order +X, -X, +Y, -Y, +Z, -Z, oriented as in OpenGL.
+#[derive(Clone)]
+pub struct CubeMapBackground<T: Texture> {
+    pub faces: [T; 6],
+}
+
+impl<T: Texture> Background for CubeMapBackground<T> {
+    fn sample(&self, dir: Vec3) -> Color {
+        let (ax, ay, az) = (dir.x.abs(), dir.y.abs(), dir.z.abs());
+        // Face, then the face's s and t axes scaled by the major axis.
+        let (face, s, t, ma) = if ax >= ay && ax >= az {
+            if dir.x > 0. {
+                (0, -dir.z, -dir.y, ax)
+            } else {
+                (1, dir.z, -dir.y, ax)
+            }
+        } else if ay >= az {
+            if dir.y > 0. {
+                (2, dir.x, dir.z, ay)
+            } else {
+                (3, dir.x, -dir.z, ay)
+            }
+        } else if dir.z > 0. {
+            (4, dir.x, -dir.y, az)
+        } else {
+            (5, -dir.x, -dir.y, az)
+        };
+        // t grows downwards on a face, v upwards in a texture.
+        let u = 0.5 * (s / ma + 1.);
+        let v = 0.5 * (1. - t / ma);
+        self.faces[face].value(u, v, dir)
+    }
+}
+
+/// `a` and `b` mixed, `weight` being the share of `b`.
+#[derive(Clone)]
+pub struct BlendBackground<A: Background, B: Background> {
+    pub a: A,
+    pub b: B,
+    pub weight: f64,
+}
+
+impl<A: Background, B: Background> Background for BlendBackground<A, B> {
+    fn sample(&self, dir: Vec3) -> Color {
+        self.a.sample(dir) * (1. - self.weight) + self.b.sample(dir) * self.weight
+    }
+}
+
+/// Importance sampler for an `EnvMapBackground`, picking texels in proportion
+/// to their luminance times their solid angle. The weights form a pyramid
+/// where every texel sums its 2x2 children, so building is linear in the
+/// number of texels and sampling walks down one child per level.
+///
+/// Add it to the lights to aim scattered rays at the bright parts of the sky;
+/// it never gets hit itself.
+pub struct EnvMapSampler {
+    width: usize,
+    height: usize,
+    // Finest level first, padded to powers of two; the last one is 1x1.
+    levels: Vec<(usize, usize, Vec<f64>)>,
+}
+
+impl EnvMapSampler {
+    /// Samples `texture` at the centers of a `width` x `height` grid, which
+    /// should match its resolution.
+    pub fn new<T: Texture>(texture: &T, width: usize, height: usize) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let (pw, ph) = (width.next_power_of_two(), height.next_power_of_two());
+        let mut weights = vec![0.; pw * ph];
+        for j in 0..height {
+            let v = (j as f64 + 0.5) / height as f64;
+            let sin_theta = (v * PI).sin();
+            for i in 0..width {
+                let u = (i as f64 + 0.5) / width as f64;
+                let c = texture.value(u, v, EnvMapSampler::direction(u, v));
+                let luminance = 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+                weights[j * pw + i] = luminance.max(0.) * sin_theta;
+            }
+        }
+        // A black map is sampled uniformly over the sphere instead.
+        if weights.iter().sum::<f64>() <= 0. {
+            for j in 0..height {
+                let sin_theta = ((j as f64 + 0.5) / height as f64 * PI).sin();
+                for i in 0..width {
+                    weights[j * pw + i] = sin_theta;
+                }
+            }
+        }
+
+        let mut levels = vec![(pw, ph, weights)];
+        loop {
+            let (w, h, fine) = levels.last().unwrap();
+            let (w, h) = (*w, *h);
+            if w == 1 && h == 1 {
+                break;
+            }
+            let (nw, nh) = ((w + 1) / 2, (h + 1) / 2);
+            let mut coarse = vec![0.; nw * nh];
+            for y in 0..h {
+                for x in 0..w {
+                    coarse[y / 2 * nw + x / 2] += fine[y * w + x];
+                }
+            }
+            levels.push((nw, nh, coarse));
+        }
+        Self {
+            width,
+            height,
+            levels,
+        }
+    }
+
+    /// Direction seen at `(u, v)` of the map, inverting `EnvMapBackground`.
+    fn direction(u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2. * PI - PI;
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            -theta.cos(),
+            -theta.sin() * phi.sin(),
+        )
+    }
+
+    fn total(&self) -> f64 {
+        self.levels.last().unwrap().2[0]
+    }
+
+    /// Picks a texel by walking down the pyramid with `xi` in `[0, 1)`.
+    fn pick(&self, mut xi: f64) -> (usize, usize) {
+        let (mut x, mut y) = (0, 0);
+        for (w, h, weights) in self.levels.iter().rev().skip(1) {
+            let children: Vec<(usize, usize)> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|(dx, dy)| (2 * x + dx, 2 * y + dy))
+                .filter(|(cx, cy)| cx < w && cy < h)
+                .collect();
+            let sum: f64 = children.iter().map(|(cx, cy)| weights[cy * w + cx]).sum();
+            let mut target = xi * sum;
+            let mut chosen = *children.last().unwrap();
+            for (cx, cy) in &children {
+                let weight = weights[cy * w + cx];
+                if target < weight {
+                    chosen = (*cx, *cy);
+                    // Rescale what is left of `xi` for the next level.
+                    xi = (target / weight).min(1. - f64::EPSILON);
+                    break;
+                }
+                target -= weight;
+            }
+            x = chosen.0;
+            y = chosen.1;
+        }
+        (x, y)
+    }
+
+    /// Solid angle density of `random` picking `dir`.
+    pub fn pdf(&self, dir: Vec3) -> f64 {
+        let d = dir.to_unit();
+        let theta = (-d.y).acos();
+        let phi = f64::atan2(-d.z, d.x) + PI;
+        let sin_theta = theta.sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        let i = ((phi / (2. * PI) * self.width as f64) as usize).min(self.width - 1);
+        let j = ((theta / PI * self.height as f64) as usize).min(self.height - 1);
+        let (pw, _, weights) = &self.levels[0];
+        let texel_probability = weights[j * pw + i] / self.total();
+        texel_probability * (self.width * self.height) as f64 / (2. * PI * PI * sin_theta)
+    }
+
+    /// A direction drawn with density `pdf`.
+    pub fn sample_direction(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let (i, j) = self.pick(rng.gen());
+        let u = (i as f64 + rng.gen::<f64>()) / self.width as f64;
+        let v = (j as f64 + rng.gen::<f64>()) / self.height as f64;
+        EnvMapSampler::direction(u, v)
+    }
+}
+
+impl Hittable for EnvMapSampler {
+    fn hit(&self, _r: Ray, _t_min: f64, _t_max: f64) -> Option<HitRecord> {
+        None
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        None
+    }
+    fn pdf_value(&self, _o: Point3, v: Vec3, _time: f64) -> f64 {
+        self.pdf(v)
+    }
+    fn random(&self, _o: Point3, _time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        self.sample_direction(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::sampler::SampleSequence;
+    use crate::texture::HdrImageTexture;
+
+    // An 8x4 sky, dim but for one bright texel just above the horizon.
+    fn one_bright_texel() -> HdrImageTexture {
+        let mut pixels = vec![Color::new(0.01, 0.01, 0.01); 8 * 4];
+        pixels[8 + 5] = Color::new(100., 100., 100.);
+        HdrImageTexture::from_pixels(8, 4, pixels)
+    }
+
+    #[test]
+    fn blends_mix_by_weight() {
+        let top = Color::new(0.5, 0.7, 1.);
+        let blend = BlendBackground {
+            a: Color::new(0., 0., 0.),
+            b: GradientBackground {
+                bottom: Color::new(1., 1., 1.),
+                top,
+            },
+            weight: 0.5,
+        };
+        let up = blend.sample(Vec3::new(0., 2., 0.));
+        assert!(Color::distance(up, top * 0.5) < 1e-12);
+    }
+
+    #[test]
+    fn env_map_samples_land_on_the_bright_texel() {
+        let texture = one_bright_texel();
+        let sampler = EnvMapSampler::new(&texture, 8, 4);
+        let sky = EnvMapBackground { texture };
+        let n = 2000;
+        let bright = (0..n)
+            .filter(|&i| {
+                let mut rng = SampleSequence::new(i, 0, 0, 1);
+                sky.sample(sampler.sample_direction(&mut rng)).x > 50.
+            })
+            .count();
+        // The texel holds over 99% of the weight.
+        assert!(bright as f64 / n as f64 > 0.95, "{} of {}", bright, n);
+    }
+
+    #[test]
+    fn env_map_pdf_integrates_to_one() {
+        let sampler = EnvMapSampler::new(&one_bright_texel(), 8, 4);
+        let (rows, columns) = (200, 400);
+        let (d_theta, d_phi) = (PI / rows as f64, 2. * PI / columns as f64);
+        let mut integral = 0.;
+        for j in 0..rows {
+            let v = (j as f64 + 0.5) / rows as f64;
+            for i in 0..columns {
+                let u = (i as f64 + 0.5) / columns as f64;
+                let dir = EnvMapSampler::direction(u, v);
+                integral += sampler.pdf(dir) * (v * PI).sin() * d_theta * d_phi;
+            }
+        }
+        assert!((integral - 1.).abs() < 1e-3, "{}", integral);
+    }
+}
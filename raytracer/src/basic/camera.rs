@@ -1,14 +1,40 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use crate::basic::vec::random_in_unit_disk;
 
 use super::ray::Ray;
 use super::vec::{Point3, Vec3};
 
+/// The up vector cameras are usually built with.
+pub const DEFAULT_VUP: Vec3 = Vec3 {
+    x: 0.,
+    y: 1.,
+    z: 0.,
+};
+
+/// The values a camera was built from, kept for reporting.
+#[derive(Copy, Clone)]
+pub struct CameraParams {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aspect_ratio: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    // Focus distance at `time1`, the same as `focus_dist` unless it is pulled.
+    pub focus_dist1: f64,
+    pub time0: f64,
+    pub time1: f64,
+}
+
 #[derive(Copy, Clone)]
 pub struct Camera {
+    params: CameraParams,
     origin: Point3,
-    lower_left_corner: Point3,
+    // The viewport one unit in front of the lens, relative to `origin`; it is
+    // scaled out to the focus distance at the ray's time.
+    lower_left_dir: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
     u: Vec3,
@@ -38,18 +64,30 @@ impl Camera {
         let viewport_width = aspect_ratio * viewport_height;
 
         let w = (lookfrom - lookat).to_unit();
-        let u = Vec3::cross(vup, w).to_unit();
+        let u = Vec3::cross(Camera::usable_vup(vup, w), w).to_unit();
         let v = Vec3::cross(w, u);
 
         let origin = lookfrom;
-        let horizontal = u * viewport_width * focus_dist;
-        let vertical = v * viewport_height * focus_dist;
-        let lower_left_corner = origin - horizontal / 2. - vertical / 2. - w * focus_dist;
+        let horizontal = u * viewport_width;
+        let vertical = v * viewport_height;
+        let lower_left_dir = -horizontal / 2. - vertical / 2. - w;
         let lens_radius = aperture / 2.;
 
         Self {
+            params: CameraParams {
+                lookfrom,
+                lookat,
+                vup,
+                vfov,
+                aspect_ratio,
+                aperture,
+                focus_dist,
+                focus_dist1: focus_dist,
+                time0,
+                time1,
+            },
             origin,
-            lower_left_corner,
+            lower_left_dir,
             horizontal,
             vertical,
             u,
@@ -61,14 +99,163 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.x + self.v * rd.y;
-        let mut rng = rand::thread_rng();
+    /// `vup`, unless it is (nearly) parallel to the view direction `w` and
+    /// would leave the basis NaN; then the world axis least aligned with `w`.
+    fn usable_vup(vup: Vec3, w: Vec3) -> Vec3 {
+        if Vec3::cross(vup, w).length_squared() > 1e-12 * vup.length_squared() {
+            return vup;
+        }
+        let (x, y, z) = (w.x.abs(), w.y.abs(), w.z.abs());
+        if x <= y && x <= z {
+            Vec3::new(1., 0., 0.)
+        } else if y <= z {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(0., 0., 1.)
+        }
+    }
+
+    /// Sweeps the plane of focus linearly from `focus_dist` at `time0` to
+    /// `focus_dist1` at `time1`, for a focus pull over the shutter interval.
+    pub fn with_focus_pull(mut self, focus_dist1: f64) -> Self {
+        self.params.focus_dist1 = focus_dist1;
+        self
+    }
+
+    /// The same camera moved `distance` along its right axis, looking the same
+    /// way, e.g. one eye of a stereo pair.
+    pub fn moved_right(&self, distance: f64) -> Self {
+        let p = self.params;
+        let offset = self.u * distance;
+        Camera::new(
+            p.lookfrom + offset,
+            p.lookat + offset,
+            p.vup,
+            p.vfov,
+            p.aspect_ratio,
+            p.aperture,
+            p.focus_dist,
+            p.time0,
+            p.time1,
+        )
+        .with_focus_pull(p.focus_dist1)
+    }
+
+    pub fn params(&self) -> &CameraParams {
+        &self.params
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, rng: &mut dyn RngCore) -> Ray {
+        let rd = random_in_unit_disk(rng);
+        let time = rng.gen();
+        self.get_ray_at(s, t, (rd.x, rd.y), time)
+    }
+
+    /// `lens` is a point on the unit disk, `time` a fraction of the shutter interval.
+    pub fn get_ray_at(&self, s: f64, t: f64, lens: (f64, f64), time: f64) -> Ray {
+        let offset = (self.u * lens.0 + self.v * lens.1) * self.lens_radius;
+        let focus_dist =
+            self.params.focus_dist + (self.params.focus_dist1 - self.params.focus_dist) * time;
         Ray::new(
             self.origin + offset,
-            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
-            rng.gen_range(self.time0..self.time1),
+            (self.lower_left_dir + self.horizontal * s + self.vertical * t) * focus_dist - offset,
+            self.time0 + (self.time1 - self.time0) * time,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How far apart rays through the image center from opposite edges of
+    /// the lens are `depth` in front of a camera looking down -z.
+    fn blur_at(cam: &Camera, time: f64, depth: f64) -> f64 {
+        let point = |lens| {
+            let r = cam.get_ray_at(0.5, 0.5, lens, time);
+            r.at(depth / -r.dir.z)
+        };
+        Vec3::distance(point((1., 0.)), point((-1., 0.)))
+    }
+
+    #[test]
+    fn focus_pull_sweeps_the_sharp_plane() {
+        let (near, far) = (2., 6.);
+        let cam = Camera::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            1.,
+            0.5,
+            near,
+            0.,
+            1.,
         )
+        .with_focus_pull(far);
+
+        // Three frames across the shutter: near sharp, neither, far sharp.
+        assert!(blur_at(&cam, 0., near) < 1e-9);
+        assert!(blur_at(&cam, 0., far) > 0.1);
+        assert!(blur_at(&cam, 0.5, near) > 0.1 && blur_at(&cam, 0.5, far) > 0.1);
+        assert!(blur_at(&cam, 0.5, 4.) < 1e-9);
+        assert!(blur_at(&cam, 1., near) > 0.1);
+        assert!(blur_at(&cam, 1., far) < 1e-9);
+    }
+
+    #[test]
+    fn fixed_focus_keeps_its_plane() {
+        let cam = Camera::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            1.,
+            0.5,
+            3.,
+            0.,
+            1.,
+        );
+        for &time in [0., 0.5, 1.].iter() {
+            assert!(blur_at(&cam, time, 3.) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn looking_along_vup_still_gives_a_basis() {
+        for &(lookat, vup) in &[
+            (Point3::new(0., -5., 0.), DEFAULT_VUP),
+            (Point3::new(0., 5., 0.), DEFAULT_VUP),
+            (Point3::new(0., 0., -5.), Vec3::new(0., 0., 2.)),
+        ] {
+            let cam = Camera::new(
+                Point3::new(0., 0., 0.),
+                lookat,
+                vup,
+                40.,
+                1.5,
+                0.,
+                1.,
+                0.,
+                1.,
+            );
+            let center = cam.get_ray_at(0.5, 0.5, (0., 0.), 0.).dir.to_unit();
+            assert!((center - lookat.to_unit()).length() < 1e-12);
+            // Right and up are finite, perpendicular, and square to the view.
+            let right = cam.get_ray_at(1., 0.5, (0., 0.), 0.).dir
+                - cam.get_ray_at(0., 0.5, (0., 0.), 0.).dir;
+            let up = cam.get_ray_at(0.5, 1., (0., 0.), 0.).dir
+                - cam.get_ray_at(0.5, 0., (0., 0.), 0.).dir;
+            assert!(right.length().is_finite() && right.length() > 0.);
+            assert!(Vec3::dot(right, up).abs() < 1e-9);
+            assert!(Vec3::dot(right, center).abs() < 1e-9 && Vec3::dot(up, center).abs() < 1e-9);
+            assert!((right.length() / up.length() - 1.5).abs() < 1e-9);
+        }
+        // A usable vup is kept as given.
+        let w = Vec3::new(0., 0., 1.);
+        assert!((Camera::usable_vup(DEFAULT_VUP, w) - DEFAULT_VUP).length() < 1e-12);
+        // Straight down, the least aligned world axis is x.
+        let down = Camera::usable_vup(DEFAULT_VUP, Vec3::new(0., 1., 0.));
+        assert!((down - Vec3::new(1., 0., 0.)).length() < 1e-12);
     }
 }
@@ -0,0 +1,89 @@
+use super::vec::Vec3;
+
+/// Axis conventions of imported assets. The renderer itself is right-handed
+/// with +Y up, like glTF.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CoordinateConvention {
+    YUpRightHanded,
+    // Blender, 3ds Max.
+    ZUpRightHanded,
+    // Unity, DirectX.
+    YUpLeftHanded,
+    // Unreal.
+    ZUpLeftHanded,
+}
+
+impl CoordinateConvention {
+    /// Maps a position or direction given in this convention to world space.
+    pub fn to_world(self, v: Vec3) -> Vec3 {
+        match self {
+            CoordinateConvention::YUpRightHanded => v,
+            // Rotate -90 degrees about X.
+            CoordinateConvention::ZUpRightHanded => Vec3::new(v.x, v.z, -v.y),
+            CoordinateConvention::YUpLeftHanded => Vec3::new(v.x, v.y, -v.z),
+            CoordinateConvention::ZUpLeftHanded => Vec3::new(v.x, v.z, v.y),
+        }
+    }
+
+    /// Whether `to_world` is a mirroring, which reverses triangle winding.
+    pub fn is_mirrored(self) -> bool {
+        matches!(
+            self,
+            CoordinateConvention::YUpLeftHanded | CoordinateConvention::ZUpLeftHanded
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [CoordinateConvention; 4] = [
+        CoordinateConvention::YUpRightHanded,
+        CoordinateConvention::ZUpRightHanded,
+        CoordinateConvention::YUpLeftHanded,
+        CoordinateConvention::ZUpLeftHanded,
+    ];
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-12, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn up_maps_to_world_up() {
+        let y = Vec3::new(0., 1., 0.);
+        let z = Vec3::new(0., 0., 1.);
+        assert_close(CoordinateConvention::YUpRightHanded.to_world(y), y);
+        assert_close(CoordinateConvention::YUpLeftHanded.to_world(y), y);
+        assert_close(CoordinateConvention::ZUpRightHanded.to_world(z), y);
+        assert_close(CoordinateConvention::ZUpLeftHanded.to_world(z), y);
+        // Blender's front, -Y, faces the renderer's +Z.
+        assert_close(
+            CoordinateConvention::ZUpRightHanded.to_world(Vec3::new(0., -1., 0.)),
+            z,
+        );
+    }
+
+    #[test]
+    fn mirrored_conventions_need_their_winding_flipped() {
+        let (a, b, c) = (
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 0.2, 0.),
+            Vec3::new(0.1, 1., 0.3),
+        );
+        let normal = Vec3::cross(b - a, c - a);
+        for &convention in &ALL {
+            let (a, mut b, mut c) = (
+                convention.to_world(a),
+                convention.to_world(b),
+                convention.to_world(c),
+            );
+            if convention.is_mirrored() {
+                std::mem::swap(&mut b, &mut c);
+            }
+            // The face still points where its normal was mapped to.
+            assert_close(Vec3::cross(b - a, c - a), convention.to_world(normal));
+        }
+    }
+}
@@ -1,5 +1,7 @@
 pub mod camera;
+pub mod convention;
 pub mod onb;
 pub mod pdf;
 pub mod ray;
+pub mod sampler;
 pub mod vec;
@@ -1,6 +1,6 @@
 use std::f64::consts::PI;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use crate::hittable::Hittable;
 
@@ -9,8 +9,7 @@ use super::{
     vec::{Point3, Vec3},
 };
 
-pub fn random_cosine_direction() -> Vec3 {
-    let mut rng = rand::thread_rng();
+pub fn random_cosine_direction(rng: &mut dyn RngCore) -> Vec3 {
     let r1: f64 = rng.gen();
     let r2: f64 = rng.gen();
     let z = (1. - r2).sqrt();
@@ -22,7 +21,7 @@ pub fn random_cosine_direction() -> Vec3 {
 
 pub trait Pdf {
     fn value(&self, direction: Vec3) -> f64;
-    fn generate(&self) -> Vec3;
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3;
 }
 
 #[derive(Clone, Copy)]
@@ -40,8 +39,8 @@ impl CosPdf {
 }
 
 impl Pdf for CosPdf {
-    fn generate(&self) -> Vec3 {
-        self.uvw.local_vec(random_cosine_direction())
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        self.uvw.local_vec(random_cosine_direction(rng))
     }
     fn value(&self, direction: Vec3) -> f64 {
         let cos = Vec3::dot(direction.to_unit(), self.uvw.w());
@@ -58,21 +57,22 @@ where
     H: Hittable,
 {
     o: Point3,
+    time: f64,
     ptr: &'a H,
 }
 
 impl<'a, H: Hittable> HittablePdf<'a, H> {
-    pub fn new(ptr: &'a H, o: Point3) -> Self {
-        Self { o, ptr }
+    pub fn new(ptr: &'a H, o: Point3, time: f64) -> Self {
+        Self { o, time, ptr }
     }
 }
 
 impl<'a, H: Hittable> Pdf for HittablePdf<'a, H> {
-    fn generate(&self) -> Vec3 {
-        self.ptr.random(self.o)
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        self.ptr.random(self.o, self.time, rng)
     }
     fn value(&self, direction: Vec3) -> f64 {
-        self.ptr.pdf_value(self.o, direction)
+        self.ptr.pdf_value(self.o, direction, self.time)
     }
 }
 
@@ -92,14 +92,55 @@ impl<P0: Pdf, P1: Pdf> MixturePdf<P0, P1> {
 }
 
 impl<P0: Pdf, P1: Pdf> Pdf for MixturePdf<P0, P1> {
-    fn generate(&self) -> Vec3 {
-        if rand::thread_rng().gen_range(0.0..1.0) < 0.5 {
-            self.p0.generate()
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        if rng.gen_range(0.0..1.0) < 0.5 {
+            self.p0.generate(rng)
         } else {
-            self.p1.generate()
+            self.p1.generate(rng)
         }
     }
     fn value(&self, direction: Vec3) -> f64 {
         0.5 * self.p0.value(direction) + 0.5 * self.p1.value(direction)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec::Color;
+    use crate::hittable::sphere::Sphere;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn mixture_value_is_the_average() {
+        let up = CosPdf::new(Vec3::new(0., 1., 0.));
+        let tilted = CosPdf::new(Vec3::new(1., 1., 0.));
+        let sphere = Sphere::new(
+            Point3::new(0., 5., 0.),
+            1.,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        );
+        let light = HittablePdf::new(&sphere, Point3::new(0., 0., 0.), 0.);
+
+        let directions = [
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0.1, 1., 0.),
+            Vec3::new(1., 0.2, 0.),
+            Vec3::new(-1., 0.5, 0.3),
+            Vec3::new(0., -1., 0.),
+        ];
+        for &v in directions.iter() {
+            let both = MixturePdf::new(up, tilted);
+            let expected = (up.value(v) + tilted.value(v)) / 2.;
+            assert!((both.value(v) - expected).abs() < 1e-12, "{}", v);
+
+            let with_light =
+                MixturePdf::new(up, HittablePdf::new(&sphere, Point3::new(0., 0., 0.), 0.));
+            let expected = (up.value(v) + light.value(v)) / 2.;
+            assert!((with_light.value(v) - expected).abs() < 1e-12, "{}", v);
+        }
+        // Only some of the directions see the light.
+        assert!(light.value(directions[0]) > 0.);
+        assert!(light.value(directions[2]) == 0.);
+    }
+}
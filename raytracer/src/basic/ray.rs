@@ -1,5 +1,7 @@
 use super::vec::{Point3, Vec3};
 
+/// The points `orig + dir * t`. `dir` is not normalized in general, so `t`
+/// measures multiples of `dir` rather than world-space distance.
 #[derive(Copy, Clone)]
 pub struct Ray {
     pub orig: Point3,
@@ -19,3 +21,16 @@ impl Ray {
         self.orig + self.dir * t
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn at_steps_in_multiples_of_dir() {
+        let r = Ray::new(Point3::new(1., 2., 3.), Vec3::new(0., 0., -2.), 0.5);
+        assert!((r.at(0.) - r.orig).length() < 1e-12);
+        assert!((r.at(1.) - Point3::new(1., 2., 1.)).length() < 1e-12);
+        assert!((r.at(-0.5) - Point3::new(1., 2., 4.)).length() < 1e-12);
+    }
+}
@@ -0,0 +1,181 @@
+use std::cell::RefCell;
+
+use rand::{Error, RngCore};
+
+/// Every random number one camera sample needs, as a pure function of
+/// `(pixel_x, pixel_y, sample_index, global_seed)`. The camera values come
+/// first in a fixed order; whatever follows is the stream scattering draws
+/// from while the sequence is installed with `with_sequence`.
+#[derive(Clone, Debug)]
+pub struct SampleSequence {
+    state: u64,
+}
+
+impl SampleSequence {
+    pub fn new(pixel_x: u32, pixel_y: u32, sample_index: u32, global_seed: u64) -> Self {
+        let mut state = splitmix64(global_seed);
+        for key in [pixel_x, pixel_y, sample_index] {
+            state = splitmix64(state ^ key as u64);
+        }
+        Self { state }
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Sub-pixel position, both coordinates in `[0, 1)`.
+    pub fn pixel_offset(&mut self) -> (f64, f64) {
+        (self.next_f64(), self.next_f64())
+    }
+
+    /// Point on the unit disk for depth of field.
+    pub fn lens(&mut self) -> (f64, f64) {
+        loop {
+            let x = self.next_f64() * 2. - 1.;
+            let y = self.next_f64() * 2. - 1.;
+            if x * x + y * y < 1. {
+                return (x, y);
+            }
+        }
+    }
+
+    /// Fraction of the shutter interval, in `[0, 1)`.
+    pub fn time(&mut self) -> f64 {
+        self.next_f64()
+    }
+}
+
+impl RngCore for SampleSequence {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        splitmix64(self.state)
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<SampleSequence>> = RefCell::new(None);
+}
+
+/// Runs `f` with `seq` feeding every `rng()` draw on this thread.
+pub fn with_sequence<R>(seq: SampleSequence, f: impl FnOnce() -> R) -> R {
+    let previous = ACTIVE.with(|active| active.borrow_mut().replace(seq));
+    let result = f();
+    ACTIVE.with(|active| *active.borrow_mut() = previous);
+    result
+}
+
+/// Like `with_sequence`, leaving `seq` where `f` stopped drawing from it, so a
+/// path can be advanced in several steps interleaved with other paths.
+pub fn with_sequence_mut<R>(seq: &mut SampleSequence, f: impl FnOnce() -> R) -> R {
+    let previous = ACTIVE.with(|active| active.borrow_mut().replace(seq.clone()));
+    let result = f();
+    let advanced = ACTIVE.with(|active| std::mem::replace(&mut *active.borrow_mut(), previous));
+    if let Some(advanced) = advanced {
+        *seq = advanced;
+    }
+    result
+}
+
+/// The generator render-time code draws from: the installed sequence, or
+/// `thread_rng` when none is.
+pub struct SampleRng;
+
+pub fn rng() -> SampleRng {
+    SampleRng
+}
+
+impl RngCore for SampleRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+    fn next_u64(&mut self) -> u64 {
+        ACTIVE.with(|active| match active.borrow_mut().as_mut() {
+            Some(seq) => seq.next_u64(),
+            None => rand::thread_rng().next_u64(),
+        })
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        ACTIVE.with(|active| match active.borrow_mut().as_mut() {
+            Some(seq) => seq.fill_bytes(dest),
+            None => rand::thread_rng().fill_bytes(dest),
+        })
+    }
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(rng: &mut dyn RngCore, n: usize) -> Vec<u64> {
+        (0..n).map(|_| rng.next_u64()).collect()
+    }
+
+    #[test]
+    fn sequences_depend_on_every_key() {
+        let base = draws(&mut SampleSequence::new(3, 5, 7, 11), 8);
+        assert_eq!(base, draws(&mut SampleSequence::new(3, 5, 7, 11), 8));
+        for other in [
+            SampleSequence::new(4, 5, 7, 11),
+            SampleSequence::new(3, 6, 7, 11),
+            SampleSequence::new(3, 5, 8, 11),
+            SampleSequence::new(3, 5, 7, 12),
+            // Swapped coordinates are another pixel.
+            SampleSequence::new(5, 3, 7, 11),
+        ]
+        .iter_mut()
+        {
+            assert_ne!(base, draws(other, 8));
+        }
+    }
+
+    #[test]
+    fn installed_sequence_feeds_rng() {
+        let expected = draws(&mut SampleSequence::new(1, 2, 3, 4), 4);
+        let seq = SampleSequence::new(1, 2, 3, 4);
+        assert_eq!(
+            with_sequence(seq.clone(), || draws(&mut rng(), 4)),
+            expected
+        );
+
+        // Nested sequences are restored when they end.
+        let outer = with_sequence(seq.clone(), || {
+            let mut outer = draws(&mut rng(), 2);
+            with_sequence(SampleSequence::new(9, 9, 9, 9), || draws(&mut rng(), 3));
+            outer.extend(draws(&mut rng(), 2));
+            outer
+        });
+        assert_eq!(outer, expected);
+
+        // `with_sequence_mut` picks up where the last call stopped.
+        let mut seq = seq;
+        let mut split = with_sequence_mut(&mut seq, || draws(&mut rng(), 1));
+        split.extend(with_sequence_mut(&mut seq, || draws(&mut rng(), 3)));
+        assert_eq!(split, expected);
+    }
+}
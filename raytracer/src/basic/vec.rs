@@ -1,4 +1,4 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 use std::f64::consts::PI;
 use std::fmt;
 use std::fmt::Display;
@@ -33,30 +33,50 @@ impl Vec3 {
         }
     }
 
-    pub fn length_sqr(&self) -> f64 {
+    pub fn length_squared(&self) -> f64 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     pub fn length(&self) -> f64 {
-        self.length_sqr().sqrt()
+        self.length_squared().sqrt()
+    }
+
+    pub fn distance(a: Self, b: Self) -> f64 {
+        (b - a).length()
     }
 
     pub fn to_unit(self) -> Self {
         self / self.length()
     }
 
-    pub fn random(l: f64, r: f64) -> Self {
-        let mut rng = rand::thread_rng();
+    /// Components uniform in `[0, 1)`.
+    pub fn random(rng: &mut dyn RngCore) -> Self {
+        Self::random_range(rng, 0., 1.)
+    }
+
+    /// Components uniform in `[min, max)`.
+    pub fn random_range(rng: &mut dyn RngCore, min: f64, max: f64) -> Self {
         Self {
-            x: rng.gen_range(l..r),
-            y: rng.gen_range(l..r),
-            z: rng.gen_range(l..r),
+            x: rng.gen_range(min..max),
+            y: rng.gen_range(min..max),
+            z: rng.gen_range(min..max),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn random_unit_vector() -> Self {
-        random_in_unit_sphere().to_unit()
+    pub fn random_unit_vector(rng: &mut dyn RngCore) -> Self {
+        random_in_unit_sphere(rng).to_unit()
+    }
+
+    /// Mirrors the vector about the plane with normal `n`.
+    pub fn reflect_about(self, n: Self) -> Self {
+        reflect(self, n)
+    }
+
+    /// Rotates by `angle` radians around `axis` (right-handed, Rodrigues' formula).
+    pub fn rotate_around(self, axis: Self, angle: f64) -> Self {
+        let k = axis.to_unit();
+        let (sin, cos) = angle.sin_cos();
+        self * cos + Vec3::cross(k, self) * sin + k * Vec3::dot(k, self) * (1. - cos)
     }
 
     #[allow(dead_code)]
@@ -66,9 +86,9 @@ impl Vec3 {
     }
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
+pub fn random_in_unit_sphere(rng: &mut dyn RngCore) -> Vec3 {
     loop {
-        let p = Vec3::random(-1., 1.);
+        let p = Vec3::random_range(rng, -1., 1.);
         if p.length() < 1. {
             return p;
         }
@@ -76,8 +96,8 @@ pub fn random_in_unit_sphere() -> Vec3 {
 }
 
 #[allow(dead_code)]
-pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
+pub fn random_in_hemisphere(normal: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+    let in_unit_sphere = random_in_unit_sphere(rng);
     if Vec3::dot(in_unit_sphere, normal) > 0. {
         in_unit_sphere
     } else {
@@ -85,8 +105,7 @@ pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
     }
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
+pub fn random_in_unit_disk(rng: &mut dyn RngCore) -> Vec3 {
     loop {
         let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.);
         if p.length() < 1. {
@@ -95,11 +114,10 @@ pub fn random_in_unit_disk() -> Vec3 {
     }
 }
 
-pub fn random_to_sphere(radius: f64, dis_sqr: f64) -> Vec3 {
-    let mut rng = rand::thread_rng();
+pub fn random_to_sphere(radius: f64, dis_sqr: f64, rng: &mut dyn RngCore) -> Vec3 {
     let r1: f64 = rng.gen();
     let r2: f64 = rng.gen();
-    let z = 1. + r2 * ((1. - radius.powi(2) / dis_sqr).sqrt() - 1.);
+    let z = 1. + r2 * ((1. - radius.powi(2) / dis_sqr).max(0.).sqrt() - 1.);
     let phi = 2. * PI * r1;
     let x = phi.cos() * (1. - z.powi(2)).sqrt();
     let y = phi.sin() * (1. - z.powi(2)).sqrt();
@@ -113,7 +131,7 @@ pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
 pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
     let cos_theta = f64::min(Vec3::dot(-uv, n), 1.);
     let r_out_perp = (uv + n * cos_theta) * etai_over_etat;
-    let r_out_parallel = -n * (f64::abs(1. - r_out_perp.length_sqr())).sqrt();
+    let r_out_parallel = -n * (f64::abs(1. - r_out_perp.length_squared())).sqrt();
     r_out_perp + r_out_parallel
 }
 
@@ -334,3 +352,102 @@ impl IndexMut<usize> for Vec3 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::sampler::SampleSequence;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-12, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn cross_products_follow_the_right_hand_rule() {
+        let (x, y, z) = (
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., 0., 1.),
+        );
+        assert_close(Vec3::cross(x, y), z);
+        assert_close(Vec3::cross(y, z), x);
+        assert_close(Vec3::cross(z, x), y);
+
+        let (lhs, rhs) = (Vec3::new(1., 2., 3.), Vec3::new(-4., 0.5, 2.));
+        assert_close(Vec3::cross(lhs, rhs), -Vec3::cross(rhs, lhs));
+        assert_close(Vec3::cross(lhs, rhs), Vec3::new(2.5, -14., 8.5));
+        // Perpendicular to both, and zero for parallel vectors.
+        assert!(Vec3::dot(Vec3::cross(lhs, rhs), lhs).abs() < 1e-12);
+        assert!(Vec3::dot(Vec3::cross(lhs, rhs), rhs).abs() < 1e-12);
+        assert_close(Vec3::cross(lhs, lhs * 3.), Vec3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn quarter_turns_about_z() {
+        let z = Vec3::new(0., 0., 1.);
+        let quarter = PI / 2.;
+        assert_close(
+            Vec3::new(1., 0., 0.).rotate_around(z, quarter),
+            Vec3::new(0., 1., 0.),
+        );
+        assert_close(
+            Vec3::new(0., 1., 0.).rotate_around(z, quarter),
+            Vec3::new(-1., 0., 0.),
+        );
+        // The axis needn't be unit length, and points along it stay put.
+        assert_close(
+            Vec3::new(1., 2., 3.).rotate_around(z * 5., -quarter),
+            Vec3::new(2., -1., 3.),
+        );
+        assert_close(z.rotate_around(z, 1.), z);
+    }
+
+    #[test]
+    fn reflections_flip_the_normal_component() {
+        let n = Vec3::new(0., 1., 0.);
+        assert_close(
+            Vec3::new(1., -1., 0.).reflect_about(n),
+            Vec3::new(1., 1., 0.),
+        );
+        // Across the plane x = y.
+        let n = Vec3::new(1., -1., 0.).to_unit();
+        assert_close(
+            Vec3::new(2., 0., 3.).reflect_about(n),
+            Vec3::new(0., 2., 3.),
+        );
+        // Reflecting twice gives the vector back.
+        let v = Vec3::new(0.3, -0.7, 0.2);
+        assert_close(v.reflect_about(n).reflect_about(n), v);
+    }
+
+    #[test]
+    fn distance_is_symmetric_and_euclidean() {
+        let (a, b) = (Vec3::new(1., 2., 3.), Vec3::new(4., 6., 3.));
+        assert!((Vec3::distance(a, b) - 5.).abs() < 1e-12);
+        assert!((Vec3::distance(b, a) - 5.).abs() < 1e-12);
+        assert!(Vec3::distance(a, a).abs() < 1e-12);
+        assert!(((b - a).length_squared() - 25.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn seeded_random_vectors_repeat_and_stay_in_range() {
+        let draw = |i| {
+            let mut rng = SampleSequence::new(i, 0, 0, 9);
+            (
+                Vec3::random(&mut rng),
+                Vec3::random_range(&mut rng, -2., 0.5),
+            )
+        };
+        for i in 0..1000 {
+            let (unit, range) = draw(i);
+            for c in 0..3 {
+                assert!((0. ..1.).contains(&unit[c]));
+                assert!((-2. ..0.5).contains(&range[c]));
+            }
+            let (again, range_again) = draw(i);
+            assert_close(unit, again);
+            assert_close(range, range_again);
+        }
+        assert!((draw(0).0 - draw(1).0).length() > 0.);
+    }
+}
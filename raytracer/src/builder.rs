@@ -0,0 +1,372 @@
+use std::sync::Arc;
+
+use crate::{
+    basic::vec::{Color, Point3, Vec3},
+    hittable::{
+        aarect::{XYRect, XZRect, YZRect},
+        boxes::Boxes,
+        bvh::{BvhNode, DEFAULT_MAX_LEAF_SIZE},
+        disk::Disk,
+        plane::Plane,
+        primitive::{Primitive, PrimitiveList},
+        sphere::{MovingSphere, Sphere},
+        triangle::Triangle,
+        Hittable, HittableList,
+    },
+    material::{Dielectric, DiffuseLight, Lambertian, Material, MaterialHandle, Metal},
+    texture::Texture,
+};
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum RectAxis {
+    XY,
+    XZ,
+    YZ,
+}
+
+#[derive(Default)]
+pub struct SceneBuilder {
+    world: HittableList,
+    lights: HittableList,
+    // The shapes added through the helpers below, again as enum values. Only
+    // usable while nothing else went into `world`.
+    primitives: PrimitiveList,
+    has_custom: bool,
+}
+
+#[allow(dead_code)]
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Materials
+
+    pub fn material<M: Material + 'static>(&self, mat: M) -> MaterialHandle {
+        MaterialHandle::new(mat)
+    }
+    pub fn material_lambertian(&self, albedo: Color) -> MaterialHandle {
+        self.material(Lambertian::new(albedo))
+    }
+    pub fn material_lambertian_texture<T>(&self, albedo: T) -> MaterialHandle
+    where
+        T: Texture + Clone + 'static,
+    {
+        self.material(Lambertian::new_arc(albedo))
+    }
+    pub fn material_metal(&self, albedo: Color, fuzz: f64) -> MaterialHandle {
+        self.material(Metal::new(albedo, fuzz))
+    }
+    pub fn material_dielectric(&self, index_of_refraction: f64) -> MaterialHandle {
+        self.material(Dielectric::new(index_of_refraction))
+    }
+    pub fn material_diffuse_light(&self, emit: Color) -> MaterialHandle {
+        self.material(DiffuseLight::new(emit))
+    }
+
+    // Objects
+
+    pub fn add(&mut self, object: Arc<dyn Hittable>) -> &mut Self {
+        self.has_custom = true;
+        self.world.add(object);
+        self
+    }
+    fn add_primitive(&mut self, object: Arc<dyn Hittable>, primitive: Primitive) -> &mut Self {
+        self.primitives.add(primitive);
+        self.world.add(object);
+        self
+    }
+    pub fn add_light(&mut self, object: Arc<dyn Hittable>) -> &mut Self {
+        self.add_light_weighted(object, 1.)
+    }
+    /// Lights with a larger weight are picked proportionally more often.
+    pub fn add_light_weighted(&mut self, object: Arc<dyn Hittable>, weight: f64) -> &mut Self {
+        self.add(object.clone());
+        self.lights.add_weighted(object, weight);
+        self
+    }
+    pub fn add_sphere(&mut self, center: Point3, radius: f64, mat: &MaterialHandle) -> &mut Self {
+        let sphere = Sphere::new(center, radius, mat.clone());
+        self.add_primitive(Arc::new(sphere.clone()), Primitive::Sphere(sphere))
+    }
+    pub fn add_moving_sphere(
+        &mut self,
+        center0: Point3,
+        center1: Point3,
+        radius: f64,
+        mat: &MaterialHandle,
+    ) -> &mut Self {
+        let sphere = MovingSphere::new(center0, center1, 0., 1., radius, mat.clone());
+        self.add_primitive(Arc::new(sphere.clone()), Primitive::MovingSphere(sphere))
+    }
+    /// Axis-aligned rectangle spanning `[a0, a1] x [b0, b1]` on the plane `k`.
+    pub fn add_quad(
+        &mut self,
+        axis: RectAxis,
+        (a0, a1): (f64, f64),
+        (b0, b1): (f64, f64),
+        k: f64,
+        mat: &MaterialHandle,
+    ) -> &mut Self {
+        let mat = mat.clone();
+        match axis {
+            RectAxis::XY => {
+                let rect = XYRect::new(a0, a1, b0, b1, k, mat);
+                self.add_primitive(Arc::new(rect.clone()), Primitive::XYRect(rect))
+            }
+            RectAxis::XZ => {
+                let rect = XZRect::new(a0, a1, b0, b1, k, mat);
+                self.add_primitive(Arc::new(rect.clone()), Primitive::XZRect(rect))
+            }
+            RectAxis::YZ => {
+                let rect = YZRect::new(a0, a1, b0, b1, k, mat);
+                self.add_primitive(Arc::new(rect.clone()), Primitive::YZRect(rect))
+            }
+        }
+    }
+    pub fn add_box(&mut self, p0: Point3, p1: Point3, mat: &MaterialHandle) -> &mut Self {
+        self.add(Arc::new(Boxes::new(p0, p1, mat.clone())))
+    }
+    pub fn add_disk(
+        &mut self,
+        center: Point3,
+        normal: Vec3,
+        radius: f64,
+        mat: &MaterialHandle,
+    ) -> &mut Self {
+        self.add(Arc::new(Disk::new(center, normal, radius, mat.clone())))
+    }
+    /// Unbounded, so it stays out of the BVH and is tested against every ray.
+    pub fn add_plane(&mut self, point: Point3, normal: Vec3, mat: &MaterialHandle) -> &mut Self {
+        self.add(Arc::new(Plane::new(point, normal, mat.clone())))
+    }
+    pub fn add_triangle(
+        &mut self,
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        mat: &MaterialHandle,
+    ) -> &mut Self {
+        let triangle = Triangle::new(a, b, c, mat.clone());
+        self.add_primitive(Arc::new(triangle.clone()), Primitive::Triangle(triangle))
+    }
+
+    pub fn len(&self) -> usize {
+        self.world.objects.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.world.objects.is_empty()
+    }
+
+    pub fn build(self) -> (HittableList, Option<HittableList>) {
+        if self.lights.objects.is_empty() {
+            (self.world, None)
+        } else {
+            (self.world, Some(self.lights))
+        }
+    }
+    /// Like `build`, with the world already in a BVH. A scene made only with
+    /// the shape helpers gets `BvhNode::new_primitives`, which skips the
+    /// vtable inside the leaves.
+    pub fn build_bvh(self, time0: f64, time1: f64) -> (BvhNode, Option<HittableList>) {
+        let world = if self.has_custom {
+            BvhNode::new_list(&self.world, time0, time1)
+        } else {
+            BvhNode::new_primitives(self.primitives, DEFAULT_MAX_LEAF_SIZE, time0, time1)
+        };
+        let lights = if self.lights.objects.is_empty() {
+            None
+        } else {
+            Some(self.lights)
+        };
+        (world, lights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        basic::{
+            ray::Ray,
+            sampler::SampleSequence,
+            vec::{random_in_unit_sphere, Vec3},
+        },
+        scene,
+        texture::CheckerTexture,
+    };
+    use rand::Rng;
+    use std::time::Instant;
+
+    /// `scene::four_spheres` written out by hand, without the builder.
+    fn four_spheres_by_hand() -> HittableList {
+        let mut world = HittableList::default();
+        let checker = CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., -1000., 0.),
+            1000.,
+            Lambertian::new_arc(checker),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 1., 0.),
+            1.,
+            Dielectric::new(1.5),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(-4., 1., 0.),
+            1.,
+            Lambertian::new(Color::new(0.4, 0.2, 0.1)),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(4., 1., 0.),
+            1.,
+            Metal::new(Color::new(0.7, 0.6, 0.5), 0.),
+        )));
+        world
+    }
+
+    #[test]
+    fn four_spheres_matches_hand_built_scene() {
+        let built = scene::four_spheres();
+        let by_hand = four_spheres_by_hand();
+        assert_eq!(built.objects.len(), by_hand.objects.len());
+
+        for (a, b) in built.objects.iter().zip(&by_hand.objects) {
+            let (box_a, box_b) = (
+                a.bounding_box(0., 1.).unwrap(),
+                b.bounding_box(0., 1.).unwrap(),
+            );
+            assert!(Vec3::distance(box_a.min, box_b.min) < 1e-12);
+            assert!(Vec3::distance(box_a.max, box_b.max) < 1e-12);
+
+            // Straight down onto the top of the sphere.
+            let top = Point3::new(box_a.centroid().x, box_a.max.y + 1., box_a.centroid().z);
+            let r = Ray::new(top, Vec3::new(0., -1., 0.), 0.);
+            let (rec_a, rec_b) = (
+                a.hit(r, 0.001, f64::INFINITY).unwrap(),
+                b.hit(r, 0.001, f64::INFINITY).unwrap(),
+            );
+            assert!((rec_a.t - rec_b.t).abs() < 1e-12);
+            assert_eq!(rec_a.mat_ptr.name(), rec_b.mat_ptr.name());
+        }
+    }
+
+    #[test]
+    fn lights_are_also_in_the_world() {
+        let mut builder = SceneBuilder::new();
+        let light = builder.material_diffuse_light(Color::new(4., 4., 4.));
+        let white = builder.material_lambertian(Color::new(0.73, 0.73, 0.73));
+        builder.add_sphere(Point3::new(0., 0., 0.), 1., &white);
+        builder.add_light(Arc::new(XZRect::new(-1., 1., -1., 1., 3., light)));
+
+        let (world, lights) = builder.build();
+        assert_eq!(world.objects.len(), 2);
+        assert_eq!(lights.unwrap().objects.len(), 1);
+        assert!(SceneBuilder::new().build().1.is_none());
+    }
+
+    /// `n` random spheres in a 20 unit cube plus a few quads and triangles,
+    /// all through the shape helpers.
+    fn random_shapes(n: u32) -> SceneBuilder {
+        let mut rng = SampleSequence::new(0, 0, 0, 7);
+        let mut builder = SceneBuilder::new();
+        let gray = builder.material_lambertian(Color::new(0.5, 0.5, 0.5));
+        for _ in 0..n {
+            let center = Vec3::random_range(&mut rng, -10., 10.);
+            builder.add_sphere(center, rng.gen_range(0.1..0.5), &gray);
+        }
+        builder.add_quad(RectAxis::XZ, (-10., 10.), (-10., 10.), -10., &gray);
+        builder.add_quad(RectAxis::YZ, (-10., 10.), (-10., 10.), 10., &gray);
+        builder.add_triangle(
+            Point3::new(-10., -10., 10.),
+            Point3::new(10., -10., 10.),
+            Point3::new(0., 10., 10.),
+            &gray,
+        );
+        builder
+    }
+
+    fn random_ray(rng: &mut SampleSequence) -> Ray {
+        let origin = Vec3::random_range(rng, -12., 12.);
+        Ray::new(origin, random_in_unit_sphere(rng), 0.)
+    }
+
+    #[test]
+    fn primitive_lists_hit_like_dyn_lists() {
+        let builder = random_shapes(200);
+        let primitives = builder.primitives.clone();
+        let (world, _) = builder.build();
+        let bvh = BvhNode::new_primitives(primitives.clone(), DEFAULT_MAX_LEAF_SIZE, 0., 1.);
+
+        let mut rng = SampleSequence::new(1, 0, 0, 7);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let r = random_ray(&mut rng);
+            let expected = world.hit(r, 0.001, f64::INFINITY);
+            for actual in [
+                primitives.hit(r, 0.001, f64::INFINITY),
+                bvh.hit(r, 0.001, f64::INFINITY),
+            ]
+            .iter()
+            {
+                match (&expected, actual) {
+                    (Some(a), Some(b)) => {
+                        assert!((a.t - b.t).abs() < 1e-12);
+                        assert!(Vec3::distance(a.p, b.p) < 1e-12);
+                        assert!(Vec3::distance(a.normal, b.normal) < 1e-12);
+                    }
+                    (None, None) => {}
+                    _ => panic!("hit and miss disagree"),
+                }
+            }
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 100, "{}", hits);
+    }
+
+    #[test]
+    fn custom_objects_fall_back_to_the_dyn_bvh() {
+        let mut builder = random_shapes(4);
+        assert!(!builder.has_custom);
+        builder.add_box(
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 1., 1.),
+            &MaterialHandle::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        assert!(builder.has_custom);
+        let (bvh, _) = builder.build_bvh(0., 1.);
+        let mut counts = Default::default();
+        bvh.count_primitives(&mut counts);
+        assert_eq!(counts.get("Boxes"), Some(&1));
+    }
+
+    /// `cargo test --release -- --ignored --nocapture` prints the time per ray
+    /// through both BVHs over 1000 spheres.
+    #[test]
+    #[ignore]
+    fn bench_primitive_bvh_against_dyn_bvh() {
+        let builder = random_shapes(1000);
+        let primitives = builder.primitives.clone();
+        let (world, _) = builder.build();
+        let dyn_bvh = BvhNode::new_list(&world, 0., 1.);
+        let enum_bvh = BvhNode::new_primitives(primitives, DEFAULT_MAX_LEAF_SIZE, 0., 1.);
+
+        let mut rng = SampleSequence::new(2, 0, 0, 7);
+        let rays: Vec<Ray> = (0..1_000_000).map(|_| random_ray(&mut rng)).collect();
+        for (name, bvh) in [("dyn", &dyn_bvh), ("enum", &enum_bvh)].iter() {
+            let start = Instant::now();
+            let hits = rays
+                .iter()
+                .filter(|r| bvh.hit(**r, 0.001, f64::INFINITY).is_some())
+                .count();
+            let elapsed = start.elapsed();
+            println!(
+                "{}: {:.1} ns/ray, {} hits",
+                name,
+                elapsed.as_nanos() as f64 / rays.len() as f64,
+                hits
+            );
+        }
+    }
+}
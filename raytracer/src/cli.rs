@@ -0,0 +1,410 @@
+//! Options from the command line. Every flag is read here, so a mistyped or
+//! incomplete one is reported instead of silently ignored.
+
+use crate::render::{ColorSpace, Parallel, RussianRoulette, ToneMap};
+
+pub const HELP: &str = "\
+Usage: raytracer [SCENE.json] [OPTIONS]
+
+Options:
+  --width N            image width in pixels (default 500)
+  --height N           image height in pixels (default 500)
+  --samples N          samples per pixel, at least 1 (default 100)
+  --depth N            maximum bounces per path (default 50)
+  --quality N          JPEG quality from 0 to 100 (default 100)
+  --gamma G            encode with a pure x^(1/G) curve instead of sRGB; 1 is
+                       linear
+  --color-space CS     output curve: srgb (default), rec709 or linear
+  --tone-map TM        none (default), reinhard or aces
+  --roulette RR        end dim paths early: off (default), luminance or max
+  --target-rmse E      replace --samples with an estimate of the samples needed
+                       for a per-pixel error of E
+  --sample-map PATH    spend from 1 to --samples samples per pixel, as bright as
+                       the grayscale image PATH is there
+  --tiles N            threads take N x N tiles instead of rows
+  --split-samples      threads split every pixel's samples, for tiny images
+  --stereo D           render a left and a right eye D apart, written next to
+                       --out with -left and -right added to the name
+  --seed N             derive every sample from N and the pixel, so equal seeds
+                       give identical images (default: unseeded)
+  --out PATH           output image; .ppm writes raw P6 (default output/output.jpg,
+                       or output/output.png with --alpha)
+  --alpha              PNG with coverage alpha
+  --flat               one-sample geometry preview
+  --aovs               also write normal, albedo and depth AOVs
+  --bounce-heatmap     write a heatmap of path lengths instead of the image
+  --wavefront          trace every path one bounce at a time, single threaded
+  --edge-samples N     render again with N samples where neighbours differ
+  --debug-pixel X Y    trace one sample of one pixel, logging every bounce
+  --info SCENE         print what a built-in scene is made of
+  --write-metadata     write render settings next to the image
+  --profile            count intersections per primitive type (profile feature)
+  --help               print this message";
+
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub samples: i32,
+    pub depth: i32,
+    pub quality: u8,
+    pub seed: Option<u64>,
+    pub out: Option<String>,
+    pub color_space: ColorSpace,
+    pub tone_map: ToneMap,
+    pub roulette: RussianRoulette,
+    pub target_rmse: Option<f64>,
+    pub sample_map: Option<String>,
+    pub parallel: Parallel,
+    // Eye separation of a stereo pair.
+    pub stereo: Option<f64>,
+    // A .json scene replacing the built-in one.
+    pub scene: Option<String>,
+    pub alpha: bool,
+    pub flat: bool,
+    pub aovs: bool,
+    pub bounce_heatmap: bool,
+    pub wavefront: bool,
+    pub edge_samples: Option<i32>,
+    pub debug_pixel: Option<(u32, u32)>,
+    pub info: Option<String>,
+    pub write_metadata: bool,
+    #[cfg(feature = "profile")]
+    pub profile: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: 500,
+            height: 500,
+            samples: 100,
+            depth: 50,
+            quality: 100,
+            seed: None,
+            out: None,
+            color_space: ColorSpace::SrgbGamma,
+            tone_map: ToneMap::None,
+            roulette: RussianRoulette::Off,
+            target_rmse: None,
+            sample_map: None,
+            parallel: Parallel::Pixels,
+            stereo: None,
+            scene: None,
+            alpha: false,
+            flat: false,
+            aovs: false,
+            bounce_heatmap: false,
+            wavefront: false,
+            edge_samples: None,
+            debug_pixel: None,
+            info: None,
+            write_metadata: false,
+            #[cfg(feature = "profile")]
+            profile: false,
+        }
+    }
+}
+
+impl Options {
+    /// Missing options keep their defaults; unknown arguments are an error.
+    /// `--help` is left to the caller.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            let mut value = || {
+                i += 1;
+                args.get(i)
+                    .map(String::as_str)
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag {
+                "--width" => options.width = number(flag, value()?)?,
+                "--height" => options.height = number(flag, value()?)?,
+                "--samples" => options.samples = number(flag, value()?)?,
+                "--depth" => options.depth = number(flag, value()?)?,
+                "--quality" => options.quality = number(flag, value()?)?,
+                "--seed" => options.seed = Some(number(flag, value()?)?),
+                "--out" | "--output" => options.out = Some(value()?.to_string()),
+                "--gamma" => {
+                    options.color_space = ColorSpace::gamma(number(flag, value()?)?)
+                        .map_err(|e| format!("{}: {}", flag, e))?
+                }
+                "--color-space" => {
+                    options.color_space = match value()? {
+                        "srgb" => ColorSpace::SrgbGamma,
+                        "rec709" => ColorSpace::Rec709,
+                        "linear" => ColorSpace::Linear,
+                        other => return Err(choice(flag, other, "srgb, rec709 or linear")),
+                    }
+                }
+                "--tone-map" => {
+                    options.tone_map = match value()? {
+                        "none" => ToneMap::None,
+                        "reinhard" => ToneMap::Reinhard,
+                        "aces" => ToneMap::Aces,
+                        other => return Err(choice(flag, other, "none, reinhard or aces")),
+                    }
+                }
+                "--roulette" => {
+                    options.roulette = match value()? {
+                        "off" => RussianRoulette::Off,
+                        "luminance" => RussianRoulette::Luminance,
+                        "max" => RussianRoulette::MaxComponent,
+                        other => return Err(choice(flag, other, "off, luminance or max")),
+                    }
+                }
+                "--target-rmse" => options.target_rmse = Some(number(flag, value()?)?),
+                "--sample-map" => options.sample_map = Some(value()?.to_string()),
+                "--tiles" => {
+                    let tile_size = number(flag, value()?)?;
+                    options.parallel = parallel(options.parallel, Parallel::Tiles(tile_size))?
+                }
+                "--split-samples" => {
+                    options.parallel = parallel(options.parallel, Parallel::Samples)?
+                }
+                "--stereo" => options.stereo = Some(number(flag, value()?)?),
+                "--alpha" => options.alpha = true,
+                "--flat" => options.flat = true,
+                "--aovs" => options.aovs = true,
+                "--bounce-heatmap" => options.bounce_heatmap = true,
+                "--wavefront" => options.wavefront = true,
+                "--edge-samples" => options.edge_samples = Some(number(flag, value()?)?),
+                "--debug-pixel" => {
+                    let x = number(flag, value()?)?;
+                    let y = number(flag, value()?)?;
+                    options.debug_pixel = Some((x, y));
+                }
+                "--info" => options.info = Some(value()?.to_string()),
+                "--write-metadata" => options.write_metadata = true,
+                #[cfg(feature = "profile")]
+                "--profile" => options.profile = true,
+                #[cfg(not(feature = "profile"))]
+                "--profile" => return Err("--profile needs the profile feature".to_string()),
+                "--help" => {}
+                scene if scene.ends_with(".json") => options.scene = Some(scene.to_string()),
+                _ => return Err(format!("unknown argument \"{}\"", flag)),
+            }
+            i += 1;
+        }
+        if options.width == 0 || options.height == 0 {
+            return Err("--width and --height must be positive".to_string());
+        }
+        if options.wavefront && options.alpha {
+            return Err("--wavefront can't write coverage alpha".to_string());
+        }
+        if options.edge_samples.is_some() && (options.alpha || options.wavefront) {
+            return Err("--edge-samples can't be combined with --alpha or --wavefront".to_string());
+        }
+        if options.edge_samples.map_or(false, |n| n < 1) {
+            return Err("--edge-samples must be at least 1".to_string());
+        }
+        if options.parallel == Parallel::Tiles(0) {
+            return Err("--tiles must be at least 1".to_string());
+        }
+        if !options
+            .target_rmse
+            .map_or(true, |e| e > 0. && e.is_finite())
+        {
+            return Err("--target-rmse must be positive".to_string());
+        }
+        if options.target_rmse.is_some() && options.flat {
+            return Err("--target-rmse can't be combined with --flat".to_string());
+        }
+        if options.sample_map.is_some() && options.wavefront {
+            return Err("--sample-map can't be combined with --wavefront".to_string());
+        }
+        if !options.stereo.map_or(true, f64::is_finite) {
+            return Err("--stereo needs a finite eye separation".to_string());
+        }
+        if options.stereo.is_some()
+            && (options.alpha || options.wavefront || options.edge_samples.is_some())
+        {
+            return Err(
+                "--stereo can't be combined with --alpha, --wavefront or --edge-samples"
+                    .to_string(),
+            );
+        }
+        if options.samples < 1 {
+            return Err("--samples must be at least 1".to_string());
+        }
+        if options.depth < 1 {
+            return Err("--depth must be at least 1".to_string());
+        }
+        if options.quality > 100 {
+            return Err("--quality must be from 0 to 100".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}: \"{}\" is not a valid number", flag, value))
+}
+
+/// `path` with `-suffix` added before its extension, e.g. `out-left.jpg`.
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let name_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}-{}{}", &path[..dot], suffix, &path[dot..])
+        }
+        _ => format!("{}-{}", path, suffix),
+    }
+}
+
+fn choice(flag: &str, value: &str, expected: &str) -> String {
+    format!("{}: \"{}\" is not one of {}", flag, value, expected)
+}
+
+/// `--tiles` and `--split-samples` each replace the default split; only one may.
+fn parallel(current: Parallel, requested: Parallel) -> Result<Parallel, String> {
+    match current {
+        Parallel::Pixels => Ok(requested),
+        _ => Err("--tiles and --split-samples can't be combined".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = std::iter::once("raytracer")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn every_flag_is_parsed() {
+        let options = parse(&[
+            "scene.json",
+            "--alpha",
+            "--flat",
+            "--aovs",
+            "--bounce-heatmap",
+            "--debug-pixel",
+            "3",
+            "4",
+            "--info",
+            "book1",
+            "--write-metadata",
+        ])
+        .unwrap();
+        assert_eq!(options.scene.as_deref(), Some("scene.json"));
+        assert!(options.alpha && options.flat && options.aovs);
+        assert!(options.bounce_heatmap && options.write_metadata);
+        assert_eq!(options.debug_pixel, Some((3, 4)));
+        assert_eq!(options.info.as_deref(), Some("book1"));
+
+        assert!(parse(&["--wavefront"]).unwrap().wavefront);
+        assert_eq!(
+            parse(&["--edge-samples", "64"]).unwrap().edge_samples,
+            Some(64)
+        );
+
+        let defaults = parse(&[]).unwrap();
+        assert!(!defaults.alpha && defaults.debug_pixel.is_none() && defaults.scene.is_none());
+    }
+
+    #[test]
+    fn bad_flags_are_errors() {
+        assert_eq!(
+            parse(&["--debug-pixel", "3"]).err().unwrap(),
+            "--debug-pixel needs a value"
+        );
+        assert!(parse(&["--debug-pixel", "3", "-1"]).is_err());
+        assert!(parse(&["--info"]).is_err());
+        assert!(parse(&["--wavefront", "--alpha"]).is_err());
+        assert!(parse(&["--edge-samples", "64", "--wavefront"]).is_err());
+        assert!(parse(&["--edge-samples", "0"]).is_err());
+        assert_eq!(
+            parse(&["--alhpa"]).err().unwrap(),
+            "unknown argument \"--alhpa\""
+        );
+    }
+
+    #[test]
+    fn render_choices_are_parsed() {
+        let options = parse(&[
+            "--color-space",
+            "rec709",
+            "--tone-map",
+            "aces",
+            "--roulette",
+            "max",
+            "--target-rmse",
+            "0.05",
+            "--sample-map",
+            "importance.png",
+            "--tiles",
+            "16",
+            "--stereo",
+            "0.2",
+        ])
+        .unwrap();
+        assert_eq!(options.color_space, ColorSpace::Rec709);
+        assert_eq!(options.tone_map, ToneMap::Aces);
+        assert_eq!(options.roulette, RussianRoulette::MaxComponent);
+        assert_eq!(options.target_rmse, Some(0.05));
+        assert_eq!(options.sample_map.as_deref(), Some("importance.png"));
+        assert_eq!(options.parallel, Parallel::Tiles(16));
+        assert_eq!(options.stereo, Some(0.2));
+        assert_eq!(
+            parse(&["--split-samples"]).unwrap().parallel,
+            Parallel::Samples
+        );
+
+        let defaults = parse(&[]).unwrap();
+        assert_eq!(defaults.tone_map, ToneMap::None);
+        assert_eq!(defaults.roulette, RussianRoulette::Off);
+        assert_eq!(defaults.parallel, Parallel::Pixels);
+    }
+
+    #[test]
+    fn bad_render_choices_are_errors() {
+        assert_eq!(
+            parse(&["--tone-map", "filmic"]).err().unwrap(),
+            "--tone-map: \"filmic\" is not one of none, reinhard or aces"
+        );
+        assert!(parse(&["--color-space", "p3"]).is_err());
+        assert!(parse(&["--roulette", "on"]).is_err());
+        assert!(parse(&["--tiles", "0"]).is_err());
+        assert!(parse(&["--tiles", "8", "--split-samples"]).is_err());
+        assert!(parse(&["--target-rmse", "0"]).is_err());
+        assert!(parse(&["--target-rmse", "0.1", "--flat"]).is_err());
+        assert!(parse(&["--sample-map", "map.png", "--wavefront"]).is_err());
+        assert!(parse(&["--stereo", "0.2", "--alpha"]).is_err());
+    }
+
+    #[test]
+    fn suffixes_go_before_the_extension() {
+        assert_eq!(
+            with_suffix("output/output.jpg", "left"),
+            "output/output-left.jpg"
+        );
+        assert_eq!(with_suffix("out.v2/image", "right"), "out.v2/image-right");
+        assert_eq!(with_suffix(".hidden", "left"), ".hidden-left");
+    }
+
+    #[test]
+    fn gamma_must_be_positive() {
+        let options = parse(&["--gamma", "1"]).unwrap();
+        assert_eq!(options.color_space, ColorSpace::Gamma(1.));
+        for &bad in ["0", "-2.2", "NaN", "inf"].iter() {
+            let err = parse(&["--gamma", bad]).err().unwrap();
+            assert!(
+                err.starts_with("--gamma: gamma must be positive"),
+                "{}",
+                err
+            );
+        }
+        assert!(parse(&["--gamma", "x"]).is_err());
+    }
+}
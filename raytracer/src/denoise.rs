@@ -0,0 +1,208 @@
+use crate::basic::vec::{Color, Vec3};
+
+/// Linear (not tone mapped) per-pixel values in image order, top row first.
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::new(0., 0., 0.); (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+/// Runs on the averaged radiance before tone mapping. `albedo` and `normal`
+/// are first-hit feature buffers of the same size, when they were rendered.
+pub trait Denoiser: Sync {
+    fn denoise(
+        &self,
+        color: &Framebuffer,
+        albedo: Option<&Framebuffer>,
+        normal: Option<&Framebuffer>,
+    ) -> Framebuffer;
+}
+
+/// Joint bilateral filter: neighbours are averaged with weights falling off
+/// with pixel distance and with differences in color, albedo and normal, so
+/// noise is smoothed within a surface but not across its edges. Neighbours
+/// whose albedo or normal differs in any channel by more than the threshold
+/// are left out entirely, so painted edges on flat geometry stay sharp.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct BilateralDenoiser {
+    pub radius: u32,
+    pub sigma_spatial: f64,
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+    pub albedo_threshold: f64,
+    pub normal_threshold: f64,
+}
+
+impl Default for BilateralDenoiser {
+    fn default() -> Self {
+        Self {
+            radius: 3,
+            sigma_spatial: 2.,
+            sigma_color: 1.,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.2,
+            albedo_threshold: 0.2,
+            normal_threshold: 0.5,
+        }
+    }
+}
+
+impl Denoiser for BilateralDenoiser {
+    fn denoise(
+        &self,
+        color: &Framebuffer,
+        albedo: Option<&Framebuffer>,
+        normal: Option<&Framebuffer>,
+    ) -> Framebuffer {
+        let falloff = |d2: f64, sigma: f64| (-d2 / (2. * sigma * sigma)).exp();
+        let mut out = Framebuffer::new(color.width, color.height);
+        for y in 0..color.height {
+            for x in 0..color.width {
+                let center = color.get(x, y);
+                let mut sum = Vec3::new(0., 0., 0.);
+                let mut total = 0.;
+                for qy in y.saturating_sub(self.radius)..(y + self.radius + 1).min(color.height) {
+                    for qx in x.saturating_sub(self.radius)..(x + self.radius + 1).min(color.width)
+                    {
+                        let dx = qx as f64 - x as f64;
+                        let dy = qy as f64 - y as f64;
+                        let value = color.get(qx, qy);
+                        let feature = |buffer: Option<&Framebuffer>, sigma, threshold| {
+                            buffer.map_or(1., |b| {
+                                let d = b.get(x, y) - b.get(qx, qy);
+                                if d.x.abs().max(d.y.abs()).max(d.z.abs()) > threshold {
+                                    0.
+                                } else {
+                                    falloff(d.length_squared(), sigma)
+                                }
+                            })
+                        };
+                        let weight = falloff(dx * dx + dy * dy, self.sigma_spatial)
+                            * falloff((value - center).length_squared(), self.sigma_color)
+                            * feature(albedo, self.sigma_albedo, self.albedo_threshold)
+                            * feature(normal, self.sigma_normal, self.normal_threshold);
+                        sum += value * weight;
+                        total += weight;
+                    }
+                }
+                out.pixels[(y * color.width + x) as usize] = sum / total;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::sampler::SampleSequence;
+    use rand::Rng;
+
+    /// `width` by `height` pixels, the left half `left` and the rest `right`.
+    fn halves(width: u32, height: u32, left: Color, right: Color) -> Framebuffer {
+        let mut buffer = Framebuffer::new(width, height);
+        for (i, pixel) in buffer.pixels.iter_mut().enumerate() {
+            let x = i as u32 % width;
+            *pixel = if x < width / 2 { left } else { right };
+        }
+        buffer
+    }
+
+    fn add_noise(buffer: &mut Framebuffer, amount: f64) {
+        let mut rng = SampleSequence::new(0, 0, 0, 21);
+        for pixel in &mut buffer.pixels {
+            *pixel += Color::new(1., 1., 1.) * rng.gen_range(-amount..amount);
+        }
+    }
+
+    /// Mean and variance of the red channel over columns `columns`.
+    fn column_stats(buffer: &Framebuffer, columns: std::ops::Range<u32>) -> (f64, f64) {
+        let values: Vec<f64> = (0..buffer.height)
+            .flat_map(|y| columns.clone().map(move |x| (x, y)))
+            .map(|(x, y)| buffer.get(x, y).x)
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        (mean, variance)
+    }
+
+    #[test]
+    fn noise_is_smoothed_but_normal_edges_stay() {
+        let (gray, light) = (Color::new(0.5, 0.5, 0.5), Color::new(0.9, 0.9, 0.9));
+        let mut color = halves(32, 16, gray, light);
+        add_noise(&mut color, 0.1);
+        let normal = halves(32, 16, Vec3::new(0., 0., 1.), Vec3::new(1., 0., 0.));
+        let out = BilateralDenoiser::default().denoise(&color, None, Some(&normal));
+
+        // Well inside the left half.
+        let (_, noisy) = column_stats(&color, 3..12);
+        let (mean, smooth) = column_stats(&out, 3..12);
+        assert!(smooth < noisy / 4., "{} -> {}", noisy, smooth);
+        assert!((mean - 0.5).abs() < 0.02, "{}", mean);
+
+        // The columns either side of the crease keep their own side's level.
+        let (left, _) = column_stats(&out, 15..16);
+        let (right, _) = column_stats(&out, 16..17);
+        assert!((left - 0.5).abs() < 0.03, "{}", left);
+        assert!((right - 0.9).abs() < 0.03, "{}", right);
+
+        // Without the normals the same edge blurs.
+        let blurred = BilateralDenoiser::default().denoise(&color, None, None);
+        let (left, _) = column_stats(&blurred, 15..16);
+        assert!(left > 0.55, "{}", left);
+    }
+
+    #[test]
+    fn albedo_stripes_stay_sharp() {
+        // A two pixel red stripe painted across flat gray geometry.
+        let (gray, red) = (Color::new(0.2, 0.2, 0.2), Color::new(0.9, 0.1, 0.1));
+        let mut albedo = Framebuffer::new(32, 16);
+        for (i, pixel) in albedo.pixels.iter_mut().enumerate() {
+            *pixel = if (15..17).contains(&(i % 32)) {
+                red
+            } else {
+                gray
+            };
+        }
+        let mut color = albedo.clone();
+        add_noise(&mut color, 0.05);
+        let normal = halves(32, 16, Vec3::new(0., 0., 1.), Vec3::new(0., 0., 1.));
+
+        let denoiser = BilateralDenoiser {
+            sigma_color: 10.,
+            ..Default::default()
+        };
+        let out = denoiser.denoise(&color, Some(&albedo), Some(&normal));
+        let (stripe, _) = column_stats(&out, 15..17);
+        let (beside, _) = column_stats(&out, 14..15);
+        assert!((stripe - 0.9).abs() < 0.02, "{}", stripe);
+        assert!((beside - 0.2).abs() < 0.02, "{}", beside);
+
+        // With the cutoff out of reach the falloff alone lets the stripe bleed.
+        let loose = BilateralDenoiser {
+            albedo_threshold: f64::INFINITY,
+            sigma_albedo: 1.,
+            ..denoiser
+        };
+        let out = loose.denoise(&color, Some(&albedo), Some(&normal));
+        let (stripe, _) = column_stats(&out, 15..17);
+        assert!(stripe < 0.8, "{}", stripe);
+    }
+}
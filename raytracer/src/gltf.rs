@@ -0,0 +1,790 @@
+use std::{fs, path::Path, sync::Arc};
+
+use crate::{
+    basic::{
+        camera::Camera,
+        convention::CoordinateConvention,
+        vec::{Color, Point3, Vec3},
+    },
+    hittable::{
+        bvh::BvhNode,
+        triangle::{collect_emissive, Triangle},
+        HittableList,
+    },
+    json::Json,
+    material::{CookTorrance, DiffuseLight, Lambertian, MaterialHandle},
+    texture::ImageTexture,
+};
+
+type Mat4 = [[f64; 4]; 4];
+
+const IDENTITY: Mat4 = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+/// Loads triangle meshes and perspective cameras from a `.gltf` file.
+///
+/// Only a subset is understood: buffers and images embedded as base64 data
+/// URIs, stored in a buffer view, or stored next to the file; indexed or
+/// non-indexed triangle lists, smooth shaded when they have `NORMAL`s; node
+/// transforms; and materials. `pbrMetallicRoughness` becomes a `CookTorrance`
+/// with `baseColorFactor`, `metallicFactor` and `roughnessFactor`; a
+/// `baseColorTexture` sampled with `TEXCOORD_0` a Lambertian, since
+/// `CookTorrance` takes no textures; and `emissiveFactor` a `DiffuseLight`.
+/// Primitives without a material are white Lambertians.
+#[allow(dead_code)]
+pub fn load_gltf(path: &str) -> Result<(HittableList, Vec<Camera>), String> {
+    load_gltf_with(path, CoordinateConvention::YUpRightHanded)
+}
+
+/// Like `load_gltf`, for exporters that ignore glTF's +Y up right-handed axes.
+pub fn load_gltf_with(
+    path: &str,
+    convention: CoordinateConvention,
+) -> Result<(HittableList, Vec<Camera>), String> {
+    load_gltf_with_lights(path, convention).map(|(world, _lights, cameras)| (world, cameras))
+}
+
+/// Like `load_gltf_with`, also returning the triangles of emissive materials
+/// (`emissiveFactor`) as a light list for importance sampling.
+pub fn load_gltf_with_lights(
+    path: &str,
+    convention: CoordinateConvention,
+) -> Result<(HittableList, HittableList, Vec<Camera>), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let doc = Json::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut buffers = Vec::<Vec<u8>>::new();
+    for buffer in array(&doc, "buffers") {
+        let uri = buffer
+            .get("uri")
+            .and_then(Json::as_str)
+            .ok_or("gltf: buffer without uri (glb is not supported)")?;
+        buffers.push(read_uri(dir, uri)?);
+    }
+
+    let loader = Loader {
+        doc: &doc,
+        convention,
+        dir,
+        buffers,
+        world: HittableList::default(),
+        lights: HittableList::default(),
+        cameras: Vec::new(),
+    };
+    loader.load()
+}
+
+struct Loader<'a> {
+    doc: &'a Json,
+    convention: CoordinateConvention,
+    dir: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    world: HittableList,
+    lights: HittableList,
+    cameras: Vec<Camera>,
+}
+
+impl<'a> Loader<'a> {
+    fn load(mut self) -> Result<(HittableList, HittableList, Vec<Camera>), String> {
+        let scene_id = self.doc.get("scene").and_then(Json::as_usize).unwrap_or(0);
+        let roots: Vec<usize> = match self.doc.get("scenes").and_then(|s| s.at(scene_id)) {
+            Some(scene) => array(scene, "nodes")
+                .iter()
+                .filter_map(Json::as_usize)
+                .collect(),
+            None => (0..array(self.doc, "nodes").len()).collect(),
+        };
+        let mut root = IDENTITY;
+        for (j, axis) in [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., 0., 1.),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let column = self.convention.to_world(*axis);
+            root[0][j] = column.x;
+            root[1][j] = column.y;
+            root[2][j] = column.z;
+        }
+        for node in roots {
+            self.visit(node, root, 0)?;
+        }
+        Ok((self.world, self.lights, self.cameras))
+    }
+
+    fn visit(&mut self, node_id: usize, parent: Mat4, depth: usize) -> Result<(), String> {
+        if depth > 64 {
+            return Err("gltf: node hierarchy too deep".to_string());
+        }
+        let node = self
+            .doc
+            .get("nodes")
+            .and_then(|n| n.at(node_id))
+            .ok_or_else(|| format!("gltf: missing node {}", node_id))?;
+        let transform = mat_mul(parent, node_transform(node));
+
+        if let Some(mesh_id) = node.get("mesh").and_then(Json::as_usize) {
+            self.load_mesh(mesh_id, transform)?;
+        }
+        if let Some(camera_id) = node.get("camera").and_then(Json::as_usize) {
+            self.load_camera(camera_id, transform)?;
+        }
+        for child in array(node, "children") {
+            if let Some(child) = child.as_usize() {
+                self.visit(child, transform, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_mesh(&mut self, mesh_id: usize, transform: Mat4) -> Result<(), String> {
+        let mesh = self
+            .doc
+            .get("meshes")
+            .and_then(|m| m.at(mesh_id))
+            .ok_or_else(|| format!("gltf: missing mesh {}", mesh_id))?;
+
+        let mut triangles = Vec::new();
+        for primitive in array(mesh, "primitives") {
+            // 4 = TRIANGLES, the default mode
+            if primitive.get("mode").and_then(Json::as_usize).unwrap_or(4) != 4 {
+                continue;
+            }
+            let position_id = primitive
+                .get("attributes")
+                .and_then(|a| a.get("POSITION"))
+                .and_then(Json::as_usize)
+                .ok_or("gltf: primitive without POSITION")?;
+            let positions: Vec<Point3> = self
+                .accessor(position_id)?
+                .chunks_exact(3)
+                .map(|p| transform_point(transform, Point3::new(p[0], p[1], p[2])))
+                .collect();
+            let uvs: Option<Vec<(f64, f64)>> = match primitive
+                .get("attributes")
+                .and_then(|a| a.get("TEXCOORD_0"))
+                .and_then(Json::as_usize)
+            {
+                // glTF puts the uv origin at the top left, textures here at the bottom left.
+                Some(uv_id) => Some(
+                    self.accessor(uv_id)?
+                        .chunks_exact(2)
+                        .map(|uv| (uv[0], 1. - uv[1]))
+                        .collect(),
+                ),
+                None => None,
+            };
+            let normals: Option<Vec<Vec3>> = match primitive
+                .get("attributes")
+                .and_then(|a| a.get("NORMAL"))
+                .and_then(Json::as_usize)
+            {
+                Some(normal_id) => Some(
+                    self.accessor(normal_id)?
+                        .chunks_exact(3)
+                        .map(|n| transform_normal(transform, Vec3::new(n[0], n[1], n[2])))
+                        .collect(),
+                ),
+                None => None,
+            };
+            let indices: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+                Some(indices_id) => self
+                    .accessor(indices_id)?
+                    .iter()
+                    .map(|i| *i as usize)
+                    .collect(),
+                None => (0..positions.len()).collect(),
+            };
+
+            // A mirroring transform turns counter-clockwise faces clockwise.
+            let mirrored = determinant(transform) < 0.;
+            let material = self.material(primitive, uvs.is_some())?;
+            for tri in indices.chunks_exact(3) {
+                let tri = if mirrored {
+                    [tri[0], tri[2], tri[1]]
+                } else {
+                    [tri[0], tri[1], tri[2]]
+                };
+                if tri.iter().any(|i| *i >= positions.len()) {
+                    return Err("gltf: index out of range".to_string());
+                }
+                let mut triangle = Triangle::new(
+                    positions[tri[0]],
+                    positions[tri[1]],
+                    positions[tri[2]],
+                    material.clone(),
+                );
+                if let Some(uvs) = &uvs {
+                    if tri.iter().any(|i| *i >= uvs.len()) {
+                        return Err("gltf: TEXCOORD_0 shorter than POSITION".to_string());
+                    }
+                    triangle = triangle.with_uv([uvs[tri[0]], uvs[tri[1]], uvs[tri[2]]]);
+                }
+                if let Some(normals) = &normals {
+                    if tri.iter().any(|i| *i >= normals.len()) {
+                        return Err("gltf: NORMAL shorter than POSITION".to_string());
+                    }
+                    triangle =
+                        triangle.with_normals([normals[tri[0]], normals[tri[1]], normals[tri[2]]]);
+                }
+                triangles.push(Arc::new(triangle));
+            }
+        }
+
+        if !triangles.is_empty() {
+            let mut object = HittableList::default();
+            for triangle in &triangles {
+                object.add(triangle.clone());
+            }
+            self.world.add(Arc::new(BvhNode::new_list(&object, 0., 1.)));
+            collect_emissive(&triangles, &mut self.lights);
+        }
+        Ok(())
+    }
+
+    fn material(&self, primitive: &Json, has_uv: bool) -> Result<MaterialHandle, String> {
+        let material = primitive
+            .get("material")
+            .and_then(Json::as_usize)
+            .and_then(|m| self.doc.get("materials").and_then(|ms| ms.at(m)));
+        let emissive = material
+            .and_then(|m| m.get("emissiveFactor"))
+            .and_then(|c| {
+                Some(Color::new(
+                    c.at(0)?.as_f64()?,
+                    c.at(1)?.as_f64()?,
+                    c.at(2)?.as_f64()?,
+                ))
+            })
+            .filter(|c| c.length_squared() > 0.);
+        if let Some(emissive) = emissive {
+            return Ok(MaterialHandle::new(DiffuseLight::new(emissive)));
+        }
+        let material = match material {
+            Some(material) => material,
+            None => return Ok(MaterialHandle::new(Lambertian::new(Color::new(1., 1., 1.)))),
+        };
+        let pbr = material.get("pbrMetallicRoughness");
+        let texture_id = pbr
+            .and_then(|pbr| pbr.get("baseColorTexture"))
+            .and_then(|t| t.get("index"))
+            .and_then(Json::as_usize);
+        if let (Some(texture_id), true) = (texture_id, has_uv) {
+            return Ok(MaterialHandle::new(Lambertian::new_arc(
+                self.texture(texture_id)?,
+            )));
+        }
+        // Both factors default to 1 in glTF.
+        let factor = |key| {
+            pbr.and_then(|pbr| pbr.get(key))
+                .and_then(Json::as_f64)
+                .unwrap_or(1.)
+        };
+        Ok(MaterialHandle::new(CookTorrance::new(
+            base_color(pbr),
+            factor("roughnessFactor"),
+            factor("metallicFactor"),
+        )))
+    }
+
+    fn texture(&self, texture_id: usize) -> Result<ImageTexture, String> {
+        let image = self
+            .doc
+            .get("textures")
+            .and_then(|t| t.at(texture_id))
+            .and_then(|t| t.get("source"))
+            .and_then(Json::as_usize)
+            .and_then(|i| self.doc.get("images").and_then(|is| is.at(i)))
+            .ok_or_else(|| format!("gltf: missing image for texture {}", texture_id))?;
+        let bytes = if let Some(uri) = image.get("uri").and_then(Json::as_str) {
+            read_uri(self.dir, uri)?
+        } else {
+            let view = image
+                .get("bufferView")
+                .and_then(Json::as_usize)
+                .and_then(|v| self.doc.get("bufferViews").and_then(|vs| vs.at(v)))
+                .ok_or("gltf: image without uri or bufferView")?;
+            let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+            let length = view.get("byteLength").and_then(Json::as_usize).unwrap_or(0);
+            view.get("buffer")
+                .and_then(Json::as_usize)
+                .and_then(|b| self.buffers.get(b))
+                .and_then(|b| b.get(offset..offset + length))
+                .ok_or("gltf: image bufferView out of buffer range")?
+                .to_vec()
+        };
+        ImageTexture::from_memory(&bytes).map_err(|e| format!("gltf: {}", e))
+    }
+
+    fn load_camera(&mut self, camera_id: usize, transform: Mat4) -> Result<(), String> {
+        let camera = self
+            .doc
+            .get("cameras")
+            .and_then(|c| c.at(camera_id))
+            .ok_or_else(|| format!("gltf: missing camera {}", camera_id))?;
+        let perspective = match camera.get("perspective") {
+            Some(perspective) => perspective,
+            // Orthographic cameras are not supported.
+            None => return Ok(()),
+        };
+        let yfov = perspective
+            .get("yfov")
+            .and_then(Json::as_f64)
+            .ok_or("gltf: perspective camera without yfov")?;
+        let aspect_ratio = perspective
+            .get("aspectRatio")
+            .and_then(Json::as_f64)
+            .unwrap_or(1.);
+
+        // glTF cameras look down -Z with +Y up.
+        let lookfrom = transform_point(transform, Point3::new(0., 0., 0.));
+        let forward = transform_dir(transform, Vec3::new(0., 0., -1.));
+        let vup = transform_dir(transform, Vec3::new(0., 1., 0.));
+        self.cameras.push(Camera::new(
+            lookfrom,
+            lookfrom + forward,
+            vup,
+            yfov.to_degrees(),
+            aspect_ratio,
+            0.,
+            1.,
+            0.,
+            1.,
+        ));
+        Ok(())
+    }
+
+    /// Reads an accessor into a flat list of numbers.
+    fn accessor(&self, accessor_id: usize) -> Result<Vec<f64>, String> {
+        let accessor = self
+            .doc
+            .get("accessors")
+            .and_then(|a| a.at(accessor_id))
+            .ok_or_else(|| format!("gltf: missing accessor {}", accessor_id))?;
+        let view = accessor
+            .get("bufferView")
+            .and_then(Json::as_usize)
+            .and_then(|v| self.doc.get("bufferViews").and_then(|vs| vs.at(v)))
+            .ok_or("gltf: accessor without bufferView")?;
+        let buffer = view
+            .get("buffer")
+            .and_then(Json::as_usize)
+            .and_then(|b| self.buffers.get(b))
+            .ok_or("gltf: bufferView with missing buffer")?;
+
+        let count = accessor.get("count").and_then(Json::as_usize).unwrap_or(0);
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err("gltf: unsupported accessor type".to_string()),
+        };
+        let component_type = accessor
+            .get("componentType")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err("gltf: unsupported componentType".to_string()),
+        };
+        let view_offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let view_length = view
+            .get("byteLength")
+            .and_then(Json::as_usize)
+            .ok_or("gltf: bufferView without byteLength")?;
+        let accessor_offset = accessor
+            .get("byteOffset")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let stride = view
+            .get("byteStride")
+            .and_then(Json::as_usize)
+            .unwrap_or(size * components);
+        if stride < size * components {
+            return Err("gltf: byteStride smaller than an element".to_string());
+        }
+        // The last element has to end inside the view, which bounds `count`
+        // by the bytes really there before anything is allocated.
+        if count > 0 {
+            (count - 1)
+                .checked_mul(stride)
+                .and_then(|n| n.checked_add(accessor_offset + size * components))
+                .filter(|&end| end <= view_length)
+                .ok_or("gltf: accessor overruns its bufferView")?;
+        }
+        if view_offset.saturating_add(view_length) > buffer.len() {
+            return Err("gltf: bufferView out of buffer range".to_string());
+        }
+        let offset = view_offset + accessor_offset;
+
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let at = offset + i * stride + c * size;
+                let bytes = buffer
+                    .get(at..at + size)
+                    .ok_or("gltf: accessor out of buffer range")?;
+                values.push(match component_type {
+                    5120 => bytes[0] as i8 as f64,
+                    5121 => bytes[0] as f64,
+                    5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5125 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                });
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn base_color(pbr: Option<&Json>) -> Color {
+    pbr.and_then(|pbr| pbr.get("baseColorFactor"))
+        .and_then(|c| {
+            Some(Color::new(
+                c.at(0)?.as_f64()?,
+                c.at(1)?.as_f64()?,
+                c.at(2)?.as_f64()?,
+            ))
+        })
+        .unwrap_or_else(|| Color::new(1., 1., 1.))
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key)
+        .and_then(Json::as_array)
+        .map_or(&[], |items| items.as_slice())
+}
+
+fn read_uri(dir: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let payload = data
+            .find(";base64,")
+            .map(|i| &data[i + 8..])
+            .ok_or("gltf: only base64 data URIs are supported")?;
+        decode_base64(payload)
+    } else {
+        let file = dir.join(uri);
+        fs::read(&file).map_err(|e| format!("{}: {}", file.display(), e))
+    }
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return Err("gltf: invalid base64".to_string()),
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[allow(clippy::many_single_char_names)]
+fn node_transform(node: &Json) -> Mat4 {
+    if let Some(m) = node.get("matrix").and_then(Json::as_array) {
+        let mut out = IDENTITY;
+        for (i, value) in m.iter().take(16).enumerate() {
+            // column-major
+            out[i % 4][i / 4] = value.as_f64().unwrap_or(0.);
+        }
+        return out;
+    }
+
+    let vec = |key: &str, default: &[f64]| -> Vec<f64> {
+        match node.get(key).and_then(Json::as_array) {
+            Some(v) => v.iter().map(|x| x.as_f64().unwrap_or(0.)).collect(),
+            None => default.to_vec(),
+        }
+    };
+    let t = vec("translation", &[0., 0., 0.]);
+    let q = vec("rotation", &[0., 0., 0., 1.]);
+    let s = vec("scale", &[1., 1., 1.]);
+    if t.len() < 3 || q.len() < 4 || s.len() < 3 {
+        return IDENTITY;
+    }
+
+    let (x, y, z, w) = (q[0], q[1], q[2], q[3]);
+    let r = [
+        [
+            1. - 2. * (y * y + z * z),
+            2. * (x * y - z * w),
+            2. * (x * z + y * w),
+        ],
+        [
+            2. * (x * y + z * w),
+            1. - 2. * (x * x + z * z),
+            2. * (y * z - x * w),
+        ],
+        [
+            2. * (x * z - y * w),
+            2. * (y * z + x * w),
+            1. - 2. * (x * x + y * y),
+        ],
+    ];
+    let mut out = IDENTITY;
+    for (i, row) in r.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            out[i][j] = value * s[j];
+        }
+        out[i][3] = t[i];
+    }
+    out
+}
+
+fn determinant(m: Mat4) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+fn mat_mul(a: Mat4, b: Mat4) -> Mat4 {
+    let mut out = [[0.; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transform_point(m: Mat4, p: Point3) -> Point3 {
+    transform_dir(m, p) + Vec3::new(m[0][3], m[1][3], m[2][3])
+}
+
+/// Normals go through the inverse transpose; the cofactor matrix is that times
+/// the determinant, so only its sign needs fixing.
+fn transform_normal(m: Mat4, n: Vec3) -> Vec3 {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let n = Vec3::new(
+        cofactor(0, 0) * n.x + cofactor(0, 1) * n.y + cofactor(0, 2) * n.z,
+        cofactor(1, 0) * n.x + cofactor(1, 1) * n.y + cofactor(1, 2) * n.z,
+        cofactor(2, 0) * n.x + cofactor(2, 1) * n.y + cofactor(2, 2) * n.z,
+    );
+    if n.length_squared() > 0. {
+        n.to_unit() * determinant(m).signum()
+    } else {
+        n
+    }
+}
+
+fn transform_dir(m: Mat4, v: Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{basic::ray::Ray, hittable::Hittable};
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+            for i in 0..=chunk.len() {
+                out.push(DIGITS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+        }
+        out
+    }
+
+    /// One triangle in the z = 0 plane with tilted vertex normals and a
+    /// metallic-roughness material, its positions accessor claiming `count`.
+    fn load_triangle(name: &str, count: usize) -> Result<HittableList, String> {
+        let mut buffer = Vec::new();
+        for value in [0., 0., 0., 1., 0., 0., 0., 1., 0.].iter() {
+            buffer.extend_from_slice(&(*value as f32).to_le_bytes());
+        }
+        for _ in 0..3 {
+            for value in [0., 0.6, 0.8].iter() {
+                buffer.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+        }
+        let text = format!(
+            r#"{{
+                "buffers": [{{"uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 36}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3"}},
+                    {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3"}}
+                ],
+                "materials": [{{"pbrMetallicRoughness": {{
+                    "baseColorFactor": [0.8, 0.2, 0.2, 1],
+                    "metallicFactor": 0.25,
+                    "roughnessFactor": 0.5
+                }}}}],
+                "meshes": [{{"primitives": [{{
+                    "attributes": {{"POSITION": 0, "NORMAL": 1}},
+                    "material": 0
+                }}]}}],
+                "nodes": [{{"mesh": 0}}],
+                "scenes": [{{"nodes": [0]}}]
+            }}"#,
+            encode_base64(&buffer),
+            count
+        );
+        let path = std::env::temp_dir().join(format!(
+            "raytracer-gltf-{}-{}.gltf",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        let loaded = load_gltf(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        loaded.map(|(world, _cameras)| world)
+    }
+
+    #[test]
+    fn loads_vertex_normals_and_metallic_roughness() {
+        let world = load_triangle("smooth", 3).unwrap();
+        let r = Ray::new(Point3::new(0.2, 0.2, 1.), Vec3::new(0., 0., -1.), 0.);
+        let rec = world.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.).abs() < 1e-6);
+        assert!(Vec3::distance(rec.geometric_normal, Vec3::new(0., 0., 1.)) < 1e-6);
+        assert!(Vec3::distance(rec.normal, Vec3::new(0., 0.6, 0.8)) < 1e-6);
+        assert!(rec.mat_ptr.name().ends_with("CookTorrance"));
+    }
+
+    #[test]
+    fn rejects_counts_past_the_buffer_view() {
+        let err = load_triangle("overrun", 1 << 40).err().unwrap();
+        assert!(err.contains("overruns"), "{}", err);
+        assert!(load_triangle("short", 4).is_err());
+    }
+
+    /// 2x2 RGB PNG: red and green on the top row, blue and white below.
+    const QUADRANTS_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAIAAAACCAIAAAD91JpzAAAAGUlEQVR4AQEOAPH/AP8AAAD/AAAAAP////8f7gX73t3sKwAAAABJRU5ErkJggg==";
+
+    /// A unit cube at the origin, each face mapping the whole texture with
+    /// its top left corner at glTF uv (0, 0).
+    fn textured_cube() -> String {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::<u16>::new();
+        for axis in 0..3 {
+            for &sign in [-1., 1.].iter() {
+                let mut normal = Vec3::new(0., 0., 0.);
+                normal[axis] = sign;
+                let base = (positions.len() / 3) as u16;
+                for &(s, t, u, v) in [
+                    (-0.5, 0.5, 0., 0.),
+                    (0.5, 0.5, 1., 0.),
+                    (0.5, -0.5, 1., 1.),
+                    (-0.5, -0.5, 0., 1.),
+                ]
+                .iter()
+                {
+                    let mut corner = normal * 0.5;
+                    corner[(axis + 1) % 3] = s;
+                    corner[(axis + 2) % 3] = t;
+                    positions.extend_from_slice(&[corner.x, corner.y, corner.z]);
+                    uvs.extend_from_slice(&[u, v]);
+                }
+                indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+        }
+        let mut buffer = Vec::new();
+        for value in positions.iter().chain(&uvs) {
+            buffer.extend_from_slice(&(*value as f32).to_le_bytes());
+        }
+        for index in &indices {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        format!(
+            r#"{{
+                "buffers": [{{"uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 288}},
+                    {{"buffer": 0, "byteOffset": 288, "byteLength": 192}},
+                    {{"buffer": 0, "byteOffset": 480, "byteLength": 72}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 24, "type": "VEC3"}},
+                    {{"bufferView": 1, "componentType": 5126, "count": 24, "type": "VEC2"}},
+                    {{"bufferView": 2, "componentType": 5123, "count": 36, "type": "SCALAR"}}
+                ],
+                "images": [{{"uri": "data:image/png;base64,{}"}}],
+                "textures": [{{"source": 0}}],
+                "materials": [{{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}}}}}],
+                "meshes": [{{"primitives": [{{
+                    "attributes": {{"POSITION": 0, "TEXCOORD_0": 1}},
+                    "indices": 2,
+                    "material": 0
+                }}]}}],
+                "nodes": [{{"mesh": 0}}],
+                "scenes": [{{"nodes": [0]}}]
+            }}"#,
+            encode_base64(&buffer),
+            QUADRANTS_PNG
+        )
+    }
+
+    #[test]
+    fn embedded_base_color_textures_reach_the_albedo() {
+        let path =
+            std::env::temp_dir().join(format!("raytracer-gltf-cube-{}.gltf", std::process::id()));
+        fs::write(&path, textured_cube()).unwrap();
+        let loaded = load_gltf(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        let (world, _) = loaded.unwrap();
+        let mut counts = Default::default();
+        world.count_primitives(&mut counts);
+        assert_eq!(counts.get("Triangle"), Some(&12));
+
+        let mut rng = crate::basic::sampler::SampleSequence::new(0, 0, 0, 1);
+        // Straight at the +z face; its top left is glTF uv (0, 0).
+        for &(x, y, expected) in [
+            (-0.25, 0.25, Color::new(1., 0., 0.)),
+            (0.25, 0.25, Color::new(0., 1., 0.)),
+            (-0.25, -0.25, Color::new(0., 0., 1.)),
+            (0.25, -0.25, Color::new(1., 1., 1.)),
+        ]
+        .iter()
+        {
+            let r = Ray::new(Point3::new(x, y, 5.), Vec3::new(0., 0., -1.), 0.);
+            let rec = world.hit(r, 0.001, f64::INFINITY).unwrap();
+            assert!((rec.t - 4.5).abs() < 1e-6);
+            assert!(rec.mat_ptr.name().contains("Lambertian"));
+            let albedo = rec.mat_ptr.scatter(r, &rec, &mut rng).unwrap().attenuation;
+            assert!(Vec3::distance(albedo, expected) < 0.01, "at ({}, {})", x, y);
+        }
+    }
+}
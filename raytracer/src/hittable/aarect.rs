@@ -1,6 +1,6 @@
 use std::f64::INFINITY;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use crate::{
     basic::ray::Ray,
@@ -10,6 +10,7 @@ use crate::{
     material::Material,
 };
 
+#[derive(Clone)]
 pub struct XYRect<M>
 where
     M: Material,
@@ -66,11 +67,16 @@ impl<M: Material> Hittable for XYRect<M> {
         );
 
         rec.set_face_normal(r, outward_normal);
+        rec.set_tangents(
+            Vec3::new(self.x1 - self.x0, 0., 0.),
+            Vec3::new(0., self.y1 - self.y0, 0.),
+        );
 
         Some(rec)
     }
 }
 
+#[derive(Clone)]
 pub struct XZRect<M>
 where
     M: Material,
@@ -127,14 +133,18 @@ impl<M: Material> Hittable for XZRect<M> {
         );
 
         rec.set_face_normal(r, outward_normal);
+        rec.set_tangents(
+            Vec3::new(self.x1 - self.x0, 0., 0.),
+            Vec3::new(0., 0., self.z1 - self.z0),
+        );
 
         Some(rec)
     }
 
-    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
-        if let Some(rec) = self.hit(Ray::new(o, v, 0.), 0.001, INFINITY) {
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(Ray::new(o, v, time), 0.001, INFINITY) {
             let area = (self.x1 - self.x0) * (self.z1 - self.z0);
-            let dis_sqr = rec.t * rec.t * v.length_sqr();
+            let dis_sqr = rec.t * rec.t * v.length_squared();
             let cos = (Vec3::dot(v, rec.normal) / v.length()).abs();
             dis_sqr / (cos * area)
         } else {
@@ -142,8 +152,7 @@ impl<M: Material> Hittable for XZRect<M> {
         }
     }
 
-    fn random(&self, origin: Point3) -> Vec3 {
-        let mut rng = rand::thread_rng();
+    fn random(&self, origin: Point3, _time: f64, rng: &mut dyn RngCore) -> Vec3 {
         let random_point = Point3::new(
             rng.gen_range(self.x0..self.x1),
             self.k,
@@ -153,6 +162,7 @@ impl<M: Material> Hittable for XZRect<M> {
     }
 }
 
+#[derive(Clone)]
 pub struct YZRect<M>
 where
     M: Material,
@@ -209,6 +219,10 @@ impl<M: Material> Hittable for YZRect<M> {
         );
 
         rec.set_face_normal(r, outward_normal);
+        rec.set_tangents(
+            Vec3::new(0., self.y1 - self.y0, 0.),
+            Vec3::new(0., 0., self.z1 - self.z0),
+        );
 
         Some(rec)
     }
@@ -5,10 +5,12 @@ use crate::{
     basic::vec::Point3,
     hittable::aarect::{XYRect, XZRect, YZRect},
     hittable::bvh::aabb::AABB,
-    hittable::{Hittable, HittableList},
+    hittable::{FlipFace, Hittable, HittableList},
     material::Material,
 };
 
+/// Six rectangles whose front faces all point out of the box, so glass knows
+/// when a ray leaves it.
 pub struct Boxes {
     min: Point3,
     max: Point3,
@@ -29,14 +31,14 @@ impl Boxes {
             p1.z,
             ptr.clone(),
         )));
-        sides.add(Arc::new(XYRect::new(
+        sides.add(Arc::new(FlipFace::new(XYRect::new(
             p0.x,
             p1.x,
             p0.y,
             p1.y,
             p0.z,
             ptr.clone(),
-        )));
+        ))));
 
         sides.add(Arc::new(XZRect::new(
             p0.x,
@@ -46,14 +48,14 @@ impl Boxes {
             p1.y,
             ptr.clone(),
         )));
-        sides.add(Arc::new(XZRect::new(
+        sides.add(Arc::new(FlipFace::new(XZRect::new(
             p0.x,
             p1.x,
             p0.z,
             p1.z,
             p0.y,
             ptr.clone(),
-        )));
+        ))));
 
         sides.add(Arc::new(YZRect::new(
             p0.y,
@@ -63,7 +65,9 @@ impl Boxes {
             p1.x,
             ptr.clone(),
         )));
-        sides.add(Arc::new(YZRect::new(p0.y, p1.y, p0.z, p1.z, p0.x, ptr)));
+        sides.add(Arc::new(FlipFace::new(YZRect::new(
+            p0.y, p1.y, p0.z, p1.z, p0.x, ptr,
+        ))));
 
         Self {
             min: p0,
@@ -80,4 +84,7 @@ impl Hittable for Boxes {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<crate::hittable::HitRecord> {
         self.sides.hit(r, t_min, t_max)
     }
+    fn contains(&self, p: Point3) -> bool {
+        (0..3).all(|axis| self.min[axis] < p[axis] && p[axis] < self.max[axis])
+    }
 }
@@ -12,23 +12,40 @@ impl AABB {
         Self { min, max }
     }
 
+    /// Slab test. A ray parallel to a slab hits it only from inside: the
+    /// infinite `inv_d` would give NaN for an origin on one of its faces.
     pub fn hit(&self, r: Ray, tmin: f64, tmax: f64) -> bool {
         let mut t_min = tmin;
         let mut t_max = tmax;
         for i in 0..3 {
-            let inv_d = 1. / r.dir[i];
-            let mut t0 = (self.min[i] - r.orig[i]) * inv_d;
-            let mut t1 = (self.max[i] - r.orig[i]) * inv_d;
-            if inv_d < 0. {
-                std::mem::swap(&mut t0, &mut t1);
-            }
-            t_min = if t0 > t_min { t0 } else { t_min };
-            t_max = if t1 < t_max { t1 } else { t_max };
-            if t_max <= t_min {
-                return false;
+            if r.dir[i] == 0. {
+                if r.orig[i] < self.min[i] || r.orig[i] > self.max[i] {
+                    return false;
+                }
+                continue;
             }
+            let inv_d = 1. / r.dir[i];
+            let t0 = (self.min[i] - r.orig[i]) * inv_d;
+            let t1 = (self.max[i] - r.orig[i]) * inv_d;
+            let (near, far) = if inv_d < 0. { (t1, t0) } else { (t0, t1) };
+            t_min = t_min.max(near);
+            t_max = t_max.min(far);
         }
-        true
+        t_min < t_max
+    }
+
+    /// Whether `p` lies inside the box or on its boundary.
+    pub fn contains(&self, p: Point3) -> bool {
+        (0..3).all(|axis| self.min[axis] <= p[axis] && p[axis] <= self.max[axis])
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) * 0.5
     }
 
     pub fn surrounding_box(box0: Self, box1: Self) -> Self {
@@ -45,3 +62,31 @@ impl AABB {
         AABB::new(small, large)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec::Vec3;
+
+    #[test]
+    fn rays_parallel_to_a_slab_hit_only_from_within_it() {
+        let unit = AABB::new(Point3::new(0., 0., 0.), Point3::new(1., 1., 1.));
+        // Along +x, or -x with a negative zero in y, at each height.
+        let hits = |y: f64| {
+            let along = Ray::new(Point3::new(-1., y, 0.5), Vec3::new(1., 0., 0.), 0.);
+            let back = Ray::new(Point3::new(2., y, 0.5), Vec3::new(-1., -0., 0.), 0.);
+            let (along, back) = (
+                unit.hit(along, 0.001, f64::INFINITY),
+                unit.hit(back, 0.001, f64::INFINITY),
+            );
+            assert_eq!(along, back, "y = {}", y);
+            along
+        };
+        assert!(hits(0.5));
+        // Grazing either face, where 0 * inf would be NaN.
+        assert!(hits(0.));
+        assert!(hits(1.));
+        assert!(!hits(1.5));
+        assert!(!hits(-1e-9));
+    }
+}
@@ -2,104 +2,605 @@ pub mod aabb;
 
 use rand::Rng;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use crate::basic::ray::Ray;
-use crate::hittable::{Hittable, HittableList};
+use crate::basic::{ray::Ray, vec::Point3};
+use crate::hittable::{march_intervals, primitive::PrimitiveList, Hittable, HittableList};
 use aabb::AABB;
 
 #[derive(Clone)]
 pub struct BvhNode {
     aabbox: AABB,
-    left: Option<Arc<dyn Hittable>>,
-    right: Option<Arc<dyn Hittable>>,
+    left: Option<Arc<BvhNode>>,
+    right: Option<Arc<BvhNode>>,
+    // The children were split along this axis, left one lower.
+    axis: usize,
+    leaf: Vec<Arc<dyn Hittable>>,
+    // Objects without a bounding box, like planes, tested on every ray. Only
+    // the root has any.
+    unbounded: Vec<Arc<dyn Hittable>>,
+    depth: usize,
+    node_count: usize,
 }
 
+/// Fastest of 1 to 8 on 1000 random spheres in `bench_max_leaf_sizes`
+/// (761 ns/ray against 958 for single-object leaves). 100k heavily
+/// overlapping spheres prefer 6, but only by about a fifth.
+pub const DEFAULT_MAX_LEAF_SIZE: usize = 4;
+
 impl BvhNode {
-    pub fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
-        let box_a = a.bounding_box(0., 0.).unwrap();
-        let box_b = b.bounding_box(0., 0.).unwrap();
-        if box_a.min[axis] < box_b.min[axis] {
-            Ordering::Less
-        } else if box_a.min[axis] > box_b.min[axis] {
-            Ordering::Greater
-        } else {
-            Ordering::Equal
-        }
-    }
     pub fn new_list(list: &HittableList, time0: f64, time1: f64) -> Self {
         BvhNode::new_vec(list.objects.clone(), time0, time1)
     }
-    #[allow(unused_assignments)]
-    pub fn new_vec(mut objects: Vec<Arc<dyn Hittable>>, time0: f64, time1: f64) -> Self {
-        let axis: usize = rand::thread_rng().gen_range(0..3);
+    pub fn new_vec(objects: Vec<Arc<dyn Hittable>>, time0: f64, time1: f64) -> Self {
+        BvhNode::new(objects, DEFAULT_MAX_LEAF_SIZE, time0, time1)
+    }
+    fn box_compare<T>(a: &(AABB, T), b: &(AABB, T), axis: usize) -> Ordering {
+        a.0.min[axis]
+            .partial_cmp(&b.0.min[axis])
+            .unwrap_or(Ordering::Equal)
+    }
+    fn centroid_compare<T>(a: &(AABB, T), b: &(AABB, T), axis: usize) -> Ordering {
+        let (ca, cb) = (a.0.centroid()[axis], b.0.centroid()[axis]);
+        ca.partial_cmp(&cb).unwrap_or(Ordering::Equal)
+    }
+    /// Sorts `objects` along the axis with the cheapest surface area heuristic
+    /// split and returns that axis and where the right half starts. Every candidate shares
+    /// the traversal cost and the parent's area, so only
+    /// `left_count * left_area + right_count * right_area` is compared.
+    fn sah_split<T>(objects: &mut [(AABB, T)]) -> (usize, usize) {
+        let n = objects.len();
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            objects.sort_by(|a, b| BvhNode::centroid_compare(a, b, axis));
+            // right_area[i] covers objects[i..]
+            let mut right_area = vec![0.; n];
+            let mut right_box = objects[n - 1].0;
+            right_area[n - 1] = right_box.surface_area();
+            for i in (1..n - 1).rev() {
+                right_box = AABB::surrounding_box(right_box, objects[i].0);
+                right_area[i] = right_box.surface_area();
+            }
+            let mut left_box = objects[0].0;
+            for i in 1..n {
+                let cost = i as f64 * left_box.surface_area() + (n - i) as f64 * right_area[i];
+                if best.map_or(true, |(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, i));
+                }
+                left_box = AABB::surrounding_box(left_box, objects[i].0);
+            }
+        }
+        let (_, axis, split) = best.unwrap();
+        if axis != 2 {
+            objects.sort_by(|a, b| BvhNode::centroid_compare(a, b, axis));
+        }
+        (axis, split)
+    }
+    /// Stops splitting once a node holds at most `max_leaf_size` objects.
+    /// Larger nodes split by the surface area heuristic, two objects by the
+    /// median along a random axis. Objects without a bounding box stay out of
+    /// the tree and are tested separately.
+    pub fn new(
+        objects: Vec<Arc<dyn Hittable>>,
+        max_leaf_size: usize,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box(time0, time1) {
+                Some(aabbox) => bounded.push((aabbox, object)),
+                None => unbounded.push(object),
+            }
+        }
+        if bounded.is_empty() && !unbounded.is_empty() {
+            // A box no ray hits, so traversal goes straight to `unbounded`.
+            let inf = f64::INFINITY;
+            return Self {
+                aabbox: AABB::new(Point3::new(inf, inf, inf), Point3::new(-inf, -inf, -inf)),
+                left: None,
+                right: None,
+                axis: 0,
+                leaf: Vec::new(),
+                unbounded,
+                depth: 1,
+                node_count: 1,
+            };
+        }
+        Self {
+            unbounded,
+            ..BvhNode::build(bounded, max_leaf_size, &|leaf| leaf)
+        }
+    }
+    /// Like `new`, but each leaf holds its shapes in one `PrimitiveList`, so
+    /// the per-object tests inside a leaf go through a `match` instead of a
+    /// vtable.
+    pub fn new_primitives(
+        objects: PrimitiveList,
+        max_leaf_size: usize,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = PrimitiveList::default();
+        for object in objects.objects {
+            match object.bounding_box(time0, time1) {
+                Some(aabbox) => bounded.push((aabbox, object)),
+                None => unbounded.add(object),
+            }
+        }
+        let unbounded: Vec<Arc<dyn Hittable>> = if unbounded.objects.is_empty() {
+            Vec::new()
+        } else {
+            vec![Arc::new(unbounded)]
+        };
+        if bounded.is_empty() {
+            return BvhNode::new(unbounded, max_leaf_size, time0, time1);
+        }
+        Self {
+            unbounded,
+            ..BvhNode::build(bounded, max_leaf_size, &|leaf| {
+                vec![Arc::new(PrimitiveList { objects: leaf }) as Arc<dyn Hittable>]
+            })
+        }
+    }
+    fn build<T>(
+        mut objects: Vec<(AABB, T)>,
+        max_leaf_size: usize,
+        make_leaf: &dyn Fn(Vec<T>) -> Vec<Arc<dyn Hittable>>,
+    ) -> Self {
+        let max_leaf_size = max_leaf_size.max(1);
 
         let objects_span = objects.len();
-        let mut left: Option<Arc<dyn Hittable>> = None;
-        let mut right: Option<Arc<dyn Hittable>> = None;
 
         if objects_span == 0 {
-            panic!("BvhNode::new_vec: Get empty vec");
-        }
-        if objects_span == 1 {
-            let obj0 = objects.pop().unwrap();
-            left = Some(obj0.clone());
-            right = Some(obj0);
-        } else if objects_span == 2 {
-            let obj0 = objects.pop().unwrap();
-            let obj1 = objects.pop().unwrap();
-            if BvhNode::box_compare(&obj0, &obj1, axis) == Ordering::Less {
-                left = Some(obj0);
-                right = Some(obj1);
-            } else {
-                left = Some(obj1);
-                right = Some(obj0);
-            }
-        } else {
+            panic!("BvhNode::new: Get empty vec");
+        }
+        if objects_span <= max_leaf_size {
+            let aabbox = objects
+                .iter()
+                .map(|(object_box, _)| *object_box)
+                .reduce(AABB::surrounding_box)
+                .unwrap();
+            return Self {
+                aabbox,
+                left: None,
+                right: None,
+                axis: 0,
+                leaf: make_leaf(objects.into_iter().map(|(_, object)| object).collect()),
+                unbounded: Vec::new(),
+                depth: 1,
+                node_count: 1,
+            };
+        }
+
+        let (axis, split) = if objects_span <= 2 {
+            let axis: usize = rand::thread_rng().gen_range(0..3);
             objects.sort_by(|a, b| BvhNode::box_compare(a, b, axis));
+            (axis, objects_span / 2)
+        } else {
+            BvhNode::sah_split(&mut objects)
+        };
 
-            let mut left_vec = objects;
-            let right_vec = left_vec.split_off(objects_span / 2);
+        let mut left_vec = objects;
+        let right_vec = left_vec.split_off(split);
 
-            left = Some(Arc::new(BvhNode::new_vec(left_vec, time0, time1)));
-            right = Some(Arc::new(BvhNode::new_vec(right_vec, time0, time1)));
+        let left = BvhNode::build(left_vec, max_leaf_size, make_leaf);
+        let right = BvhNode::build(right_vec, max_leaf_size, make_leaf);
+        let depth = 1 + left.depth.max(right.depth);
+        let node_count = 1 + left.node_count + right.node_count;
+        Self {
+            aabbox: AABB::surrounding_box(left.aabbox, right.aabbox),
+            left: Some(Arc::new(left)),
+            right: Some(Arc::new(right)),
+            axis,
+            leaf: Vec::new(),
+            unbounded: Vec::new(),
+            depth,
+            node_count,
         }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        !self.leaf.is_empty()
+    }
 
-        if let Some(left_box) = left.as_ref().unwrap().bounding_box(time0, time1) {
-            if let Some(right_box) = right.as_ref().unwrap().bounding_box(time0, time1) {
-                Self {
-                    aabbox: AABB::surrounding_box(left_box, right_box),
-                    left,
-                    right,
+    /// Levels in the tree, 1 for a single leaf.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    // `contains` for the tree below this node, skipping subtrees whose box
+    // leaves `p` out.
+    fn contains_bounded(&self, p: Point3) -> bool {
+        if !self.aabbox.contains(p) {
+            return false;
+        }
+        self.leaf.iter().any(|object| object.contains(p))
+            || self
+                .left
+                .iter()
+                .chain(self.right.iter())
+                .any(|child| child.contains_bounded(p))
+    }
+}
+
+impl Hittable for BvhNode {
+    /// Walks the tree with an explicit stack, nearer child first, skipping
+    /// nodes whose box the ray misses before the closest hit so far. Each
+    /// level pushes two and pops one, so `depth + 1` slots always suffice.
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<crate::hittable::HitRecord> {
+        let mut temp_rec = None;
+        let mut closest_so_far = t_max;
+        let mut stack: Vec<&BvhNode> = Vec::with_capacity(self.depth + 1);
+        stack.push(self);
+        while let Some(node) = stack.pop() {
+            if !node.aabbox.hit(r, t_min, closest_so_far) {
+                continue;
+            }
+            if node.is_leaf() {
+                for object in &node.leaf {
+                    if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                        closest_so_far = rec.t;
+                        temp_rec = Some(rec);
+                    }
                 }
+                continue;
+            }
+            let (left, right) = (node.left.as_ref().unwrap(), node.right.as_ref().unwrap());
+            let (near, far) = if r.dir[node.axis] < 0. {
+                (right, left)
             } else {
-                panic!("BvhNode::new_vec: No bounding box in bvh_node constructor.");
+                (left, right)
+            };
+            // Pushed last, popped first.
+            stack.push(far);
+            stack.push(near);
+        }
+        for object in &self.unbounded {
+            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                temp_rec = Some(rec);
             }
+        }
+        temp_rec
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        if self.unbounded.is_empty() {
+            Some(self.aabbox)
         } else {
-            panic!("BvhNode::new_vec: No bounding box in bvh_node constructor.");
+            None
+        }
+    }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        march_intervals(self, r, t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.unbounded.iter().any(|object| object.contains(p)) || self.contains_bounded(p)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        for child in self.left.iter().chain(self.right.iter()) {
+            child.count_primitives(counts);
+        }
+        for object in self.leaf.iter().chain(self.unbounded.iter()) {
+            object.count_primitives(counts);
         }
     }
 }
 
-impl Hittable for BvhNode {
-    #[allow(clippy::manual_map)]
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<crate::hittable::HitRecord> {
-        if !self.aabbox.hit(r, t_min, t_max) {
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{
+        sampler::SampleSequence,
+        vec::{Color, Vec3},
+    };
+    use crate::hittable::{sphere::Sphere, triangle::Triangle, HitRecord};
+    use crate::material::{Lambertian, MaterialHandle};
+    use rand::Rng;
+    use std::time::Instant;
+
+    /// The plain recursive walk the explicit stack replaced, as a reference.
+    fn hit_recursive(node: &BvhNode, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if !node.aabbox.hit(r, t_min, t_max) {
             return None;
         }
-        if let Some(recl) = self.left.as_ref().unwrap().hit(r, t_min, t_max) {
-            if let Some(recr) = self.right.as_ref().unwrap().hit(r, t_min, recl.t) {
-                Some(recr)
-            } else {
-                Some(recl)
+        let mut temp_rec = None;
+        let mut closest_so_far = t_max;
+        for object in &node.leaf {
+            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                temp_rec = Some(rec);
+            }
+        }
+        for child in node.left.iter().chain(node.right.iter()) {
+            if let Some(rec) = hit_recursive(child, r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                temp_rec = Some(rec);
             }
-        } else if let Some(recr) = self.right.as_ref().unwrap().hit(r, t_min, t_max) {
-            Some(recr)
-        } else {
-            None
         }
+        temp_rec
     }
-    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
-        Some(self.aabbox)
+
+    /// A bumpy `n` by `n` grid, two triangles per cell, over [0, n]².
+    fn height_field(n: usize) -> HittableList {
+        let gray = MaterialHandle::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let height = |i: usize, j: usize| ((i as f64 * 0.3).sin() + (j as f64 * 0.2).cos()) * 2.;
+        let vertex = |i: usize, j: usize| Point3::new(i as f64, height(i, j), j as f64);
+        let mut list = HittableList::default();
+        for i in 0..n {
+            for j in 0..n {
+                let (near, right) = (vertex(i, j), vertex(i + 1, j));
+                let (up, far) = (vertex(i, j + 1), vertex(i + 1, j + 1));
+                list.add(Arc::new(Triangle::new(near, right, far, gray.clone())));
+                list.add(Arc::new(Triangle::new(near, far, up, gray.clone())));
+            }
+        }
+        list
+    }
+
+    #[test]
+    fn lopsided_trees_hit_like_the_list() {
+        // Each sphere twice the size of the last, so the surface area
+        // heuristic only ever splits off the few largest.
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut list = HittableList::default();
+        for i in 0..400 {
+            let x = 2f64.powi(i);
+            list.add(Arc::new(Sphere::new(
+                Point3::new(x, 0., 0.),
+                x / 4.,
+                gray.clone(),
+            )));
+        }
+        let bvh = BvhNode::new(list.objects.clone(), 1, 0., 1.);
+        assert!(bvh.depth() > 64, "depth {}", bvh.depth());
+
+        for k in [0, 3, 20, 100, 250, 398].iter() {
+            let between = Point3::new(1.4 * 2f64.powi(*k), 0., 0.);
+            for &dir in [Vec3::new(-1., 0., 0.), Vec3::new(1., 0., 0.)].iter() {
+                let r = Ray::new(between, dir, 0.);
+                let expected = list.hit(r, 0., f64::INFINITY).unwrap().t;
+                let actual = bvh.hit(r, 0., f64::INFINITY).unwrap().t;
+                assert!((actual - expected).abs() <= 1e-9 * expected, "{}", k);
+            }
+        }
+    }
+
+    /// `cargo test --release -- --ignored --nocapture` prints the time per ray
+    /// of `hit` and of the recursive walk on a 100k triangle mesh.
+    #[test]
+    #[ignore]
+    fn bench_stack_walk_against_recursion() {
+        let n = 224;
+        let list = height_field(n);
+        assert!(list.objects.len() > 100_000);
+        let bvh = BvhNode::new_list(&list, 0., 1.);
+
+        let mut rng = SampleSequence::new(0, 0, 0, 3);
+        let size = n as f64;
+        let rays: Vec<Ray> = (0..1_000_000)
+            .map(|_| {
+                let from = Point3::new(rng.gen_range(0.0..size), 10., rng.gen_range(0.0..size));
+                let to = Point3::new(rng.gen_range(0.0..size), 0., rng.gen_range(0.0..size));
+                Ray::new(from, to - from, 0.)
+            })
+            .collect();
+        let walks: [(&str, &dyn Fn(Ray) -> bool); 2] = [
+            ("stack", &|r| bvh.hit(r, 0.001, f64::INFINITY).is_some()),
+            ("recursive", &|r| {
+                hit_recursive(&bvh, r, 0.001, f64::INFINITY).is_some()
+            }),
+        ];
+        for (name, walk) in walks.iter() {
+            let start = Instant::now();
+            let hits = rays.iter().filter(|r| walk(**r)).count();
+            println!(
+                "{}: {:.1} ns/ray, {} hits, depth {}",
+                name,
+                start.elapsed().as_nanos() as f64 / rays.len() as f64,
+                hits,
+                bvh.depth()
+            );
+        }
+    }
+
+    #[test]
+    fn stack_walk_matches_recursion_on_a_mesh() {
+        let list = height_field(20);
+        let bvh = BvhNode::new_list(&list, 0., 1.);
+        let mut rng = SampleSequence::new(0, 0, 0, 4);
+        for _ in 0..500 {
+            let from = Point3::new(rng.gen_range(0.0..20.), 10., rng.gen_range(0.0..20.));
+            let to = Point3::new(rng.gen_range(0.0..20.), 0., rng.gen_range(0.0..20.));
+            let r = Ray::new(from, to - from, 0.);
+            let expected = hit_recursive(&bvh, r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            let actual = bvh.hit(r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            assert_eq!(expected.is_some(), actual.is_some());
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                assert!((expected - actual).abs() < 1e-12);
+            }
+        }
+    }
+
+    /// A tree split at the centroid median of the widest axis, the usual
+    /// alternative to the surface area heuristic.
+    #[cfg(feature = "profile")]
+    fn median_tree(mut objects: Vec<(AABB, Arc<dyn Hittable>)>) -> BvhNode {
+        let aabbox = objects
+            .iter()
+            .map(|(object_box, _)| *object_box)
+            .reduce(AABB::surrounding_box)
+            .unwrap();
+        if objects.len() <= DEFAULT_MAX_LEAF_SIZE {
+            return BvhNode {
+                aabbox,
+                left: None,
+                right: None,
+                axis: 0,
+                leaf: objects.into_iter().map(|(_, object)| object).collect(),
+                unbounded: Vec::new(),
+                depth: 1,
+                node_count: 1,
+            };
+        }
+        let extent = aabbox.max - aabbox.min;
+        let axis = (0..3)
+            .max_by(|&a, &b| extent[a].partial_cmp(&extent[b]).unwrap())
+            .unwrap();
+        objects.sort_by(|a, b| BvhNode::centroid_compare(a, b, axis));
+        let right = objects.split_off(objects.len() / 2);
+        let (left, right) = (median_tree(objects), median_tree(right));
+        BvhNode {
+            aabbox,
+            depth: 1 + left.depth.max(right.depth),
+            node_count: 1 + left.node_count + right.node_count,
+            left: Some(Arc::new(left)),
+            right: Some(Arc::new(right)),
+            axis,
+            leaf: Vec::new(),
+            unbounded: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "profile")]
+    #[test]
+    fn sah_trees_test_fewer_spheres_than_median_splits() {
+        use crate::hittable::profile::{profile_list, RenderStats};
+
+        // Mostly small spheres with a few large ones, where splitting by
+        // count alone leaves big overlapping boxes.
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut rng = SampleSequence::new(0, 0, 0, 5);
+        let mut list = HittableList::default();
+        for i in 0..10_000 {
+            let radius = if i % 100 == 0 { 5. } else { 0.2 };
+            list.add(Arc::new(Sphere::new(
+                Vec3::random_range(&mut rng, -50., 50.),
+                radius,
+                gray.clone(),
+            )));
+        }
+        let rays: Vec<Ray> = (0..20_000)
+            .map(|_| {
+                let origin = Vec3::random_range(&mut rng, -60., 60.);
+                Ray::new(origin, Vec3::random_range(&mut rng, -1., 1.), 0.)
+            })
+            .collect();
+
+        let mut calls = Vec::new();
+        for sah in [true, false].iter() {
+            let stats = RenderStats::new();
+            let profiled = profile_list(&list, &stats);
+            let tree = if *sah {
+                BvhNode::new_list(&profiled, 0., 1.)
+            } else {
+                let boxed = profiled.objects.iter();
+                median_tree(
+                    boxed
+                        .map(|object| (object.bounding_box(0., 1.).unwrap(), object.clone()))
+                        .collect(),
+                )
+            };
+            let hits = rays
+                .iter()
+                .filter(|r| tree.hit(**r, 0.001, f64::INFINITY).is_some())
+                .count();
+            calls.push((stats.totals()["Sphere"].calls, hits));
+        }
+        let ((sah_calls, sah_hits), (median_calls, median_hits)) = (calls[0], calls[1]);
+        println!("sphere tests: SAH {}, median {}", sah_calls, median_calls);
+        assert_eq!(sah_hits, median_hits);
+        assert!(
+            sah_calls < median_calls,
+            "{} vs {}",
+            sah_calls,
+            median_calls
+        );
+    }
+
+    fn leaf_sizes(node: &BvhNode, sizes: &mut Vec<usize>) {
+        if node.is_leaf() {
+            sizes.push(node.leaf.len());
+        }
+        for child in node.left.iter().chain(node.right.iter()) {
+            leaf_sizes(child, sizes);
+        }
+    }
+
+    fn random_spheres(n: usize, seed: u64) -> HittableList {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut rng = SampleSequence::new(0, 0, 0, seed);
+        let mut list = HittableList::default();
+        for _ in 0..n {
+            list.add(Arc::new(Sphere::new(
+                Vec3::random_range(&mut rng, -10., 10.),
+                rng.gen_range(0.2..1.),
+                gray.clone(),
+            )));
+        }
+        list
+    }
+
+    #[test]
+    fn leaves_stay_within_max_leaf_size() {
+        let list = random_spheres(20, 6);
+        let bvh = BvhNode::new(list.objects.clone(), 4, 0., 1.);
+        let mut sizes = Vec::new();
+        leaf_sizes(&bvh, &mut sizes);
+        assert!(
+            sizes.iter().all(|&size| (1..=4).contains(&size)),
+            "{:?}",
+            sizes
+        );
+        assert_eq!(sizes.iter().sum::<usize>(), 20);
+        assert!(bvh.node_count() < 20);
+
+        let mut rng = SampleSequence::new(1, 0, 0, 6);
+        for _ in 0..1000 {
+            let origin = Vec3::random_range(&mut rng, -12., 12.);
+            let r = Ray::new(origin, Vec3::random_range(&mut rng, -1., 1.), 0.);
+            let expected = list.hit(r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            let actual = bvh.hit(r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    /// `cargo test --release -- --ignored --nocapture` prints the time per ray
+    /// for each leaf size, the measurement behind `DEFAULT_MAX_LEAF_SIZE`.
+    #[test]
+    #[ignore]
+    fn bench_max_leaf_sizes() {
+        for &n in [1_000, 100_000].iter() {
+            let list = random_spheres(n, 8);
+            let mut rng = SampleSequence::new(2, 0, 0, 8);
+            let rays: Vec<Ray> = (0..500_000)
+                .map(|_| {
+                    let origin = Vec3::random_range(&mut rng, -12., 12.);
+                    Ray::new(origin, Vec3::random_range(&mut rng, -1., 1.), 0.)
+                })
+                .collect();
+            for max_leaf_size in 1..=8 {
+                let bvh = BvhNode::new(list.objects.clone(), max_leaf_size, 0., 1.);
+                let start = Instant::now();
+                let hits = rays
+                    .iter()
+                    .filter(|r| bvh.hit(**r, 0.001, f64::INFINITY).is_some())
+                    .count();
+                println!(
+                    "{} spheres, leaf size {}: {:.1} ns/ray, {} hits",
+                    n,
+                    max_leaf_size,
+                    start.elapsed().as_nanos() as f64 / rays.len() as f64,
+                    hits
+                );
+            }
+        }
     }
 }
@@ -4,6 +4,7 @@ use rand::Rng;
 
 use crate::{
     basic::ray::Ray,
+    basic::sampler,
     basic::vec::{Color, Vec3},
     hittable::bvh::aabb::AABB,
     hittable::{HitRecord, Hittable},
@@ -47,38 +48,32 @@ impl<H: Hittable, T: Texture + Copy + Clone> Hittable for ConstantMedium<H, T> {
         self.boundary.bounding_box(time0, time1)
     }
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<crate::hittable::HitRecord> {
-        if let Some(mut rec1) = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY) {
-            if let Some(mut rec2) = self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY) {
-                rec1.t = rec1.t.max(t_min);
-                rec2.t = rec2.t.min(t_max);
-                if rec1.t >= rec2.t {
-                    return None;
-                }
-                rec1.t = rec1.t.max(0.);
-                let ray_length = r.dir.length();
-                let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let rnd: f64 = rand::thread_rng().gen();
-                let hit_distance: f64 = self.neg_inv_density * rnd.log(E);
-                if hit_distance > distance_inside_boundary {
-                    return None;
-                }
+        let ray_length = r.dir.length();
+        let rnd: f64 = sampler::rng().gen();
+        let mut hit_distance: f64 = self.neg_inv_density * rnd.log(E);
 
+        // The free path is memoryless, so it can be spent across every segment
+        // of the boundary the ray passes through.
+        for (t0, t1) in self.boundary.hit_intervals(r, t_min, t_max) {
+            let t0 = t0.max(0.);
+            if t0 >= t1 {
+                continue;
+            }
+            let distance_inside_boundary = (t1 - t0) * ray_length;
+            if hit_distance <= distance_inside_boundary {
                 let rec = HitRecord::new(
-                    r.at(rec1.t + hit_distance / ray_length),
+                    r.at(t0 + hit_distance / ray_length),
                     Vec3::new(1., 0., 0.),
-                    rec1.t + hit_distance / ray_length,
+                    t0 + hit_distance / ray_length,
                     0.,
                     0.,
                     true,
                     &self.phase_function,
                 );
-
-                Some(rec)
-            } else {
-                None
+                return Some(rec);
             }
-        } else {
-            None
+            hit_distance -= distance_inside_boundary;
         }
+        None
     }
 }
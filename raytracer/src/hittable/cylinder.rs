@@ -0,0 +1,191 @@
+use std::f64::consts::PI;
+
+use crate::{
+    basic::ray::Ray,
+    basic::{
+        onb::Onb,
+        vec::{Point3, Vec3},
+    },
+    hittable::bvh::aabb::AABB,
+    hittable::sphere::quadratic_roots,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+};
+
+/// Cylinder from `p0` to `p1`, open unless `caps`. On the side `u` goes
+/// around and `v` from `p0` to `p1`; on the caps `v` goes out from the axis.
+pub struct Cylinder<M>
+where
+    M: Material,
+{
+    p0: Point3,
+    p1: Point3,
+    radius: f64,
+    caps: bool,
+    uvw: Onb,
+    mp: M,
+}
+
+impl<M: Material> Cylinder<M> {
+    #[allow(dead_code)]
+    pub fn new(p0: Point3, p1: Point3, radius: f64, caps: bool, mp: M) -> Self {
+        Self {
+            p0,
+            p1,
+            radius,
+            caps,
+            uvw: Onb::build_from_w(p1 - p0),
+            mp,
+        }
+    }
+
+    fn height(&self) -> f64 {
+        (self.p1 - self.p0).length()
+    }
+
+    /// Angle around the axis of `offset`, taken from a point on the axis.
+    fn around(&self, offset: Vec3) -> f64 {
+        let along_u = Vec3::dot(offset, self.uvw.u());
+        let along_v = Vec3::dot(offset, self.uvw.v());
+        (f64::atan2(along_v, along_u) + PI) / (2. * PI)
+    }
+
+    /// Nearest side hit within `[t_min, t_max]` and its height along the axis.
+    fn side_root(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let axis = self.uvw.w();
+        let from_base = r.orig - self.p0;
+        // Only the components perpendicular to the axis matter.
+        let dir_across = r.dir - axis * Vec3::dot(r.dir, axis);
+        let orig_across = from_base - axis * Vec3::dot(from_base, axis);
+        let a = dir_across.length_squared();
+        if a < 1e-12 {
+            return None;
+        }
+        let half_b = Vec3::dot(orig_across, dir_across);
+        let c = orig_across.length_squared() - self.radius * self.radius;
+        let discriminant = half_b.powi(2) - a * c;
+        if discriminant < 0. {
+            return None;
+        }
+        let (near, far) = quadratic_roots(a, half_b, c, discriminant);
+        let height = self.height();
+        [near, far].iter().find_map(|&t| {
+            let along = Vec3::dot(from_base + r.dir * t, axis);
+            if t_min <= t && t <= t_max && (0. ..=height).contains(&along) {
+                Some((t, along))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl<M: Material> Hittable for Cylinder<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let axis = self.uvw.w();
+        let mut closest = t_max;
+        let mut rec = None;
+
+        if let Some((t, along)) = self.side_root(r, t_min, closest) {
+            let p = r.at(t);
+            let outward_normal = (p - self.p0 - axis * along) / self.radius;
+            let (u, height) = (self.around(outward_normal), along / self.height());
+            let mut side = HitRecord::new(p, outward_normal, t, u, height, true, &self.mp);
+            side.set_face_normal(r, outward_normal);
+            closest = t;
+            rec = Some(side);
+        }
+
+        let denom = Vec3::dot(r.dir, axis);
+        if self.caps && denom.abs() > 1e-12 {
+            for &(center, outward_normal) in &[(self.p0, -axis), (self.p1, axis)] {
+                let t = Vec3::dot(center - r.orig, axis) / denom;
+                if t < t_min || t > closest {
+                    continue;
+                }
+                let p = r.at(t);
+                let offset = p - center;
+                if offset.length_squared() > self.radius * self.radius {
+                    continue;
+                }
+                let (u, out) = (self.around(offset), offset.length() / self.radius);
+                let mut cap = HitRecord::new(p, outward_normal, t, u, out, true, &self.mp);
+                cap.set_face_normal(r, outward_normal);
+                closest = t;
+                rec = Some(cap);
+            }
+        }
+        rec
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        let small = Point3::new(
+            self.p0.x.min(self.p1.x),
+            self.p0.y.min(self.p1.y),
+            self.p0.z.min(self.p1.z),
+        );
+        let large = Point3::new(
+            self.p0.x.max(self.p1.x),
+            self.p0.y.max(self.p1.y),
+            self.p0.z.max(self.p1.z),
+        );
+        Some(AABB::new(small - radius, large + radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec::Color;
+    use crate::material::Lambertian;
+
+    /// Radius 1, from y = 0 to y = 2.
+    fn upright(caps: bool) -> Cylinder<Lambertian<crate::texture::SolidColor>> {
+        Cylinder::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 2., 0.),
+            1.,
+            caps,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )
+    }
+
+    #[test]
+    fn side_hits_face_out_from_the_axis() {
+        let r = Ray::new(Point3::new(5., 0.5, 0.), Vec3::new(-1., 0., 0.), 0.);
+        let (closed, open) = (upright(true), upright(false));
+        let rec = closed.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.).abs() < 1e-12);
+        assert!(Vec3::distance(rec.normal, Vec3::new(1., 0., 0.)) < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.v - 0.25).abs() < 1e-12);
+
+        // Above the top the side is missed, and without caps so is the rest.
+        let over = Ray::new(Point3::new(5., 2.5, 0.), Vec3::new(-1., 0., 0.), 0.);
+        assert!(closed.hit(over, 0.001, f64::INFINITY).is_none());
+        assert!(open.hit(over, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn caps_close_the_ends() {
+        let (closed, open) = (upright(true), upright(false));
+        let down = Ray::new(Point3::new(0.3, 5., 0.2), Vec3::new(0., -1., 0.), 0.);
+        let top = closed.hit(down, 0.001, f64::INFINITY).unwrap();
+        assert!((top.t - 3.).abs() < 1e-12);
+        assert!(Vec3::distance(top.normal, Vec3::new(0., 1., 0.)) < 1e-12);
+        assert!(top.front_face);
+
+        let up = Ray::new(Point3::new(0.3, -5., 0.2), Vec3::new(0., 1., 0.), 0.);
+        let bottom = closed.hit(up, 0.001, f64::INFINITY).unwrap();
+        assert!((bottom.t - 5.).abs() < 1e-12);
+        assert!(Vec3::distance(bottom.normal, Vec3::new(0., -1., 0.)) < 1e-12);
+        assert!(bottom.front_face);
+
+        // Open tubes let rays along the axis straight through.
+        assert!(open.hit(down, 0.001, f64::INFINITY).is_none());
+        // Outside the cap's radius.
+        let beside = Ray::new(Point3::new(1.5, 5., 0.), Vec3::new(0., -1., 0.), 0.);
+        assert!(closed.hit(beside, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,147 @@
+use std::f64::consts::PI;
+use std::f64::INFINITY;
+
+use rand::{Rng, RngCore};
+
+use crate::{
+    basic::ray::Ray,
+    basic::{
+        onb::Onb,
+        vec::{Point3, Vec3},
+    },
+    hittable::bvh::aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+};
+
+/// Flat circle facing `normal`. `u` goes around it, `v` outwards from the
+/// center.
+pub struct Disk<M>
+where
+    M: Material,
+{
+    center: Point3,
+    radius: f64,
+    uvw: Onb,
+    mp: M,
+}
+
+impl<M: Material> Disk<M> {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mp: M) -> Self {
+        Self {
+            center,
+            radius,
+            uvw: Onb::build_from_w(normal),
+            mp,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Disk<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let normal = self.uvw.w();
+        let denom = Vec3::dot(normal, r.dir);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = Vec3::dot(self.center - r.orig, normal) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let point = r.at(t);
+        let offset = point - self.center;
+        if offset.length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        let along_u = Vec3::dot(offset, self.uvw.u());
+        let along_v = Vec3::dot(offset, self.uvw.v());
+        let u = (f64::atan2(along_v, along_u) + PI) / (2. * PI);
+        let v = offset.length() / self.radius;
+        let mut rec = HitRecord::new(point, normal, t, u, v, true, &self.mp);
+        rec.set_face_normal(r, normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        // Along each axis the rim reaches radius * sin of the angle to the normal.
+        let normal = self.uvw.w();
+        let reach = |along: f64| self.radius * (1. - along * along).max(0.).sqrt() + 0.0001;
+        let extent = Vec3::new(reach(normal.x), reach(normal.y), reach(normal.z));
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(Ray::new(o, v, time), 0.001, INFINITY) {
+            let area = PI * self.radius * self.radius;
+            let dis_sqr = rec.t * rec.t * v.length_squared();
+            let cos = (Vec3::dot(v, rec.normal) / v.length()).abs();
+            dis_sqr / (cos * area)
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, origin: Point3, _time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        let radial = self.radius * rng.gen::<f64>().sqrt();
+        let phi = 2. * PI * rng.gen::<f64>();
+        self.center + self.uvw.local(radial * phi.cos(), radial * phi.sin(), 0.) - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec::Color;
+    use crate::material::Lambertian;
+
+    fn disk() -> Disk<Lambertian<crate::texture::SolidColor>> {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        Disk::new(Point3::new(1., 2., 3.), Vec3::new(0., 0., 2.), 0.5, gray)
+    }
+
+    #[test]
+    fn rays_inside_the_rim_hit() {
+        let disk = disk();
+        let down = Vec3::new(0., 0., -1.);
+        let rec = disk
+            .hit(Ray::new(Point3::new(1., 2., 5.), down, 0.), 0.001, INFINITY)
+            .unwrap();
+        assert!((rec.t - 2.).abs() < 1e-12 && rec.v.abs() < 1e-12);
+        assert!((rec.normal - Vec3::new(0., 0., 1.)).length() < 1e-12);
+        let rec = disk
+            .hit(
+                Ray::new(Point3::new(1.3, 2.3, 5.), down, 0.),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert!((rec.v - 0.18f64.sqrt() / 0.5).abs() < 1e-12);
+
+        // From behind, the normal faces the ray.
+        let up = Ray::new(Point3::new(1.2, 2., 0.), Vec3::new(0., 0., 1.), 0.);
+        let rec = disk.hit(up, 0.001, INFINITY).unwrap();
+        assert!(!rec.front_face && rec.normal.z < 0.);
+    }
+
+    #[test]
+    fn rays_past_the_rim_or_along_the_plane_miss() {
+        let disk = disk();
+        let down = Vec3::new(0., 0., -1.);
+        assert!(disk
+            .hit(
+                Ray::new(Point3::new(1.4, 2.4, 5.), down, 0.),
+                0.001,
+                INFINITY
+            )
+            .is_none());
+        let along = Ray::new(Point3::new(0., 2., 3.), Vec3::new(1., 0., 0.), 0.);
+        assert!(disk.hit(along, 0.001, INFINITY).is_none());
+        // Behind the ray, or past t_max.
+        let away = Ray::new(Point3::new(1., 2., 5.), -down, 0.);
+        assert!(disk.hit(away, 0.001, INFINITY).is_none());
+        assert!(disk
+            .hit(Ray::new(Point3::new(1., 2., 5.), down, 0.), 0.001, 1.5)
+            .is_none());
+    }
+}
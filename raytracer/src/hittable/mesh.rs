@@ -0,0 +1,337 @@
+use std::{collections::HashMap, f64::INFINITY, fs, sync::Arc};
+
+use rand::{Rng, RngCore};
+
+use crate::{
+    basic::{
+        ray::Ray,
+        vec::{Point3, Vec3},
+    },
+    material::{Material, MaterialHandle},
+};
+
+use super::{bvh::aabb::AABB, triangle::Triangle, HitRecord, Hittable, HittableList};
+
+/// Triangles indexing into one shared vertex buffer.
+#[derive(Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    // One per position, when the mesh has smooth shading.
+    pub normals: Option<Vec<Vec3>>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    /// Merges vertices closer than `epsilon` and points the faces at the
+    /// survivors, dropping faces that collapse. Normals are averaged over the
+    /// merged vertices, or computed from the faces around each vertex if the
+    /// mesh has none.
+    pub fn weld(&self, epsilon: f64) -> Mesh {
+        let cell = |p: Point3| {
+            (
+                (p.x / epsilon).floor() as i64,
+                (p.y / epsilon).floor() as i64,
+                (p.z / epsilon).floor() as i64,
+            )
+        };
+        let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut normal_sums = Vec::new();
+        let mut remap = Vec::with_capacity(self.positions.len());
+        for (i, &p) in self.positions.iter().enumerate() {
+            let (cx, cy, cz) = cell(p);
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = grid.get(&(cx + dx, cy + dy, cz + dz)) {
+                            for &j in candidates {
+                                if Vec3::distance(positions[j], p) <= epsilon {
+                                    found = Some(j);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            let j = match found {
+                Some(j) => j,
+                None => {
+                    positions.push(p);
+                    normal_sums.push(Vec3::new(0., 0., 0.));
+                    grid.entry((cx, cy, cz))
+                        .or_insert_with(Vec::new)
+                        .push(positions.len() - 1);
+                    positions.len() - 1
+                }
+            };
+            if let Some(normals) = &self.normals {
+                normal_sums[j] += normals[i];
+            }
+            remap.push(j);
+        }
+
+        let faces: Vec<[usize; 3]> = self
+            .faces
+            .iter()
+            .map(|f| [remap[f[0]], remap[f[1]], remap[f[2]]])
+            .filter(|f| f[0] != f[1] && f[1] != f[2] && f[2] != f[0])
+            .collect();
+
+        if self.normals.is_none() {
+            // Area weighted, since the cross product's length is twice the area.
+            for f in &faces {
+                let n = Vec3::cross(
+                    positions[f[1]] - positions[f[0]],
+                    positions[f[2]] - positions[f[0]],
+                );
+                for &v in f {
+                    normal_sums[v] += n;
+                }
+            }
+        }
+        let normals = normal_sums
+            .into_iter()
+            .map(|n| {
+                if n.length_squared() > 0. {
+                    n.to_unit()
+                } else {
+                    n
+                }
+            })
+            .collect();
+
+        Mesh {
+            positions,
+            normals: Some(normals),
+            faces,
+        }
+    }
+
+    fn corners(&self, face: usize) -> (Point3, Point3, Point3) {
+        let [a, b, c] = self.faces[face];
+        (self.positions[a], self.positions[b], self.positions[c])
+    }
+}
+
+/// One face of a `Mesh`, with its normals interpolated when the mesh has them.
+pub struct MeshTriangle<M>
+where
+    M: Material,
+{
+    mesh: Arc<Mesh>,
+    face: usize,
+    mp: M,
+}
+
+impl<M: Material> MeshTriangle<M> {
+    pub fn new(mesh: Arc<Mesh>, face: usize, mp: M) -> Self {
+        Self { mesh, face, mp }
+    }
+    pub fn area(&self) -> f64 {
+        let (a, b, c) = self.mesh.corners(self.face);
+        Vec3::cross(b - a, c - a).length() / 2.
+    }
+}
+
+/// Every face of `mesh` as a `MeshTriangle` sharing material `mp`.
+pub fn triangles<M>(mesh: Arc<Mesh>, mp: M) -> HittableList
+where
+    M: Material + Clone + 'static,
+{
+    let mut list = HittableList::default();
+    for face in 0..mesh.faces.len() {
+        list.add(Arc::new(MeshTriangle::new(mesh.clone(), face, mp.clone())));
+    }
+    list
+}
+
+impl<M: Material> Hittable for MeshTriangle<M> {
+    #[allow(clippy::many_single_char_names)]
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (a, b, c) = self.mesh.corners(self.face);
+        let e1 = b - a;
+        let e2 = c - a;
+        let pvec = Vec3::cross(r.dir, e2);
+        let det = Vec3::dot(e1, pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let tvec = r.orig - a;
+        let u = Vec3::dot(tvec, pvec) * inv_det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+        let qvec = Vec3::cross(tvec, e1);
+        let v = Vec3::dot(r.dir, qvec) * inv_det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+        let t = Vec3::dot(e2, qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let n = Vec3::cross(e1, e2).to_unit();
+        let mut rec = HitRecord::new(r.at(t), n, t, u, v, true, &self.mp);
+        rec.set_face_normal(r, n);
+        rec.set_tangents(e1, e2);
+        if let Some(normals) = &self.mesh.normals {
+            let [ia, ib, ic] = self.mesh.faces[self.face];
+            let shading = normals[ia] * (1. - u - v) + normals[ib] * u + normals[ic] * v;
+            if shading.length_squared() > 0. {
+                rec.set_shading_normal(shading.to_unit());
+            }
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let (a, b, c) = self.mesh.corners(self.face);
+        Some(AABB::new(
+            Point3::new(
+                a.x.min(b.x.min(c.x)),
+                a.y.min(b.y.min(c.y)),
+                a.z.min(b.z.min(c.z)),
+            ),
+            Point3::new(
+                a.x.max(b.x.max(c.x)),
+                a.y.max(b.y.max(c.y)),
+                a.z.max(b.z.max(c.z)),
+            ),
+        ))
+    }
+
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(Ray::new(o, v, time), 0.001, INFINITY) {
+            let dis_sqr = rec.t * rec.t * v.length_squared();
+            let cos = (Vec3::dot(v, rec.geometric_normal) / v.length()).abs();
+            dis_sqr / (cos * self.area())
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, o: Point3, _time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        let (a, b, c) = self.mesh.corners(self.face);
+        let (mut r1, mut r2): (f64, f64) = (rng.gen(), rng.gen());
+        if r1 + r2 > 1. {
+            r1 = 1. - r1;
+            r2 = 1. - r2;
+        }
+        a + (b - a) * r1 + (c - a) * r2 - o
+    }
+}
+
+/// Reads the `v` and `f` lines of a Wavefront OBJ into triangles of material
+/// `mat`. Polygons are split into a fan around their first corner; texture and
+/// normal indices (`v/vt/vn`) and every other directive are ignored.
+#[allow(dead_code)]
+pub fn load_obj(path: &str, mat: Arc<dyn Material>) -> Result<HittableList, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mat = MaterialHandle::from_arc(mat);
+    let mut vertices: Vec<Point3> = Vec::new();
+    let mut list = HittableList::default();
+    for (line_number, line) in text.lines().enumerate() {
+        let error = |what: &str| format!("{}:{}: {}", path, line_number + 1, what);
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut coords = [0.; 3];
+                for coord in coords.iter_mut() {
+                    *coord = tokens
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .ok_or_else(|| error("bad vertex"))?;
+                }
+                vertices.push(Point3::new(coords[0], coords[1], coords[2]));
+            }
+            Some("f") => {
+                let mut corners = Vec::new();
+                for token in tokens {
+                    let index: i64 = token
+                        .split('/')
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .ok_or_else(|| error("bad face index"))?;
+                    // 1-based, or negative to count back from the last vertex.
+                    let index = if index < 0 {
+                        vertices.len() as i64 + index
+                    } else {
+                        index - 1
+                    };
+                    if index < 0 || index as usize >= vertices.len() {
+                        return Err(error("face index out of range"));
+                    }
+                    corners.push(vertices[index as usize]);
+                }
+                if corners.len() < 3 {
+                    return Err(error("face with fewer than 3 vertices"));
+                }
+                for i in 1..corners.len() - 1 {
+                    list.add(Arc::new(Triangle::new(
+                        corners[0],
+                        corners[i],
+                        corners[i + 1],
+                        mat.clone(),
+                    )));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit cube centred on the origin, each face with its own four
+    /// corners and flat normals, as OBJ exporters write hard edges.
+    fn split_cube() -> Mesh {
+        let mut mesh = Mesh {
+            normals: Some(Vec::new()),
+            ..Default::default()
+        };
+        for axis in 0..3 {
+            for &sign in [-1., 1.].iter() {
+                let mut normal = Vec3::new(0., 0., 0.);
+                normal[axis] = sign;
+                let (s_axis, t_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+                let base = mesh.positions.len();
+                for &(s, t) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].iter() {
+                    let mut corner = normal * 0.5;
+                    corner[s_axis] = s;
+                    corner[t_axis] = t;
+                    mesh.positions.push(corner);
+                    mesh.normals.as_mut().unwrap().push(normal);
+                }
+                mesh.faces.push([base, base + 1, base + 2]);
+                mesh.faces.push([base, base + 2, base + 3]);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn welding_a_split_cube_shares_its_corners() {
+        let cube = split_cube();
+        assert_eq!(cube.positions.len(), 24);
+        let welded = cube.weld(1e-6);
+        assert_eq!(welded.positions.len(), 8);
+        assert_eq!(welded.faces.len(), 12);
+
+        // Each corner averages its three faces' normals, pointing out of it.
+        let normals = welded.normals.as_ref().unwrap();
+        for (p, n) in welded.positions.iter().zip(normals) {
+            assert!(Vec3::distance(*n, p.to_unit()) < 1e-12);
+        }
+        for face in &welded.faces {
+            assert!(face.iter().all(|&v| v < 8));
+        }
+    }
+}
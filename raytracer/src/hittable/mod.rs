@@ -2,12 +2,20 @@ pub mod aarect;
 pub mod boxes;
 pub mod bvh;
 pub mod constantmedium;
+pub mod cylinder;
+pub mod disk;
+pub mod mesh;
+pub mod plane;
+pub mod primitive;
+#[cfg(feature = "profile")]
+pub mod profile;
 pub mod sphere;
 pub mod triangle;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use super::basic::ray::Ray;
 use super::basic::vec::{Point3, Vec3};
@@ -16,10 +24,16 @@ use super::material::Material;
 
 pub struct HitRecord<'a> {
     pub p: Point3,
+    // Shading normal, may be perturbed (e.g. by a normal map).
     pub normal: Vec3,
+    // Normal of the actual surface, always on the same side as `normal`.
+    pub geometric_normal: Vec3,
     pub t: f64,
     pub u: f64,
     pub v: f64,
+    // dp/du and dp/dv, unnormalized; zero where the shape doesn't give them.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub front_face: bool,
     pub mat_ptr: &'a dyn Material,
 }
@@ -37,14 +51,23 @@ impl<'a> HitRecord<'a> {
         Self {
             p,
             normal,
+            geometric_normal: normal,
             t,
             u,
             v,
+            tangent: Vec3::new(0., 0., 0.),
+            bitangent: Vec3::new(0., 0., 0.),
             front_face,
             mat_ptr,
         }
     }
 
+    /// Records how the hit point moves with `u` and `v`, for tangent space.
+    pub fn set_tangents(&mut self, dpdu: Vec3, dpdv: Vec3) {
+        self.tangent = dpdu;
+        self.bitangent = dpdv;
+    }
+
     pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
         self.front_face = Vec3::dot(r.dir, outward_normal) < 0.;
         self.normal = if self.front_face {
@@ -52,39 +75,149 @@ impl<'a> HitRecord<'a> {
         } else {
             -outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+
+    /// World-space distance from `origin` to the hit point. Unlike `t`, it
+    /// does not depend on the length of the ray direction.
+    pub fn distance_from(&self, origin: Point3) -> f64 {
+        (self.p - origin).length()
+    }
+
+    /// Replaces the shading normal, keeping it on the geometric normal's side.
+    pub fn set_shading_normal(&mut self, normal: Vec3) {
+        self.normal = if Vec3::dot(normal, self.geometric_normal) < 0. {
+            -normal
+        } else {
+            normal
+        };
     }
 }
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB>;
-    fn pdf_value(&self, _o: Point3, _v: Vec3) -> f64 {
+    /// Density, in solid angle from `o`, of `random` picking direction `v` for
+    /// a ray at `time`.
+    fn pdf_value(&self, _o: Point3, _v: Vec3, _time: f64) -> f64 {
         0.
     }
-    fn random(&self, _o: Point3) -> Vec3 {
+    fn random(&self, _o: Point3, _time: f64, _rng: &mut dyn RngCore) -> Vec3 {
         Vec3::new(1., 0., 0.)
     }
+    /// Whether `p` lies strictly inside this closed object. Open surfaces,
+    /// and objects with no cheap analytic test, say no.
+    fn contains(&self, _p: Point3) -> bool {
+        false
+    }
+    /// Parameter ranges of `r` inside this (closed) object, clipped to
+    /// `[t_min, t_max]`. The default assumes a convex object, entered and left
+    /// at most once.
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        if let Some(rec1) = self.hit(r, f64::NEG_INFINITY, f64::INFINITY) {
+            if let Some(rec2) = self.hit(r, rec1.t + 0.0001, f64::INFINITY) {
+                let t0 = rec1.t.max(t_min);
+                let t1 = rec2.t.min(t_max);
+                if t0 < t1 {
+                    return vec![(t0, t1)];
+                }
+            }
+        }
+        Vec::new()
+    }
+    /// Adds the primitives making up this object to `counts`, keyed by type.
+    /// Containers and wrappers count what they hold instead of themselves.
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        *counts
+            .entry(short_type_name(std::any::type_name::<Self>()))
+            .or_insert(0) += 1;
+    }
+    /// Type of the object behind a `dyn Hittable`, as `std::any::type_name`
+    /// gives it.
+    #[cfg(feature = "profile")]
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    /// `hit`, timed and counted into `counters`.
+    #[cfg(feature = "profile")]
+    fn hit_with_stats(
+        &self,
+        r: Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &profile::Counters,
+    ) -> Option<HitRecord> {
+        let begin = std::time::Instant::now();
+        let rec = self.hit(r, t_min, t_max);
+        counters.record(begin.elapsed(), rec.is_some());
+        rec
+    }
+}
+
+/// `Sphere` for `raytracer::hittable::sphere::Sphere<...>`, a name as
+/// `std::any::type_name` gives it.
+pub fn short_type_name(name: &'static str) -> &'static str {
+    let name = &name[..name.find('<').unwrap_or(name.len())];
+    &name[name.rfind("::").map_or(0, |i| i + 2)..]
 }
 
 #[derive(Clone)]
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
+    // Relative probability of each object being picked by `random`.
+    weights: Vec<f64>,
 }
 
 impl Default for HittableList {
     fn default() -> Self {
         Self {
             objects: Vec::new(),
+            weights: Vec::new(),
         }
     }
 }
 
 impl HittableList {
     pub fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.add_weighted(object, 1.);
+    }
+    pub fn add_weighted(&mut self, object: Arc<dyn Hittable>, weight: f64) {
+        assert!(
+            weight > 0.,
+            "HittableList::add_weighted: weight must be positive"
+        );
         self.objects.push(object);
+        self.weights.push(weight);
+    }
+    fn total_weight(&self) -> f64 {
+        self.weights.iter().sum()
     }
 }
 
+/// Walks every surface crossing along `r` and pairs them up as enter/exit, which
+/// also works for concave objects and groups of disjoint closed objects.
+pub fn march_intervals<H>(object: &H, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)>
+where
+    H: Hittable + ?Sized,
+{
+    let mut crossings = Vec::<f64>::new();
+    let mut t = f64::NEG_INFINITY;
+    while let Some(rec) = object.hit(r, t, f64::INFINITY) {
+        crossings.push(rec.t);
+        t = rec.t + 0.0001;
+    }
+
+    let mut intervals = Vec::new();
+    for pair in crossings.chunks_exact(2) {
+        let t0 = pair[0].max(t_min);
+        let t1 = pair[1].min(t_max);
+        if t0 < t1 {
+            intervals.push((t0, t1));
+        }
+    }
+    intervals
+}
+
 impl Hittable for HittableList {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut temp_rec: Option<HitRecord> = None;
@@ -117,17 +250,34 @@ impl Hittable for HittableList {
 
         Some(output_box)
     }
-    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
         let len = self.objects.len();
         let mut sum = 0.;
         for i in 0..len {
-            sum += self.objects[i].pdf_value(o, v);
+            sum += self.weights[i] * self.objects[i].pdf_value(o, v, time);
+        }
+        sum / self.total_weight()
+    }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        march_intervals(self, r, t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.objects.iter().any(|object| object.contains(p))
+    }
+    fn random(&self, o: Point3, time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        let mut target = rng.gen_range(0.0..self.total_weight());
+        for i in 0..self.objects.len() {
+            if target < self.weights[i] {
+                return self.objects[i].random(o, time, rng);
+            }
+            target -= self.weights[i];
         }
-        sum / len as f64
+        self.objects[self.objects.len() - 1].random(o, time, rng)
     }
-    fn random(&self, o: Point3) -> Vec3 {
-        let target = rand::thread_rng().gen_range(0..self.objects.len());
-        self.objects[target].random(o)
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        for object in &self.objects {
+            object.count_primitives(counts);
+        }
     }
 }
 
@@ -165,12 +315,24 @@ impl<H: Hittable> Hittable for Translate<H> {
         let moved_r = Ray::new(r.orig - self.offset, r.dir, r.tm);
         if let Some(mut rec) = self.ptr.hit(moved_r, t_min, t_max) {
             rec.p += self.offset;
-            rec.set_face_normal(moved_r, rec.normal);
+            let normal = rec.normal;
+            rec.set_face_normal(moved_r, rec.geometric_normal);
+            rec.set_shading_normal(normal);
             Some(rec)
         } else {
             None
         }
     }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let moved_r = Ray::new(r.orig - self.offset, r.dir, r.tm);
+        self.ptr.hit_intervals(moved_r, t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.ptr.contains(p - self.offset)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        self.ptr.count_primitives(counts)
+    }
 }
 
 pub struct RotateY<H>
@@ -198,10 +360,8 @@ impl<H: Hittable> RotateY<H> {
                         let y = j as f64 * output_box.max.y + (1 - j) as f64 * output_box.min.y;
                         let z = k as f64 * output_box.max.z + (1 - k) as f64 * output_box.min.z;
 
-                        let newx = cos_theta * x + sin_theta * z;
-                        let newz = -sin_theta * x + cos_theta * z;
-
-                        let tester = Vec3::new(newx, y, newz);
+                        let tester =
+                            Vec3::new(x, y, z).rotate_around(Vec3::new(0., 1., 0.), radians);
 
                         for c in 0..3 {
                             min[c] = min[c].min(tester[c]);
@@ -246,6 +406,7 @@ impl<H: Hittable> Hittable for RotateY<H> {
         if let Some(mut rec) = self.ptr.hit(rotated_r, t_min, t_max) {
             let mut p = rec.p;
             let mut normal = rec.normal;
+            let mut geometric_normal = rec.geometric_normal;
 
             p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
             p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
@@ -253,14 +414,52 @@ impl<H: Hittable> Hittable for RotateY<H> {
             normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
             normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
 
+            geometric_normal[0] =
+                self.cos_theta * rec.geometric_normal[0] + self.sin_theta * rec.geometric_normal[2];
+            geometric_normal[2] = -self.sin_theta * rec.geometric_normal[0]
+                + self.cos_theta * rec.geometric_normal[2];
+
+            let rotate = |v: Vec3| {
+                Vec3::new(
+                    self.cos_theta * v.x + self.sin_theta * v.z,
+                    v.y,
+                    -self.sin_theta * v.x + self.cos_theta * v.z,
+                )
+            };
+            rec.tangent = rotate(rec.tangent);
+            rec.bitangent = rotate(rec.bitangent);
+
             rec.p = p;
-            rec.set_face_normal(rotated_r, normal);
+            rec.set_face_normal(rotated_r, geometric_normal);
+            rec.set_shading_normal(normal);
 
             Some(rec)
         } else {
             None
         }
     }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let mut origin = r.orig;
+        let mut direction = r.dir;
+
+        origin[0] = self.cos_theta * r.orig[0] - self.sin_theta * r.orig[2];
+        origin[2] = self.sin_theta * r.orig[0] + self.cos_theta * r.orig[2];
+
+        direction[0] = self.cos_theta * r.dir[0] - self.sin_theta * r.dir[2];
+        direction[2] = self.sin_theta * r.dir[0] + self.cos_theta * r.dir[2];
+
+        self.ptr
+            .hit_intervals(Ray::new(origin, direction, r.tm), t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        let mut rotated = p;
+        rotated[0] = self.cos_theta * p[0] - self.sin_theta * p[2];
+        rotated[2] = self.sin_theta * p[0] + self.cos_theta * p[2];
+        self.ptr.contains(rotated)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        self.ptr.count_primitives(counts)
+    }
 }
 
 pub struct FlipFace<H>
@@ -288,6 +487,21 @@ impl<H: Hittable> Hittable for FlipFace<H> {
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
         self.ptr.bounding_box(time0, time1)
     }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        self.ptr.hit_intervals(r, t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.ptr.contains(p)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        self.ptr.pdf_value(o, v, time)
+    }
+    fn random(&self, o: Point3, time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        self.ptr.random(o, time, rng)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        self.ptr.count_primitives(counts)
+    }
 }
 
 pub struct Zoom<H>
@@ -321,10 +535,256 @@ impl<H: Hittable> Hittable for Zoom<H> {
         let moved_r = Ray::new(r.orig / self.rate, r.dir, r.tm);
         if let Some(mut rec) = self.ptr.hit(moved_r, t_min, t_max) {
             rec.p *= self.rate;
-            rec.set_face_normal(moved_r, rec.normal);
+            let normal = rec.normal;
+            rec.set_face_normal(moved_r, rec.geometric_normal);
+            rec.set_shading_normal(normal);
             Some(rec)
         } else {
             None
         }
     }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let moved_r = Ray::new(r.orig / self.rate, r.dir, r.tm);
+        self.ptr.hit_intervals(moved_r, t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.ptr.contains(p / self.rate)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        self.ptr.count_primitives(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::sampler::SampleSequence;
+    use crate::basic::vec::Color;
+    use crate::hittable::{
+        aarect::XZRect, boxes::Boxes, bvh::BvhNode, constantmedium::ConstantMedium, plane::Plane,
+        sphere::Sphere,
+    };
+    use crate::material::{DiffuseLight, Lambertian};
+
+    /// 2 x 2 square lights centered 2 above and 3 below the origin.
+    fn two_lights(upper_weight: f64, lower_weight: f64) -> HittableList {
+        let light = DiffuseLight::new(Color::new(1., 1., 1.));
+        let mut lights = HittableList::default();
+        lights.add_weighted(
+            Arc::new(XZRect::new(-1., 1., -1., 1., 2., light.clone())),
+            upper_weight,
+        );
+        lights.add_weighted(
+            Arc::new(XZRect::new(-1., 1., -1., 1., -3., light)),
+            lower_weight,
+        );
+        lights
+    }
+
+    /// Solid angle of a `2a x 2b` rectangle seen from `h` above its center.
+    fn rect_solid_angle(a: f64, b: f64, h: f64) -> f64 {
+        4. * (a * b / ((a * a + h * h) * (b * b + h * h)).sqrt()).asin()
+    }
+
+    #[test]
+    fn weighted_lights_are_picked_in_proportion() {
+        let lights = two_lights(3., 1.);
+        let o = Point3::new(0., 0., 0.);
+        let n = 20000;
+        let mut rng = SampleSequence::new(0, 0, 0, 9);
+        let upper = (0..n)
+            .filter(|_| lights.random(o, 0., &mut rng).y > 0.)
+            .count();
+        let ratio = upper as f64 / (n - upper) as f64;
+        assert!((ratio - 3.).abs() < 0.2, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn weighted_pdf_stays_normalized() {
+        let o = Point3::new(0., 0., 0.);
+        let up = Vec3::new(0., 1., 0.);
+        let upper_alone = two_lights(1., 1.).objects[0].pdf_value(o, up, 0.);
+        assert!((two_lights(3., 1.).pdf_value(o, up, 0.) - 0.75 * upper_alone).abs() < 1e-12);
+
+        // E[1 / pdf] over the lights' own samples is their total solid angle,
+        // whatever the weights.
+        let expected = rect_solid_angle(1., 1., 2.) + rect_solid_angle(1., 1., 3.);
+        for &(w0, w1) in [(1., 1.), (3., 1.), (1., 5.)].iter() {
+            let lights = two_lights(w0, w1);
+            let n = 40000;
+            let mut rng = SampleSequence::new(1, 2, 3, 4);
+            let sum: f64 = (0..n)
+                .map(|_| 1. / lights.pdf_value(o, lights.random(o, 0., &mut rng), 0.))
+                .sum();
+            let estimate = sum / n as f64;
+            assert!(
+                (estimate - expected).abs() < 0.02 * expected,
+                "weights {}:{} gave {} instead of {}",
+                w0,
+                w1,
+                estimate,
+                expected
+            );
+        }
+    }
+
+    /// Unit-high boxes spanning x in [1, 2] and [4, 6].
+    fn two_boxes() -> HittableList {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut boxes = HittableList::default();
+        boxes.add(Arc::new(Boxes::new(
+            Point3::new(1., -1., -1.),
+            Point3::new(2., 1., 1.),
+            gray.clone(),
+        )));
+        boxes.add(Arc::new(Boxes::new(
+            Point3::new(4., -1., -1.),
+            Point3::new(6., 1., 1.),
+            gray,
+        )));
+        boxes
+    }
+
+    fn assert_intervals(actual: Vec<(f64, f64)>, expected: &[(f64, f64)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a.0 - e.0).abs() < 1e-9 && (a.1 - e.1).abs() < 1e-9,
+                "{:?}",
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn intervals_cover_every_disjoint_object() {
+        let boxes = two_boxes();
+        let r = Ray::new(Point3::new(0., 0., 0.), Vec3::new(1., 0., 0.), 0.);
+        assert_intervals(
+            boxes.hit_intervals(r, 0.001, f64::INFINITY),
+            &[(1., 2.), (4., 6.)],
+        );
+        assert_intervals(boxes.hit_intervals(r, 1.5, 5.), &[(1.5, 2.), (4., 5.)]);
+        assert_intervals(boxes.hit_intervals(r, 2.5, 3.5), &[]);
+        // Starting inside the first box still pairs the crossings up.
+        let inside = Ray::new(Point3::new(1.5, 0., 0.), Vec3::new(1., 0., 0.), 0.);
+        assert_intervals(
+            boxes.hit_intervals(inside, 0.001, f64::INFINITY),
+            &[(0.001, 0.5), (2.5, 4.5)],
+        );
+    }
+
+    #[test]
+    fn medium_skips_the_gap_between_boxes() {
+        let fog = ConstantMedium::new(two_boxes(), 1e6, Color::new(1., 1., 1.));
+        let r = Ray::new(Point3::new(3., 0., 0.), Vec3::new(1., 0., 0.), 0.);
+        let rec = fog.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.t > 1. && rec.t < 1.001, "t {}", rec.t);
+        let back = Ray::new(Point3::new(3., 0., 0.), Vec3::new(-1., 0., 0.), 0.);
+        let rec = fog.hit(back, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.t > 1. && rec.t < 1.001, "t {}", rec.t);
+    }
+
+    #[test]
+    fn containers_and_wrappers_pass_contains_on() {
+        let boxes = two_boxes();
+        assert!(boxes.contains(Point3::new(1.5, 0., 0.)));
+        assert!(!boxes.contains(Point3::new(3., 0., 0.)));
+        let bvh = BvhNode::new_list(&boxes, 0., 1.);
+        assert!(bvh.contains(Point3::new(5., 0.5, 0.)));
+        assert!(!bvh.contains(Point3::new(5., 1.5, 0.)));
+
+        let moved = Translate::new(bvh, Vec3::new(0., 10., 0.));
+        assert!(moved.contains(Point3::new(1.5, 10., 0.)));
+        assert!(!moved.contains(Point3::new(1.5, 0., 0.)));
+        // A quarter turn takes x in [1, 2] to z in [-2, -1].
+        let turned = RotateY::new(two_boxes(), 90.);
+        assert!(turned.contains(Point3::new(0., 0., -1.5)));
+        assert!(!turned.contains(Point3::new(1.5, 0., 0.)));
+
+        // Rects have no inside.
+        assert!(!two_lights(1., 1.).contains(Point3::new(0., 0., 0.)));
+    }
+
+    #[test]
+    fn flipped_faces_swap_front_and_back() {
+        let light = DiffuseLight::new(Color::new(1., 1., 1.));
+        let rect = XZRect::new(-1., 1., -1., 1., 2., light.clone());
+        let flipped = FlipFace::new(XZRect::new(-1., 1., -1., 1., 2., light));
+        let up = Ray::new(Point3::new(0.2, 0., 0.3), Vec3::new(0., 1., 0.), 0.);
+        let (plain, turned) = (
+            rect.hit(up, 0.001, f64::INFINITY).unwrap(),
+            flipped.hit(up, 0.001, f64::INFINITY).unwrap(),
+        );
+        // From below the rect's +y normal it is a back face, until flipped.
+        assert!(!plain.front_face && turned.front_face);
+        assert!((plain.t - turned.t).abs() < 1e-12);
+        assert!((plain.p - turned.p).length() < 1e-12);
+        assert!((plain.normal - turned.normal).length() < 1e-12);
+        let (plain_box, turned_box) = (
+            rect.bounding_box(0., 1.).unwrap(),
+            flipped.bounding_box(0., 1.).unwrap(),
+        );
+        assert!((plain_box.min - turned_box.min).length() < 1e-12);
+        assert!((plain_box.max - turned_box.max).length() < 1e-12);
+    }
+
+    #[test]
+    fn lists_box_their_children_and_nest_in_the_bvh() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut list = HittableList::default();
+        assert!(list.bounding_box(0., 1.).is_none());
+        list.add(Arc::new(Sphere::new(
+            Point3::new(0., 0., 0.),
+            1.,
+            gray.clone(),
+        )));
+        list.add(Arc::new(Sphere::new(
+            Point3::new(3., 1., 0.),
+            0.5,
+            gray.clone(),
+        )));
+        list.add(Arc::new(Sphere::new(
+            Point3::new(-2., 0., 4.),
+            2.,
+            gray.clone(),
+        )));
+        let aabbox = list.bounding_box(0., 1.).unwrap();
+        assert!((aabbox.min - Point3::new(-4., -2., -1.)).length() < 1e-12);
+        assert!((aabbox.max - Point3::new(3.5, 2., 6.)).length() < 1e-12);
+
+        // The whole list as one leaf of a BVH, beside a far sphere.
+        let mut world = HittableList::default();
+        world.add(Arc::new(list.clone()));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(20., 0., 0.),
+            1.,
+            gray.clone(),
+        )));
+        let bvh = BvhNode::new_list(&world, 0., 1.);
+        assert!((bvh.bounding_box(0., 1.).unwrap().max.x - 21.).abs() < 1e-12);
+        let r = Ray::new(Point3::new(3., 1., -5.), Vec3::new(0., 0., 1.), 0.);
+        assert!((bvh.hit(r, 0.001, f64::INFINITY).unwrap().t - 4.5).abs() < 1e-9);
+
+        // One unbounded child leaves the list unbounded.
+        list.add(Arc::new(Plane::new(
+            Point3::new(0., -5., 0.),
+            Vec3::new(0., 1., 0.),
+            gray,
+        )));
+        assert!(list.bounding_box(0., 1.).is_none());
+    }
+
+    #[test]
+    fn short_type_names_drop_path_and_generics() {
+        assert_eq!(short_type_name("Sphere"), "Sphere");
+        assert_eq!(
+            short_type_name("raytracer::hittable::sphere::Sphere<raytracer::material::Metal>"),
+            "Sphere"
+        );
+        assert_eq!(
+            short_type_name(std::any::type_name::<Translate<HittableList>>()),
+            "Translate"
+        );
+    }
 }
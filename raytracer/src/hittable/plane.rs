@@ -0,0 +1,127 @@
+use crate::{
+    basic::ray::Ray,
+    basic::{
+        onb::Onb,
+        vec::{Point3, Vec3},
+    },
+    hittable::bvh::aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+};
+
+/// Unbounded plane through `point` facing `normal`. `u` and `v` are the
+/// in-plane coordinates divided by `uv_scale`, wrapped to `[0, 1)`, so image
+/// textures tile every `uv_scale` world units.
+pub struct Plane<M>
+where
+    M: Material,
+{
+    point: Point3,
+    uvw: Onb,
+    pub uv_scale: f64,
+    mp: M,
+}
+
+impl<M: Material> Plane<M> {
+    pub fn new(point: Point3, normal: Vec3, mp: M) -> Self {
+        Self {
+            point,
+            uvw: Onb::build_from_w(normal),
+            uv_scale: 1.,
+            mp,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Plane<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let normal = self.uvw.w();
+        let denom = Vec3::dot(normal, r.dir);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = Vec3::dot(self.point - r.orig, normal) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let hit_point = r.at(t);
+        let offset = hit_point - self.point;
+        let u = (Vec3::dot(offset, self.uvw.u()) / self.uv_scale).rem_euclid(1.);
+        let v = (Vec3::dot(offset, self.uvw.v()) / self.uv_scale).rem_euclid(1.);
+        let mut rec = HitRecord::new(hit_point, normal, t, u, v, true, &self.mp);
+        rec.set_face_normal(r, normal);
+        Some(rec)
+    }
+
+    /// None: the BVH keeps planes out of the tree.
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec::Color;
+    use crate::hittable::{bvh::BvhNode, sphere::Sphere, HittableList};
+    use crate::material::Lambertian;
+    use std::sync::Arc;
+
+    #[test]
+    fn planes_tile_their_uvs() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut plane = Plane::new(Point3::new(0., 0., 0.), Vec3::new(0., 1., 0.), gray);
+        plane.uv_scale = 2.;
+        let down = Vec3::new(0., -1., 0.);
+        let at = |x, z| {
+            let rec = plane.hit(
+                Ray::new(Point3::new(x, 1., z), down, 0.),
+                0.001,
+                f64::INFINITY,
+            );
+            let rec = rec.unwrap();
+            (rec.u, rec.v)
+        };
+        let (u0, v0) = at(0.3, -0.7);
+        for &(dx, dz) in &[(2., 0.), (0., -4.), (-6., 2.)] {
+            let (u, v) = at(0.3 + dx, -0.7 + dz);
+            assert!((u - u0).abs() < 1e-9 && (v - v0).abs() < 1e-9);
+        }
+        assert!((0. ..1.).contains(&u0) && (0. ..1.).contains(&v0));
+    }
+
+    #[test]
+    fn planes_sit_beside_the_bvh() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut list = HittableList::default();
+        list.add(Arc::new(Plane::new(
+            Point3::new(0., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            gray.clone(),
+        )));
+        for i in 0..8 {
+            let center = Point3::new(i as f64 * 3., 1., 0.);
+            list.add(Arc::new(Sphere::new(center, 1., gray.clone())));
+        }
+        let world = BvhNode::new_list(&list, 0., 1.);
+        let down = Vec3::new(0., -1., 0.);
+
+        // On a sphere, then far outside every sphere's box.
+        let rec = world.hit(
+            Ray::new(Point3::new(6., 5., 0.), down, 0.),
+            0.001,
+            f64::INFINITY,
+        );
+        assert!((rec.unwrap().t - 3.).abs() < 1e-9);
+        let rec = world.hit(
+            Ray::new(Point3::new(-500., 5., 40.), down, 0.),
+            0.001,
+            f64::INFINITY,
+        );
+        assert!((rec.unwrap().t - 5.).abs() < 1e-9);
+        // A sphere in front of the plane still wins along a slanted ray.
+        let slant = Ray::new(Point3::new(-5., 1., 0.), Vec3::new(1., -0.01, 0.), 0.);
+        let rec = world.hit(slant, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.p.y > 0.5);
+    }
+}
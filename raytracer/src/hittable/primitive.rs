@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use rand::RngCore;
+
+use crate::{
+    basic::{
+        ray::Ray,
+        vec::{Point3, Vec3},
+    },
+    hittable::{
+        aarect::{XYRect, XZRect, YZRect},
+        bvh::aabb::AABB,
+        sphere::{MovingSphere, Sphere},
+        triangle::Triangle,
+        HitRecord, Hittable,
+    },
+    material::MaterialHandle,
+};
+
+/// Closed set of the built-in shapes, dispatched with a `match` instead of a
+/// vtable. Materials stay type-erased behind `MaterialHandle`.
+#[derive(Clone)]
+pub enum Primitive {
+    Sphere(Sphere<MaterialHandle>),
+    MovingSphere(MovingSphere<MaterialHandle>),
+    XYRect(XYRect<MaterialHandle>),
+    XZRect(XZRect<MaterialHandle>),
+    YZRect(YZRect<MaterialHandle>),
+    Triangle(Triangle<MaterialHandle>),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $p:ident => $e:expr) => {
+        match $self {
+            Primitive::Sphere($p) => $e,
+            Primitive::MovingSphere($p) => $e,
+            Primitive::XYRect($p) => $e,
+            Primitive::XZRect($p) => $e,
+            Primitive::YZRect($p) => $e,
+            Primitive::Triangle($p) => $e,
+        }
+    };
+}
+
+impl Hittable for Primitive {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        dispatch!(self, p => p.hit(r, t_min, t_max))
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        dispatch!(self, p => p.bounding_box(time0, time1))
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        dispatch!(self, p => p.pdf_value(o, v, time))
+    }
+    fn random(&self, o: Point3, time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        dispatch!(self, p => p.random(o, time, rng))
+    }
+    fn contains(&self, point: Point3) -> bool {
+        dispatch!(self, p => p.contains(point))
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        dispatch!(self, p => p.count_primitives(counts))
+    }
+}
+
+/// A `HittableList` without dynamic dispatch, for scenes made only of
+/// built-in shapes.
+#[derive(Clone, Default)]
+pub struct PrimitiveList {
+    pub objects: Vec<Primitive>,
+}
+
+impl PrimitiveList {
+    pub fn add(&mut self, object: Primitive) {
+        self.objects.push(object);
+    }
+}
+
+impl Hittable for PrimitiveList {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut temp_rec: Option<HitRecord> = None;
+        let mut closest_so_far = t_max;
+        for object in &self.objects {
+            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                temp_rec = Some(rec);
+            }
+        }
+        temp_rec
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        let mut output_box: Option<AABB> = None;
+        for object in &self.objects {
+            let temp_box = object.bounding_box(time0, time1)?;
+            output_box = Some(match output_box {
+                Some(output_box) => AABB::surrounding_box(output_box, temp_box),
+                None => temp_box,
+            });
+        }
+        output_box
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.objects.iter().any(|object| object.contains(p))
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        for object in &self.objects {
+            object.count_primitives(counts);
+        }
+    }
+}
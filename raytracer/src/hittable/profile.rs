@@ -0,0 +1,232 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rand::RngCore;
+
+use crate::{
+    basic::{
+        ray::Ray,
+        vec::{Point3, Vec3},
+    },
+    hittable::{bvh::aabb::AABB, short_type_name, HitRecord, Hittable, HittableList},
+};
+
+#[derive(Clone, Copy, Default)]
+pub struct PrimitiveStats {
+    pub calls: u64,
+    pub hits: u64,
+    pub time: Duration,
+}
+
+/// Running totals for one primitive type. Relaxed atomics, so the render
+/// threads never wait on each other to count.
+#[derive(Default)]
+pub struct Counters {
+    calls: AtomicU64,
+    hits: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl Counters {
+    pub fn record(&self, time: Duration, hit: bool) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> PrimitiveStats {
+        PrimitiveStats {
+            calls: self.calls.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            time: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Per-primitive-type intersection counts and time. The lock is only taken
+/// to hand out counters when a `Profiled` is built and to read the totals.
+#[derive(Default)]
+pub struct RenderStats {
+    primitives: Mutex<BTreeMap<&'static str, Arc<Counters>>>,
+}
+
+impl RenderStats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The counters every object of type `kind`, a full type name, adds to.
+    pub fn counters(&self, kind: &'static str) -> Arc<Counters> {
+        self.primitives
+            .lock()
+            .unwrap()
+            .entry(short_type_name(kind))
+            .or_default()
+            .clone()
+    }
+
+    /// Totals so far for each primitive type, by short type name.
+    pub fn totals(&self) -> BTreeMap<&'static str, PrimitiveStats> {
+        self.primitives
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&kind, counters)| (kind, counters.snapshot()))
+            .collect()
+    }
+
+    /// `totals` summed over every primitive type.
+    pub fn total(&self) -> PrimitiveStats {
+        self.totals()
+            .values()
+            .fold(PrimitiveStats::default(), |sum, stats| PrimitiveStats {
+                calls: sum.calls + stats.calls,
+                hits: sum.hits + stats.hits,
+                time: sum.time + stats.time,
+            })
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, stats) in self.totals() {
+            writeln!(
+                f,
+                "{:<16} calls: {:>12} hits: {:>12} time: {:?}",
+                kind, stats.calls, stats.hits, stats.time
+            )?;
+        }
+        let total = self.total();
+        writeln!(
+            f,
+            "{:<16} calls: {:>12} hits: {:>12} time: {:?}",
+            "total", total.calls, total.hits, total.time
+        )
+    }
+}
+
+/// Records every `hit` on the wrapped object under its type.
+pub struct Profiled {
+    ptr: Arc<dyn Hittable>,
+    counters: Arc<Counters>,
+}
+
+impl Profiled {
+    pub fn new(ptr: Arc<dyn Hittable>, stats: &RenderStats) -> Self {
+        let counters = stats.counters(ptr.type_name());
+        Self { ptr, counters }
+    }
+}
+
+/// `list` with each object wrapped in a `Profiled` counting into `stats`.
+pub fn profile_list(list: &HittableList, stats: &RenderStats) -> HittableList {
+    let mut profiled = HittableList::default();
+    for object in &list.objects {
+        profiled.add(Arc::new(Profiled::new(object.clone(), stats)));
+    }
+    profiled
+}
+
+impl Hittable for Profiled {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.ptr.hit_with_stats(r, t_min, t_max, &self.counters)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.ptr.bounding_box(time0, time1)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        self.ptr.pdf_value(o, v, time)
+    }
+    fn random(&self, o: Point3, time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        self.ptr.random(o, time, rng)
+    }
+    fn hit_intervals(&self, r: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        self.ptr.hit_intervals(r, t_min, t_max)
+    }
+    fn contains(&self, p: Point3) -> bool {
+        self.ptr.contains(p)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        self.ptr.count_primitives(counts)
+    }
+    fn type_name(&self) -> &'static str {
+        self.ptr.type_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::vec::Color;
+    use crate::hittable::{bvh::BvhNode, sphere::Sphere, triangle::Triangle};
+    use crate::material::Lambertian;
+
+    #[test]
+    fn hits_are_counted_by_primitive_type() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 0., -5.),
+            1.,
+            gray.clone(),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(10., 0., -5.),
+            1.,
+            gray.clone(),
+        )));
+        world.add(Arc::new(Triangle::new(
+            Point3::new(-11., -1., -5.),
+            Point3::new(-9., -1., -5.),
+            Point3::new(-10., 1., -5.),
+            gray,
+        )));
+
+        let stats = RenderStats::new();
+        let world = BvhNode::new_list(&profile_list(&world, &stats), 0., 1.);
+        let origin = Point3::new(0., 0., 0.);
+        for &x in [0., 10., 10., -10.].iter() {
+            let r = Ray::new(origin, Vec3::new(x, 0., -5.), 0.);
+            assert!(world.hit(r, 0.001, f64::INFINITY).is_some());
+        }
+        // Misses everything, but not every bounding box.
+        world.hit(Ray::new(origin, Vec3::new(0., 5., -5.), 0.), 0.001, 1e9);
+
+        let totals = stats.totals();
+        let spheres = totals["Sphere"];
+        let triangles = totals["Triangle"];
+        assert_eq!(spheres.hits, 3);
+        assert_eq!(triangles.hits, 1);
+        assert!(spheres.calls >= 3 && triangles.calls >= 1);
+        assert_eq!(totals.len(), 2);
+        assert!(format!("{}", stats).contains("Triangle"));
+    }
+
+    #[test]
+    fn time_adds_up_per_type_and_in_total() {
+        let stats = RenderStats::new();
+        let spheres = stats.counters("raytracer::hittable::sphere::Sphere<M>");
+        let triangles = stats.counters("raytracer::hittable::triangle::Triangle<M>");
+        spheres.record(Duration::from_micros(3), true);
+        spheres.record(Duration::from_micros(5), false);
+        triangles.record(Duration::from_micros(7), true);
+
+        let totals = stats.totals();
+        assert_eq!(totals["Sphere"].time, Duration::from_micros(8));
+        assert_eq!(totals["Triangle"].time, Duration::from_micros(7));
+        let total = stats.total();
+        assert_eq!((total.calls, total.hits), (3, 2));
+        assert_eq!(total.time, Duration::from_micros(15));
+        assert!(format!("{}", stats).contains("total"));
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::BTreeMap;
 use std::f64::consts::PI;
 use std::f64::INFINITY;
 
+use rand::RngCore;
+
 use crate::basic::onb::Onb;
 use crate::basic::ray::Ray;
 use crate::basic::vec::{random_to_sphere, Point3, Vec3};
@@ -35,32 +38,16 @@ impl<M: Material + Clone> Sphere<M> {
 }
 
 impl<M: Material + Clone> Hittable for Sphere<M> {
-    #[allow(clippy::many_single_char_names)]
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig - self.center;
-        let a = r.dir.length_sqr();
-        let half_b = Vec3::dot(oc, r.dir);
-        let c = oc.length_sqr() - self.radius * self.radius;
-
-        let discriminant = half_b.powi(2) - a * c;
-        if discriminant < 0. {
-            return None;
-        }
-
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
-        }
+        let root = sphere_root(self.center, self.radius, r, t_min, t_max)?;
 
         let outward_normal = (r.at(root) - self.center) / self.radius;
         let (u, v) = self.get_sphere_uv(outward_normal);
         let mut rec = HitRecord::new(r.at(root), outward_normal, root, u, v, false, &self.mat_ptr);
 
         rec.set_face_normal(r, outward_normal);
+        let (dpdu, dpdv) = sphere_tangents(outward_normal);
+        rec.set_tangents(dpdu * self.radius, dpdv * self.radius);
 
         Some(rec)
     }
@@ -72,24 +59,124 @@ impl<M: Material + Clone> Hittable for Sphere<M> {
         ))
     }
 
-    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
-        if let Some(_rec) = self.hit(Ray::new(o, v, 0.), 0.001, INFINITY) {
-            let cos_max = (1. - self.radius * self.radius / (self.center - o).length_sqr()).sqrt();
-            let solid_angle = 2. * PI * (1. - cos_max);
-            1. / solid_angle
+    /// A negative radius only flips the normals, so it bounds the same ball.
+    fn contains(&self, p: Point3) -> bool {
+        (p - self.center).length_squared() < self.radius * self.radius
+    }
+
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        // From inside every direction hits, even within `t_min` of the surface.
+        if in_ball(self.center, self.radius, o)
+            || self.hit(Ray::new(o, v, time), 0.001, INFINITY).is_some()
+        {
+            cone_pdf(self.center, self.radius, o)
         } else {
             0.
         }
     }
 
-    fn random(&self, o: Point3) -> Vec3 {
-        let direction = self.center - o;
-        let dis_sqr = direction.length_sqr();
-        let uvw = Onb::build_from_w(direction);
-        uvw.local_vec(random_to_sphere(self.radius, dis_sqr))
+    fn random(&self, o: Point3, _time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        random_in_cone(self.center, self.radius, o, rng)
+    }
+}
+
+/// Inside or on the sphere, where cone sampling turns into sampling every
+/// direction.
+fn in_ball(center: Point3, radius: f64, o: Point3) -> bool {
+    (center - o).length_squared() <= radius * radius
+}
+
+/// Uniform density over the cone of directions from `o` subtending the sphere.
+/// From inside or on the sphere every direction hits it, so the density is
+/// uniform over all of them, matching `random_in_cone`.
+fn cone_pdf(center: Point3, radius: f64, o: Point3) -> f64 {
+    if in_ball(center, radius, o) {
+        return 1. / (4. * PI);
+    }
+    let dis_sqr = (center - o).length_squared();
+    // Rounding can push this below zero just outside the surface.
+    let cos_max = (1. - radius * radius / dis_sqr).max(0.).sqrt();
+    let solid_angle = 2. * PI * (1. - cos_max);
+    1. / solid_angle
+}
+
+fn random_in_cone(center: Point3, radius: f64, o: Point3, rng: &mut dyn RngCore) -> Vec3 {
+    if in_ball(center, radius, o) {
+        return Vec3::random_unit_vector(rng);
+    }
+    let direction = center - o;
+    let dis_sqr = direction.length_squared();
+    let uvw = Onb::build_from_w(direction);
+    uvw.local_vec(random_to_sphere(radius, dis_sqr, rng))
+}
+
+/// Sphere stretched by `radii` along the axes. Hits are found on the unit
+/// sphere after dividing the ray by the radii, which keeps `t` unchanged.
+#[derive(Clone)]
+pub struct Ellipsoid<M>
+where
+    M: Material + Clone,
+{
+    pub center: Point3,
+    pub radii: Vec3,
+    pub mat_ptr: M,
+}
+
+impl<M: Material + Clone> Ellipsoid<M> {
+    #[allow(dead_code)]
+    pub fn new(center: Point3, radii: Vec3, mat_ptr: M) -> Self {
+        Self {
+            center,
+            radii,
+            mat_ptr,
+        }
+    }
+
+    fn to_unit_space(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.x / self.radii.x, v.y / self.radii.y, v.z / self.radii.z)
+    }
+}
+
+impl<M: Material + Clone> Hittable for Ellipsoid<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = Ray::new(
+            self.to_unit_space(r.orig - self.center),
+            self.to_unit_space(r.dir),
+            r.tm,
+        );
+        let root = sphere_root(Point3::new(0., 0., 0.), 1., local, t_min, t_max)?;
+
+        let unit_point = local.at(root);
+        // Normals scale by the inverse of the stretch.
+        let outward_normal = self.to_unit_space(unit_point).to_unit();
+        let theta = (-unit_point.y).acos();
+        let phi = f64::atan2(-unit_point.z, unit_point.x) + PI;
+        let mut rec = HitRecord::new(
+            r.at(root),
+            outward_normal,
+            root,
+            phi / (2. * PI),
+            theta / PI,
+            false,
+            &self.mat_ptr,
+        );
+        rec.set_face_normal(r, outward_normal);
+        let (dpdu, dpdv) = sphere_tangents(unit_point);
+        rec.set_tangents(dpdu * self.radii, dpdv * self.radii);
+        Some(rec)
+    }
+
+    fn contains(&self, p: Point3) -> bool {
+        self.to_unit_space(p - self.center).length_squared() < 1.
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let radii = Vec3::new(self.radii.x.abs(), self.radii.y.abs(), self.radii.z.abs());
+        Some(AABB::new(self.center - radii, self.center + radii))
     }
 }
 
+#[derive(Clone)]
 pub struct MovingSphere<M>
 where
     M: Material,
@@ -134,36 +221,31 @@ impl<M: Material> MovingSphere<M> {
 }
 
 impl<M: Material> Hittable for MovingSphere<M> {
-    #[allow(clippy::many_single_char_names)]
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig - self.center(r.tm);
-        let a = r.dir.length_sqr();
-        let half_b = Vec3::dot(oc, r.dir);
-        let c = oc.length_sqr() - self.radius * self.radius;
-
-        let discriminant = half_b.powi(2) - a * c;
-        if discriminant < 0. {
-            return None;
-        }
-
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
-        }
+        let root = sphere_root(self.center(r.tm), self.radius, r, t_min, t_max)?;
 
         let outward_normal = (r.at(root) - self.center(r.tm)) / self.radius;
         let (u, v) = self.get_sphere_uv(outward_normal);
         let mut rec = HitRecord::new(r.at(root), outward_normal, root, u, v, false, &self.mat_ptr);
 
         rec.set_face_normal(r, outward_normal);
+        let (dpdu, dpdv) = sphere_tangents(outward_normal);
+        rec.set_tangents(dpdu * self.radius, dpdv * self.radius);
 
         Some(rec)
     }
 
+    /// Inside the ball at some time of the motion from `time0` to `time1`.
+    fn contains(&self, p: Point3) -> bool {
+        let path = self.center1 - self.center0;
+        let along = if path.length_squared() > 0. {
+            (Vec3::dot(p - self.center0, path) / path.length_squared()).clamp(0., 1.)
+        } else {
+            0.
+        };
+        (p - (self.center0 + path * along)).length_squared() < self.radius * self.radius
+    }
+
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
         let box0 = AABB::new(
             self.center(time0) - Vec3::new(self.radius, self.radius, self.radius),
@@ -175,4 +257,491 @@ impl<M: Material> Hittable for MovingSphere<M> {
         );
         Some(AABB::surrounding_box(box0, box1))
     }
+
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        if in_ball(self.center(time), self.radius, o)
+            || self.hit(Ray::new(o, v, time), 0.001, INFINITY).is_some()
+        {
+            cone_pdf(self.center(time), self.radius, o)
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, o: Point3, time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        random_in_cone(self.center(time), self.radius, o, rng)
+    }
+}
+
+/// Many spheres sharing one material, with a flat BVH over them. One `hit`
+/// call walks the tree without any per-sphere `Arc` or dynamic dispatch.
+pub struct SphereBatch<M>
+where
+    M: Material,
+{
+    spheres: Vec<(Point3, f64)>,
+    nodes: Vec<BatchNode>,
+    mat_ptr: M,
+}
+
+#[derive(Clone, Copy)]
+struct BatchNode {
+    aabbox: AABB,
+    // Leaves cover `spheres[start..start + count]`; inner nodes have `count`
+    // 0, their left child right after them and their right child at `start`.
+    start: usize,
+    count: usize,
+}
+
+const BATCH_LEAF_SIZE: usize = 4;
+
+#[allow(dead_code)]
+impl<M: Material> SphereBatch<M> {
+    pub fn new(mut spheres: Vec<(Point3, f64)>, mat_ptr: M) -> Self {
+        let mut nodes = Vec::new();
+        if !spheres.is_empty() {
+            let len = spheres.len();
+            SphereBatch::<M>::build(&mut spheres, 0, len, &mut nodes);
+        }
+        Self {
+            spheres,
+            nodes,
+            mat_ptr,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spheres.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.spheres.is_empty()
+    }
+
+    fn bounds(spheres: &[(Point3, f64)]) -> AABB {
+        let mut aabbox = sphere_box(spheres[0]);
+        for sphere in &spheres[1..] {
+            aabbox = AABB::surrounding_box(aabbox, sphere_box(*sphere));
+        }
+        aabbox
+    }
+
+    fn build(spheres: &mut [(Point3, f64)], start: usize, end: usize, nodes: &mut Vec<BatchNode>) {
+        let aabbox = SphereBatch::<M>::bounds(&spheres[start..end]);
+        let id = nodes.len();
+        if end - start <= BATCH_LEAF_SIZE {
+            nodes.push(BatchNode {
+                aabbox,
+                start,
+                count: end - start,
+            });
+            return;
+        }
+        nodes.push(BatchNode {
+            aabbox,
+            start: 0,
+            count: 0,
+        });
+
+        // Split the longest axis at the median center.
+        let extent = aabbox.max - aabbox.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        spheres[start..end].sort_by(|a, b| {
+            a.0[axis]
+                .partial_cmp(&b.0[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mid = (start + end) / 2;
+        SphereBatch::<M>::build(spheres, start, mid, nodes);
+        nodes[id].start = nodes.len();
+        SphereBatch::<M>::build(spheres, mid, end, nodes);
+    }
+}
+
+fn sphere_box((center, radius): (Point3, f64)) -> AABB {
+    let r = Vec3::new(radius, radius, radius);
+    AABB::new(center - r, center + r)
+}
+
+/// Roots of `a t^2 + 2 half_b t + c`, smaller first, given its non-negative
+/// `discriminant` (`half_b^2 - a c`). Avoids the cancellation in
+/// `-half_b +- sqrtd` by taking the larger-magnitude root first and the other
+/// from the product of the roots, `c / a`.
+pub fn quadratic_roots(a: f64, half_b: f64, c: f64, discriminant: f64) -> (f64, f64) {
+    let q = -(half_b + half_b.signum() * discriminant.sqrt());
+    if q == 0. {
+        return (0., 0.);
+    }
+    let (t0, t1) = (q / a, c / q);
+    if t0 < t1 {
+        (t0, t1)
+    } else {
+        (t1, t0)
+    }
+}
+
+/// dp/du and dp/dv on the unit sphere at `n`, for the UVs `get_sphere_uv`
+/// gives; scale by the radius. dp/dv is zero at the poles.
+fn sphere_tangents(n: Vec3) -> (Vec3, Vec3) {
+    let dpdu = Vec3::new(n.z, 0., -n.x) * (2. * PI);
+    let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+    if sin_theta < 1e-12 {
+        return (dpdu, Vec3::new(0., 0., 0.));
+    }
+    let dpdv = Vec3::new(-n.x * n.y / sin_theta, sin_theta, -n.y * n.z / sin_theta) * PI;
+    (dpdu, dpdv)
+}
+
+/// Nearest root of the ray against a sphere within `[t_min, t_max]`.
+fn sphere_root(center: Point3, radius: f64, r: Ray, t_min: f64, t_max: f64) -> Option<f64> {
+    let oc = r.orig - center;
+    let a = r.dir.length_squared();
+    let half_b = Vec3::dot(oc, r.dir);
+    let c = oc.length_squared() - radius * radius;
+
+    // `half_b^2 - a c` cancels badly far from the origin; this is the same
+    // value through the ray's closest approach to the center.
+    let perpendicular = oc - r.dir * (half_b / a);
+    let discriminant = a * (radius * radius - perpendicular.length_squared());
+    if discriminant < 0. {
+        return None;
+    }
+
+    let (near, far) = quadratic_roots(a, half_b, c, discriminant);
+    if t_min <= near && near <= t_max {
+        Some(near)
+    } else if t_min <= far && far <= t_max {
+        Some(far)
+    } else {
+        None
+    }
+}
+
+impl<M: Material> Hittable for SphereBatch<M> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut closest: Option<(f64, usize)> = None;
+        // Median splits keep the tree balanced, so it is never deeper than
+        // log2 of the sphere count and a fixed stack does.
+        let mut stack = [0; 64];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let id = stack[len];
+            let t_far = closest.map_or(t_max, |(t, _)| t);
+            let node = &self.nodes[id];
+            if !node.aabbox.hit(r, t_min, t_far) {
+                continue;
+            }
+            if node.count == 0 {
+                stack[len] = node.start;
+                stack[len + 1] = id + 1;
+                len += 2;
+                continue;
+            }
+            for i in node.start..node.start + node.count {
+                let (center, radius) = self.spheres[i];
+                let t_far = closest.map_or(t_max, |(t, _)| t);
+                if let Some(t) = sphere_root(center, radius, r, t_min, t_far) {
+                    closest = Some((t, i));
+                }
+            }
+        }
+
+        let (t, i) = closest?;
+        let (center, radius) = self.spheres[i];
+        let outward_normal = (r.at(t) - center) / radius;
+        let theta = (-outward_normal.y).acos();
+        let phi = f64::atan2(-outward_normal.z, outward_normal.x) + PI;
+        let mut rec = HitRecord::new(
+            r.at(t),
+            outward_normal,
+            t,
+            phi / (2. * PI),
+            theta / PI,
+            false,
+            &self.mat_ptr,
+        );
+        rec.set_face_normal(r, outward_normal);
+        let (dpdu, dpdv) = sphere_tangents(outward_normal);
+        rec.set_tangents(dpdu * radius, dpdv * radius);
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        self.nodes.first().map(|node| node.aabbox)
+    }
+    fn count_primitives(&self, counts: &mut BTreeMap<&'static str, usize>) {
+        *counts.entry("Sphere").or_insert(0) += self.spheres.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{sampler::SampleSequence, vec::Color};
+    use crate::hittable::{bvh::BvhNode, HittableList};
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use rand::Rng;
+    use std::{sync::Arc, time::Instant};
+
+    #[test]
+    fn spheres_contain_their_center() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let center = Point3::new(1., 2., 3.);
+        for &radius in [0.5, -0.5].iter() {
+            let sphere = Sphere::new(center, radius, gray.clone());
+            assert!(sphere.contains(center));
+            assert!(!sphere.contains(center + Vec3::new(0.6, 0., 0.)));
+        }
+
+        let ellipsoid = Ellipsoid::new(center, Vec3::new(2., 0.5, 1.), gray.clone());
+        assert!(ellipsoid.contains(center + Vec3::new(1.9, 0., 0.)));
+        assert!(!ellipsoid.contains(center + Vec3::new(0., 0.6, 0.)));
+
+        // Anywhere along the motion counts.
+        let moving = MovingSphere::new(center, center + Vec3::new(4., 0., 0.), 0., 1., 0.5, gray);
+        assert!(moving.contains(center + Vec3::new(2., 0.4, 0.)));
+        assert!(moving.contains(center + Vec3::new(4.4, 0., 0.)));
+        assert!(!moving.contains(center + Vec3::new(-0.6, 0., 0.)));
+    }
+
+    #[test]
+    fn sphere_tangents_match_the_uvs() {
+        let sphere = Sphere::new(
+            Point3::new(0., 0., 0.),
+            1.,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        );
+        let h = 1e-6;
+        for &n in [
+            Vec3::new(1., 0.3, 0.2),
+            Vec3::new(-0.4, -0.7, 0.5),
+            Vec3::new(0.1, 0.9, -0.8),
+        ]
+        .iter()
+        {
+            let n = n.to_unit();
+            let (u, v) = sphere.get_sphere_uv(n);
+            let (dpdu, dpdv) = sphere_tangents(n);
+            let (u1, v1) = sphere.get_sphere_uv((n + dpdu * h).to_unit());
+            assert!((u1 - u - h).abs() < 1e-9 && (v1 - v).abs() < 1e-9, "{}", n);
+            let (u1, v1) = sphere.get_sphere_uv((n + dpdv * h).to_unit());
+            assert!((u1 - u).abs() < 1e-9 && (v1 - v - h).abs() < 1e-9, "{}", n);
+        }
+    }
+
+    #[test]
+    fn sphere_lights_sample_from_inside_and_outside() {
+        let sphere = Sphere::new(
+            Point3::new(0., 0., 0.),
+            1.,
+            Lambertian::new(Color::new(1., 1., 1.)),
+        );
+        let cone = |d: f64| 1. / (2. * PI * (1. - (1. - 1. / (d * d)).sqrt()));
+        let uniform = 1. / (4. * PI);
+        let mut rng = SampleSequence::new(0, 0, 0, 5);
+        for &(d, expected) in [
+            (3., cone(3.)),
+            (1.01, cone(1.01)),
+            (0.999_9, uniform),
+            (0.5, uniform),
+            (0., uniform),
+        ]
+        .iter()
+        {
+            let o = Point3::new(d, d, -d) / 3f64.sqrt();
+            let mut matching = 0;
+            let mut mean = Vec3::new(0., 0., 0.);
+            for _ in 0..1000 {
+                let v = sphere.random(o, 0., &mut rng);
+                let pdf = sphere.pdf_value(o, v, 0.);
+                assert!(pdf.is_finite(), "{} at {}", pdf, d);
+                if (pdf - expected).abs() < 1e-9 * expected {
+                    matching += 1;
+                }
+                mean += v.to_unit() / 1000.;
+            }
+            // Grazing directions may round to a miss.
+            assert!(matching >= 995, "{} of 1000 at {}", matching, d);
+            if d < 1. {
+                assert!(mean.length() < 0.1, "{} at {}", mean, d);
+            }
+        }
+        // Outside, only the cone counts.
+        let o = Point3::new(3., 0., 0.);
+        assert!(sphere.pdf_value(o, Vec3::new(1., 0., 0.), 0.) == 0.);
+
+        let moving = MovingSphere::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(4., 0., 0.),
+            0.,
+            1.,
+            1.,
+            Lambertian::new(Color::new(1., 1., 1.)),
+        );
+        let inside_late = Point3::new(3.5, 0., 0.);
+        assert!((moving.pdf_value(inside_late, Vec3::new(0., 1., 0.), 1.) - uniform).abs() < 1e-12);
+        assert!(
+            (moving.pdf_value(inside_late, Vec3::new(-1., 0., 0.), 0.) - cone(3.5)).abs() < 1e-9
+        );
+    }
+
+    #[test]
+    fn batch_hits_like_a_list_of_spheres() {
+        let mut rng = SampleSequence::new(0, 0, 0, 11);
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let (batch, list) = batch_and_list(5000, &mut rng, &gray);
+
+        for _ in 0..500 {
+            let r = random_ray(&mut rng);
+            let expected = list.hit(r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            let actual = batch.hit(r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            match (expected, actual) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-9, "{} vs {}", e, a),
+                (None, None) => {}
+                _ => panic!("{:?} vs {:?}", expected, actual),
+            }
+        }
+    }
+
+    /// `n` random spheres as one batch and as a list of separate spheres.
+    fn batch_and_list(
+        n: usize,
+        rng: &mut SampleSequence,
+        mat: &Lambertian<SolidColor>,
+    ) -> (SphereBatch<Lambertian<SolidColor>>, HittableList) {
+        let spheres: Vec<(Point3, f64)> = (0..n)
+            .map(|_| (Vec3::random_range(rng, -50., 50.), rng.gen_range(0.1..1.)))
+            .collect();
+        let mut list = HittableList::default();
+        for &(center, radius) in &spheres {
+            list.add(Arc::new(Sphere::new(center, radius, mat.clone())));
+        }
+        (SphereBatch::new(spheres, mat.clone()), list)
+    }
+
+    fn random_ray(rng: &mut SampleSequence) -> Ray {
+        Ray::new(
+            Vec3::random_range(rng, -60., 60.),
+            Vec3::random_range(rng, -1., 1.),
+            0.,
+        )
+    }
+
+    /// `cargo test --release -- --ignored --nocapture` prints the time per ray
+    /// through 10k spheres as a batch, a BVH of `Arc<Sphere>` and a flat list.
+    #[test]
+    #[ignore]
+    fn bench_batch_against_sphere_list() {
+        let mut rng = SampleSequence::new(0, 0, 0, 12);
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let (batch, list) = batch_and_list(10_000, &mut rng, &gray);
+        let bvh = BvhNode::new_list(&list, 0., 1.);
+        let rays: Vec<Ray> = (0..200_000).map(|_| random_ray(&mut rng)).collect();
+        let targets: [(&str, &dyn Hittable, usize); 3] = [
+            ("batch", &batch, rays.len()),
+            ("bvh", &bvh, rays.len()),
+            ("list", &list, 2_000),
+        ];
+        for (name, target, count) in targets.iter() {
+            let start = Instant::now();
+            let hits = rays[..*count]
+                .iter()
+                .filter(|r| target.hit(**r, 0.001, f64::INFINITY).is_some())
+                .count();
+            println!(
+                "{}: {:.1} ns/ray, {} of {} hit",
+                name,
+                start.elapsed().as_nanos() as f64 / *count as f64,
+                hits,
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn round_ellipsoids_hit_like_spheres() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let center = Point3::new(1., -2., 0.5);
+        let sphere = Sphere::new(center, 2., gray.clone());
+        let ellipsoid = Ellipsoid::new(center, Vec3::new(2., 2., 2.), gray);
+        let mut rng = SampleSequence::new(0, 0, 0, 13);
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let r = Ray::new(
+                center + Vec3::random_range(&mut rng, -4., 4.),
+                Vec3::random_range(&mut rng, -1., 1.),
+                0.,
+            );
+            let (expected, actual) = (
+                sphere.hit(r, 0.001, f64::INFINITY),
+                ellipsoid.hit(r, 0.001, f64::INFINITY),
+            );
+            assert_eq!(expected.is_some(), actual.is_some());
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                assert!((expected.t - actual.t).abs() < 1e-9);
+                assert!(Vec3::distance(expected.p, actual.p) < 1e-9);
+                assert!(Vec3::distance(expected.normal, actual.normal) < 1e-9);
+                assert_eq!(expected.front_face, actual.front_face);
+                assert!((expected.u - actual.u).abs() < 1e-9);
+                assert!((expected.v - actual.v).abs() < 1e-9);
+                hits += 1;
+            }
+        }
+        assert!(hits > 100, "{}", hits);
+    }
+
+    #[test]
+    fn rays_from_inside_hit_the_far_side() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let center = Point3::new(0., 0., -3.);
+        for &radius in [1., -1.].iter() {
+            let sphere = Sphere::new(center, radius, gray.clone());
+            // The near root, -1.2, is behind the origin.
+            let r = Ray::new(center + Vec3::new(0.2, 0., 0.), Vec3::new(1., 0., 0.), 0.);
+            let rec = sphere.hit(r, 0.001, INFINITY).unwrap();
+            assert!((rec.t - 0.8).abs() < 1e-12, "{}", rec.t);
+            assert!((rec.p.x - 1.).abs() < 1e-12);
+            // A negative radius turns the surface inside out.
+            assert_eq!(rec.front_face, radius < 0.);
+
+            // Not normalized, and starting at the center.
+            let r = Ray::new(center, Vec3::new(0., 2., 0.), 0.);
+            let rec = sphere.hit(r, 0.001, INFINITY).unwrap();
+            assert!((rec.t - 0.5).abs() < 1e-12, "{}", rec.t);
+
+            // The far side past `t_max` is a miss, not the near root.
+            let r = Ray::new(center + Vec3::new(0.2, 0., 0.), Vec3::new(1., 0., 0.), 0.);
+            assert!(sphere.hit(r, 0.001, 0.5).is_none());
+        }
+    }
+
+    #[test]
+    fn quadratic_roots_keep_the_small_root() {
+        // t^2 - 2e8 t + 1: roots near 2e8 and 5e-9.
+        let (a, half_b, c): (f64, f64, f64) = (1., -1e8, 1.);
+        let discriminant = half_b.powi(2) - a * c;
+        let exact_small = 1. / (1e8 + (1e16f64 - 1.).sqrt());
+
+        let (small, large) = quadratic_roots(a, half_b, c, discriminant);
+        assert!(
+            ((small - exact_small) / exact_small).abs() < 1e-12,
+            "{}",
+            small
+        );
+        assert!(((large - 2e8) / 2e8).abs() < 1e-12, "{}", large);
+
+        // The textbook formula cancels the small root away entirely.
+        let naive = (-half_b - discriminant.sqrt()) / a;
+        assert!(((naive - exact_small) / exact_small).abs() > 0.5);
+    }
 }
@@ -1,3 +1,7 @@
+use std::{f64::INFINITY, sync::Arc};
+
+use rand::{Rng, RngCore};
+
 use crate::{
     basic::{
         ray::Ray,
@@ -6,8 +10,9 @@ use crate::{
     material::Material,
 };
 
-use super::{bvh::aabb::AABB, HitRecord, Hittable};
+use super::{bvh::aabb::AABB, HitRecord, Hittable, HittableList};
 
+#[derive(Clone)]
 pub struct Triangle<M>
 where
     M: Material,
@@ -15,6 +20,10 @@ where
     a: Point3,
     b: Point3,
     c: Point3,
+    // Texture coordinates at a, b and c; barycentrics are reported if absent.
+    uv: Option<[(f64, f64); 3]>,
+    // Vertex normals at a, b and c, interpolated for smooth shading.
+    normals: Option<[Vec3; 3]>,
     mp: M,
 }
 
@@ -24,69 +33,231 @@ impl<M: Material> Triangle<M> {
             a: x,
             b: y,
             c: z,
+            uv: None,
+            normals: None,
             mp,
         }
     }
+    pub fn with_uv(mut self, uv: [(f64, f64); 3]) -> Self {
+        self.uv = Some(uv);
+        self
+    }
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+    pub fn area(&self) -> f64 {
+        Vec3::cross(self.b - self.a, self.c - self.a).length() / 2.
+    }
     pub fn get_normal(&self) -> Vec3 {
         Vec3::cross(self.b - self.a, self.c - self.a).to_unit()
     }
-    pub fn inside(&self, p: Point3) -> bool {
-        Vec3::dot(
-            Vec3::cross(self.c - self.a, p - self.a),
-            Vec3::cross(self.c - self.a, self.b - self.a),
-        ) >= 0.
-            && Vec3::dot(
-                Vec3::cross(self.a - self.b, p - self.b),
-                Vec3::cross(self.a - self.b, self.c - self.b),
-            ) >= 0.
-            && Vec3::dot(
-                Vec3::cross(self.b - self.c, p - self.c),
-                Vec3::cross(self.b - self.c, self.a - self.c),
-            ) >= 0.
+}
+
+/// dp/du and dp/dv of a triangle with edges `e1` (a to b) and `e2` (a to c),
+/// from its texture coordinates or else its barycentrics. Zero if the UVs are
+/// degenerate.
+pub fn uv_tangents(e1: Vec3, e2: Vec3, uv: Option<[(f64, f64); 3]>) -> (Vec3, Vec3) {
+    let [ua, ub, uc] = match uv {
+        Some(uv) => uv,
+        None => return (e1, e2),
+    };
+    let (du1, dv1) = (ub.0 - ua.0, ub.1 - ua.1);
+    let (du2, dv2) = (uc.0 - ua.0, uc.1 - ua.1);
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < 1e-12 {
+        let zero = Vec3::new(0., 0., 0.);
+        return (zero, zero);
     }
+    ((e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det)
 }
 
 impl<M: Material> Hittable for Triangle<M> {
+    /// Möller–Trumbore: solves for `t` and the barycentrics of b and c at once.
     #[allow(clippy::many_single_char_names)]
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let origin = r.orig;
-        let direction = r.dir;
-        let n = self.get_normal();
-        let t = Vec3::dot(direction, n);
-        let t = Vec3::dot(self.a - origin, n) / t;
-        if t.is_nan() || t < t_min || t > t_max {
+        let e1 = self.b - self.a;
+        let e2 = self.c - self.a;
+        let pvec = Vec3::cross(r.dir, e2);
+        let det = Vec3::dot(e1, pvec);
+        // Parallel to the plane, or a degenerate triangle.
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let tvec = r.orig - self.a;
+        let u = Vec3::dot(tvec, pvec) * inv_det;
+        if !(0. ..=1.).contains(&u) {
             return None;
         }
-        let p = origin + direction * t;
-        if !self.inside(p) {
+        let qvec = Vec3::cross(tvec, e1);
+        let v = Vec3::dot(r.dir, qvec) * inv_det;
+        if v < 0. || u + v > 1. {
             return None;
         }
+        let t = Vec3::dot(e2, qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let p = r.at(t);
+        let n = self.get_normal();
 
-        let a1 = self.a.x - self.b.x;
-        let b1 = self.a.x - self.c.x;
-        let c1 = self.a.x - p.x;
-        let a2 = self.a.y - self.b.y;
-        let b2 = self.a.y - self.c.y;
-        let c2 = self.a.y - p.y;
-        let u = (c1 * b2 - b1 * c2) / (a1 * b2 - b1 * a2);
-        let v = (a1 * c2 - a2 * c1) / (a1 * b2 - b1 * a2);
-        let mut rec = HitRecord::new(p, n, t, u, v, true, &self.mp);
+        let (tex_u, tex_v) = match self.uv {
+            Some([ua, ub, uc]) => (
+                ua.0 * (1. - u - v) + ub.0 * u + uc.0 * v,
+                ua.1 * (1. - u - v) + ub.1 * u + uc.1 * v,
+            ),
+            None => (u, v),
+        };
+        let mut rec = HitRecord::new(p, n, t, tex_u, tex_v, true, &self.mp);
         rec.set_face_normal(r, n);
+        let (dpdu, dpdv) = uv_tangents(e1, e2, self.uv);
+        rec.set_tangents(dpdu, dpdv);
+        if let Some([na, nb, nc]) = self.normals {
+            let shading = na * (1. - u - v) + nb * u + nc * v;
+            if shading.length_squared() > 0. {
+                rec.set_shading_normal(shading.to_unit());
+            }
+        }
         Some(rec)
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        // Padded so axis-aligned triangles don't get a flat box.
+        let pad = Vec3::new(0.0001, 0.0001, 0.0001);
         Some(AABB::new(
             Point3::new(
                 self.a.x.min(self.b.x.min(self.c.x)),
                 self.a.y.min(self.b.y.min(self.c.y)),
                 self.a.z.min(self.b.z.min(self.c.z)),
-            ),
+            ) - pad,
             Point3::new(
                 self.a.x.max(self.b.x.max(self.c.x)),
                 self.a.y.max(self.b.y.max(self.c.y)),
                 self.a.z.max(self.b.z.max(self.c.z)),
-            ),
+            ) + pad,
         ))
     }
+
+    fn pdf_value(&self, o: Point3, v: Vec3, time: f64) -> f64 {
+        if let Some(rec) = self.hit(Ray::new(o, v, time), 0.001, INFINITY) {
+            let dis_sqr = rec.t * rec.t * v.length_squared();
+            let cos = (Vec3::dot(v, rec.normal) / v.length()).abs();
+            dis_sqr / (cos * self.area())
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, o: Point3, _time: f64, rng: &mut dyn RngCore) -> Vec3 {
+        let (mut r1, mut r2): (f64, f64) = (rng.gen(), rng.gen());
+        if r1 + r2 > 1. {
+            r1 = 1. - r1;
+            r2 = 1. - r2;
+        }
+        self.a + (self.b - self.a) * r1 + (self.c - self.a) * r2 - o
+    }
+}
+
+/// Adds the triangles with an emissive material to `lights`, weighted by area
+/// so every point of the emitting surface is equally likely to be sampled.
+pub fn collect_emissive<M>(triangles: &[Arc<Triangle<M>>], lights: &mut HittableList)
+where
+    M: Material + 'static,
+{
+    for triangle in triangles
+        .iter()
+        .filter(|t| t.mp.is_emissive() && t.area() > 0.)
+    {
+        lights.add_weighted(triangle.clone(), triangle.area());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{sampler::SampleSequence, vec::Color};
+    use crate::material::{DiffuseLight, Lambertian, MaterialHandle};
+
+    /// A ceiling at y = 2 of two emitting triangles, areas 2 and 4, over a
+    /// floor that doesn't emit.
+    fn lit_room() -> Vec<Arc<Triangle<MaterialHandle>>> {
+        let light = MaterialHandle::new(DiffuseLight::new(Color::new(4., 4., 4.)));
+        let gray = MaterialHandle::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let ceiling = |x, z| Point3::new(x, 2., z);
+        let floor = |x, z| Point3::new(x, -1., z);
+        vec![
+            Arc::new(Triangle::new(
+                ceiling(-1., -1.),
+                ceiling(1., -1.),
+                ceiling(-1., 1.),
+                light.clone(),
+            )),
+            Arc::new(Triangle::new(
+                ceiling(1., -1.),
+                ceiling(3., 1.),
+                ceiling(-1., 1.),
+                light,
+            )),
+            Arc::new(Triangle::new(
+                floor(-1., -1.),
+                floor(1., -1.),
+                floor(-1., 1.),
+                gray.clone(),
+            )),
+            Arc::new(Triangle::new(
+                floor(1., -1.),
+                floor(1., 1.),
+                floor(-1., 1.),
+                gray,
+            )),
+        ]
+    }
+
+    #[test]
+    fn emitting_triangles_light_the_room_evenly() {
+        let triangles = lit_room();
+        let mut lights = HittableList::default();
+        collect_emissive(&triangles, &mut lights);
+        assert_eq!(lights.objects.len(), 2);
+
+        // Weighted by area, a point of either triangle is as likely as one of
+        // a single light covering both.
+        let o = Point3::new(0., 0., 0.);
+        for &target in &[Point3::new(-0.5, 2., -0.5), Point3::new(1.5, 2., 0.5)] {
+            let v = target - o;
+            let cos = 2. / v.length();
+            let expected = v.length_squared() / (cos * 6.);
+            assert!((lights.pdf_value(o, v, 0.) - expected).abs() < 1e-9);
+        }
+        for i in 0..100 {
+            let mut rng = SampleSequence::new(i, 0, 0, 1);
+            let v = lights.random(o, 0., &mut rng);
+            assert!((v.y - 2.).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn triangle_pdfs_integrate_to_one() {
+        let triangles = lit_room();
+        let o = Point3::new(0.2, 0., 0.1);
+        let n = 200_000;
+        let integral = |pdf: &dyn Fn(Vec3) -> f64| {
+            (0..n)
+                .map(|i| {
+                    let mut rng = SampleSequence::new(i, 0, 0, 1);
+                    pdf(Vec3::random_unit_vector(&mut rng))
+                })
+                .sum::<f64>()
+                * 4.
+                * std::f64::consts::PI
+                / n as f64
+        };
+        let one = integral(&|v| triangles[1].pdf_value(o, v, 0.));
+        assert!((one - 1.).abs() < 0.05, "{}", one);
+        let mut lights = HittableList::default();
+        collect_emissive(&triangles, &mut lights);
+        let both = integral(&|v| lights.pdf_value(o, v, 0.));
+        assert!((both - 1.).abs() < 0.05, "{}", both);
+    }
 }
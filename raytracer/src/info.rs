@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use console::style;
+
+use crate::hittable::{bvh::aabb::AABB, bvh::BvhNode, Hittable, HittableList};
+
+/// What a scene is made of, and the shape of its BVH.
+pub struct SceneInfo {
+    pub objects: usize,
+    // Primitives by short type name, looking through wrappers and lists.
+    pub counts: BTreeMap<&'static str, usize>,
+    pub lights: usize,
+    pub bounds: Option<AABB>,
+    // Node count and depth; none for an empty world.
+    pub bvh: Option<(usize, usize)>,
+}
+
+impl SceneInfo {
+    pub fn triangles(&self) -> usize {
+        ["Triangle", "MeshTriangle"]
+            .iter()
+            .map(|kind| self.counts.get(kind).copied().unwrap_or(0))
+            .sum()
+    }
+}
+
+/// Gathers a `SceneInfo` without rendering anything.
+pub fn scene_info(
+    world: &HittableList,
+    lights: &HittableList,
+    time0: f64,
+    time1: f64,
+) -> SceneInfo {
+    let mut counts = BTreeMap::new();
+    world.count_primitives(&mut counts);
+    let bvh = if world.objects.is_empty() {
+        None
+    } else {
+        let bvh = BvhNode::new_list(world, time0, time1);
+        Some((bvh.node_count(), bvh.depth()))
+    };
+    SceneInfo {
+        objects: world.objects.len(),
+        counts,
+        lights: lights.objects.len(),
+        bounds: world.bounding_box(time0, time1),
+        bvh,
+    }
+}
+
+impl fmt::Display for SceneInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TOP-LEVEL OBJECTS: {}", style(self.objects).yellow())?;
+        for (kind, count) in &self.counts {
+            writeln!(f, "  {}: {}", kind, style(count).yellow())?;
+        }
+        writeln!(f, "TRIANGLES: {}", style(self.triangles()).yellow())?;
+        writeln!(f, "LIGHTS: {}", style(self.lights).yellow())?;
+        match self.bounds {
+            Some(aabbox) => writeln!(
+                f,
+                "BOUNDS: ({}, {}, {}) - ({}, {}, {})",
+                aabbox.min.x, aabbox.min.y, aabbox.min.z, aabbox.max.x, aabbox.max.y, aabbox.max.z
+            )?,
+            None => writeln!(f, "BOUNDS: {}", style("unbounded").yellow())?,
+        }
+        if let Some((nodes, depth)) = self.bvh {
+            writeln!(
+                f,
+                "BVH: {} nodes, depth {}",
+                style(nodes).yellow(),
+                style(depth).yellow()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene;
+
+    #[test]
+    fn random_scene_is_all_spheres() {
+        let world = scene::random_scene();
+        let info = scene_info(&world, &HittableList::default(), 0., 1.);
+        // The ground, the small spheres that weren't too close to the big
+        // metal one, and the three big ones.
+        let spheres = info.counts.get("Sphere").copied().unwrap_or(0)
+            + info.counts.get("MovingSphere").copied().unwrap_or(0);
+        assert_eq!(spheres, world.objects.len());
+        assert_eq!(info.objects, world.objects.len());
+        assert!(info.objects > 4 && info.objects <= 1 + 23 * 23 + 3);
+        assert_eq!(info.triangles(), 0);
+        let (nodes, depth) = info.bvh.unwrap();
+        assert!(nodes >= info.objects / 4 && depth >= 1);
+        assert!(info.bounds.unwrap().min.y <= -1000.);
+    }
+}
@@ -1,46 +1,81 @@
+mod aov;
+mod background;
 mod basic;
+mod builder;
+mod cli;
+mod denoise;
+mod gltf;
 mod hittable;
+mod info;
+mod json;
 mod material;
+mod metadata;
+mod postprocess;
+mod render;
 mod scene;
+mod scenefile;
 mod texture;
 
 use console::style;
-use image::{ImageBuffer, RgbImage};
-use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
-use rand::Rng;
-use std::{
-    fs::File,
-    process::exit,
-    sync::{mpsc, Arc},
-    thread,
-    time::Instant,
-};
+use indicatif::HumanDuration;
+use std::{fs::File, process::exit, time::Instant};
 
-use basic::{camera::Camera, pdf::Pdf};
-use basic::{pdf::HittablePdf, ray::Ray};
-use basic::{
-    pdf::MixturePdf,
-    vec::{Color, Point3, Vec3},
-};
-use hittable::bvh::BvhNode;
-use hittable::{Hittable, HittableList};
+use aov::Aov;
+use basic::camera::{Camera, DEFAULT_VUP};
+use basic::vec::{Color, Point3};
+use hittable::{bvh::BvhNode, Hittable};
+use render::{Fit, RenderSettings, SampleMap};
 
 fn main() {
     print!("{}[2J", 27 as char); // Clear screen
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char); // Set cursor position as 1,1
 
     // Image
-    let path = "output/output.jpg";
-    const IMAGE_WIDTH: u32 = 500;
-    const IMAGE_HEIGHT: u32 = 500;
-    const ASPECT_RATIO: f64 = IMAGE_WIDTH as f64 / IMAGE_HEIGHT as f64;
-    const IMAGE_QUALITY: u8 = 100; // From 0 to 100
-    const SAMPLES_PER_PIXEL: i32 = 100;
-    const MAX_DEPTH: i32 = 50;
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("{}", cli::HELP);
+        return;
+    }
+    let options = match cli::Options::parse(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{} {}\n\n{}", style("Bad arguments:").red(), e, cli::HELP);
+            exit(2);
+        }
+    };
+    let write_metadata = options.write_metadata;
+    let alpha = options.alpha; // PNG with coverage alpha
+    let write_aovs = options.aovs;
+    let debug_pixel = options.debug_pixel;
+    // --info SCENE: print what the scene is made of instead of rendering it
+    if let Some(name) = &options.info {
+        match scene::by_name(name) {
+            Some((world, lights)) => {
+                println!("SCENE: {}", style(name).yellow());
+                print!("{}", info::scene_info(&world, &lights, 0., 1.));
+            }
+            None => println!("{} {}", style("Unknown scene:").red(), name),
+        }
+        return;
+    }
+    let bounce_heatmap = options.bounce_heatmap;
+    let wavefront = options.wavefront; // Breadth-first tracer
+    let edge_samples = options.edge_samples; // Extra samples along silhouettes
+    let flat = options.flat; // One-sample geometry preview
+    let path = match &options.out {
+        Some(path) => path.as_str(),
+        None if alpha => "output/output.png",
+        None => "output/output.jpg",
+    };
+    let image_width = options.width;
+    let image_height = options.height;
+    let aspect_ratio = image_width as f64 / image_height as f64;
+    let image_quality = options.quality; // From 0 to 100
+    let samples_per_pixel = options.samples;
+    let max_depth = options.depth;
     const THREAD_NUMBER: u32 = 8;
-    const SECTION_LINE_NUM: u32 = IMAGE_HEIGHT / THREAD_NUMBER;
 
-    let vup = Vec3::new(0., 1., 0.);
+    let vup = DEFAULT_VUP;
     let vfov = 40.;
     let aperture = 0.0;
     let focus_dist = 10.;
@@ -48,7 +83,11 @@ fn main() {
     let time1 = 1.;
     let lookfrom = Point3::new(278., 278., -800.);
     let lookat = Point3::new(278., 278., 0.);
-    let background = Color::new(0., 0., 0.);
+    static BACKGROUND: Color = Color {
+        x: 0.,
+        y: 0.,
+        z: 0.,
+    };
 
     let begin_time = Instant::now();
     println!(
@@ -58,10 +97,10 @@ fn main() {
     );
     println!(
         "IMAGE SIZE: {}\nJPEG QUALITY: {}\nSAMPLE PER PIXEL: {}\nMAX DEPTH: {}",
-        style(IMAGE_WIDTH.to_string() + &"x".to_string() + &IMAGE_HEIGHT.to_string()).yellow(),
-        style(IMAGE_QUALITY.to_string()).yellow(),
-        style(SAMPLES_PER_PIXEL.to_string()).yellow(),
-        style(MAX_DEPTH.to_string()).yellow(),
+        style(image_width.to_string() + &"x".to_string() + &image_height.to_string()).yellow(),
+        style(image_quality.to_string()).yellow(),
+        style(samples_per_pixel.to_string()).yellow(),
+        style(max_depth.to_string()).yellow(),
     );
 
     // Camera
@@ -70,7 +109,7 @@ fn main() {
         lookat,
         vup,
         vfov,
-        ASPECT_RATIO,
+        aspect_ratio,
         aperture,
         focus_dist,
         time0,
@@ -86,118 +125,193 @@ fn main() {
     );
 
     // World & lights
-    let (main_world, main_lights) = scene::cornell_box();
-    let main_world = BvhNode::new_list(&main_world, time0, time1);
-
-    // Random line
-    let mut random_line_id: [u32; IMAGE_HEIGHT as usize] = [0; IMAGE_HEIGHT as usize];
-    let mut rng = rand::thread_rng();
-    for i in 0..IMAGE_HEIGHT {
-        random_line_id[i as usize] = i;
-        let target = rng.gen_range(0..i + 1);
-        random_line_id.swap(i as usize, target as usize);
-    }
-
-    // Progress bar
-    let multiprogress = Arc::new(MultiProgress::new());
-    multiprogress.set_move_cursor(true);
-
-    // Thread
-    let mut output_pixel_color = Vec::<Color>::new();
-    let mut thread_pool = Vec::<_>::new();
-
-    for thread_id in 0..THREAD_NUMBER {
-        // line
-        let line_id = random_line_id;
-        let line_beg = thread_id * SECTION_LINE_NUM;
-        let mut line_end = line_beg + SECTION_LINE_NUM;
-        if thread_id == THREAD_NUMBER - 1 {
-            line_end = IMAGE_HEIGHT;
+    // --profile counts intersections per primitive type (profile feature only)
+    #[cfg(feature = "profile")]
+    let profile = options.profile.then(hittable::profile::RenderStats::new);
+    let build_bvh = |world: &hittable::HittableList| {
+        #[cfg(feature = "profile")]
+        if let Some(stats) = &profile {
+            let world = hittable::profile::profile_list(world, stats);
+            return BvhNode::new_list(&world, time0, time1);
         }
-
-        // world & lights
-        let world = main_world.clone();
-        let lights = main_lights.clone();
-
-        //progress
-        let mp = multiprogress.clone();
-        let progress_bar = mp.add(ProgressBar::new((line_end - line_beg) as u64));
-        progress_bar.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] [{pos}/{len}] ({eta})")
-        .progress_chars("#>-"));
-
-        // thread code
-        let (tx, rx) = mpsc::channel();
-
-        thread_pool.push((
-            thread::spawn(move || {
-                let mut progress = 0;
-                progress_bar.set_position(progress);
-
-                let mut section_pixel_color = Vec::<Color>::new();
-
-                let mut rng = rand::thread_rng();
-                for y_id in line_beg..line_end {
-                    for x in 0..IMAGE_WIDTH {
-                        let y = line_id[y_id as usize];
-                        let mut pixel_color = Color::new(0., 0., 0.);
-                        for _i in 0..SAMPLES_PER_PIXEL {
-                            let rand_u: f64 = rng.gen();
-                            let rand_v: f64 = rng.gen();
-                            let u = (x as f64 + rand_u) / (IMAGE_WIDTH - 1) as f64;
-                            let v = (y as f64 + rand_v) / (IMAGE_HEIGHT - 1) as f64;
-                            let r = cam.get_ray(u, v);
-                            pixel_color += ray_color(r, background, &world, &lights, MAX_DEPTH);
-                        }
-                        section_pixel_color.push(pixel_color);
-                    }
-                    progress += 1;
-                    progress_bar.set_position(progress);
-                }
-                tx.send(section_pixel_color).unwrap();
-                progress_bar.finish_with_message("Finished.");
-            }),
-            rx,
-        ));
-    }
-    multiprogress.join().unwrap();
-
-    println!(
-        "{} 🚛 {}",
-        style("[3/5]").bold().dim(),
-        style("Collecting Threads Results...").green(),
-    );
-
-    for _thread_id in 0..THREAD_NUMBER {
-        let thread = thread_pool.remove(0);
-        match thread.0.join() {
-            Ok(_) => {
-                let mut received = thread.1.recv().unwrap();
-                output_pixel_color.append(&mut received);
+        BvhNode::new_list(world, time0, time1)
+    };
+    let (main_world, main_lights) = scene::cornell_box();
+    let main_world = build_bvh(&main_world);
+
+    let settings = RenderSettings {
+        image_width,
+        image_height,
+        samples_per_pixel: if flat { 1 } else { samples_per_pixel },
+        max_depth,
+        thread_number: THREAD_NUMBER,
+        background: &BACKGROUND,
+        alpha,
+        russian_roulette: options.roulette,
+        tone_map: options.tone_map,
+        seed: options.seed,
+        fit: Fit::Stretch,
+        flat,
+        parallel: options.parallel,
+        denoiser: None,
+        jitter_offset: None,
+        sample_map: None,
+        firefly_clamp: None,
+        output_colorspace: options.color_space,
+        adaptive: None,
+    };
+
+    // A .json argument replaces the built-in scene, camera and image settings
+    let scene_file = options.scene.as_ref();
+    let (main_world, main_lights, cam, settings) = match scene_file {
+        Some(scene_path) => match scenefile::load_scene(scene_path) {
+            Ok((world, lights, cam, loaded)) => (
+                build_bvh(&world),
+                lights,
+                cam,
+                RenderSettings {
+                    samples_per_pixel: if flat { 1 } else { loaded.samples_per_pixel },
+                    alpha,
+                    flat,
+                    seed: options.seed,
+                    russian_roulette: options.roulette,
+                    tone_map: options.tone_map,
+                    parallel: options.parallel,
+                    output_colorspace: options.color_space,
+                    ..loaded
+                },
+            ),
+            Err(e) => {
+                println!("{} {}", style("Loading scene fails:").red(), e);
+                exit(1);
             }
-            Err(_) => {
-                println!("Thread error");
-                exit(0);
+        },
+        None => (main_world, main_lights, cam, settings),
+    };
+    // --target-rmse: a few quick low resolution passes pick the sample count
+    let settings = match options.target_rmse {
+        Some(target_rmse) => {
+            let samples =
+                render::probe_samples(&main_world, &main_lights, cam, &settings, target_rmse);
+            println!(
+                "ESTIMATED SAMPLES PER PIXEL: {}",
+                style(samples.to_string()).yellow()
+            );
+            RenderSettings {
+                samples_per_pixel: samples.min(i32::MAX as u32) as i32,
+                ..settings
             }
         }
+        None => settings,
+    };
+    // --sample-map: black pixels take one sample, white ones all of them
+    let settings = match &options.sample_map {
+        Some(map_path) => match SampleMap::open(map_path, 1, settings.samples_per_pixel) {
+            Ok(map) => RenderSettings {
+                sample_map: Some(Box::leak(Box::new(map))),
+                ..settings
+            },
+            Err(e) => {
+                println!(
+                    "{} {}: {}",
+                    style("Loading sample map fails:").red(),
+                    map_path,
+                    e
+                );
+                exit(1);
+            }
+        },
+        None => settings,
+    };
+    let lookfrom = cam.params().lookfrom;
+    if main_world.contains(lookfrom) {
+        println!(
+            "{} the camera at {} is inside an object",
+            style("Warning:").yellow(),
+            lookfrom
+        );
     }
-
-    println!(
-        "{} 🏭 {}",
-        style("[4/5]").bold().dim(),
-        style("Generating Image...").green()
-    );
-
-    let mut img: RgbImage = ImageBuffer::new(IMAGE_WIDTH, IMAGE_HEIGHT);
-    let mut pixel_id = 0;
-    for y_id in 0..IMAGE_HEIGHT {
-        for x in 0..IMAGE_WIDTH {
-            let y = random_line_id[y_id as usize];
-            let pixel_color = output_pixel_color[pixel_id];
-            let pixel = img.get_pixel_mut(x, IMAGE_HEIGHT - y - 1);
-            *pixel = image::Rgb(write_color(pixel_color, SAMPLES_PER_PIXEL));
-            pixel_id += 1;
+    if let Some((x, y)) = debug_pixel {
+        if x >= settings.image_width || y >= settings.image_height {
+            println!(
+                "{} --debug-pixel {} {} is outside the {}x{} image\n\n{}",
+                style("Bad arguments:").red(),
+                x,
+                y,
+                settings.image_width,
+                settings.image_height,
+                cli::HELP
+            );
+            exit(2);
+        }
+        render::debug_pixel(&main_world, &main_lights, cam, &settings, x, y);
+        return;
+    }
+    if bounce_heatmap {
+        let heatmap_path = "output/bounces.png";
+        println!(
+            "Ouput bounce heatmap as \"{}\"",
+            style(heatmap_path).yellow()
+        );
+        let heatmap = render::render_bounce_heatmap(&main_world, &main_lights, cam, &settings);
+        if heatmap.save(heatmap_path).is_err() {
+            println!("{}", style("Outputting bounce heatmap fails.").red());
         }
+        return;
+    }
+    // --stereo: both eyes share the threads and the BVH
+    let outputs: Vec<(String, image::DynamicImage)> = if let Some(separation) = options.stereo {
+        let eyes = [
+            cam.moved_right(-separation / 2.),
+            cam.moved_right(separation / 2.),
+        ];
+        render::render_multi(&main_world, &main_lights, &eyes, &settings)
+            .into_iter()
+            .zip(["left", "right"].iter())
+            .map(|(eye, name)| {
+                (
+                    cli::with_suffix(path, name),
+                    image::DynamicImage::ImageRgb8(eye),
+                )
+            })
+            .collect()
+    } else {
+        let output_image = if alpha {
+            image::DynamicImage::ImageRgba8(render::render_rgba(
+                &main_world,
+                &main_lights,
+                cam,
+                &settings,
+            ))
+        } else if wavefront {
+            image::DynamicImage::ImageRgb8(render::render_wavefront(
+                &main_world,
+                &main_lights,
+                cam,
+                &settings,
+            ))
+        } else if let Some(edge_samples) = edge_samples {
+            image::DynamicImage::ImageRgb8(render::render_edge_aware(
+                &main_world,
+                &main_lights,
+                cam,
+                &settings,
+                edge_samples,
+                render::EDGE_THRESHOLD,
+            ))
+        } else {
+            image::DynamicImage::ImageRgb8(render::render(
+                &main_world,
+                &main_lights,
+                cam,
+                &settings,
+            ))
+        };
+        vec![(path.to_string(), output_image)]
+    };
+    #[cfg(feature = "profile")]
+    if let Some(stats) = &profile {
+        print!("{}", stats);
     }
 
     println!(
@@ -209,15 +323,51 @@ fn main() {
     // ==================== afterwork ====================
 
     // Output image to file
-    println!("Ouput image as \"{}\"", style(path).yellow());
-    let output_image = image::DynamicImage::ImageRgb8(img);
-    let mut output_file = File::create(path).unwrap();
-    match output_image.write_to(
-        &mut output_file,
-        image::ImageOutputFormat::Jpeg(IMAGE_QUALITY),
-    ) {
-        Ok(_) => {}
-        Err(_) => println!("{}", style("Outputting image fails.").red()),
+    for (path, output_image) in outputs.iter() {
+        println!("Ouput image as \"{}\"", style(path).yellow());
+        let output_format = if alpha {
+            image::ImageOutputFormat::Png
+        } else {
+            image::ImageOutputFormat::Jpeg(image_quality)
+        };
+        let written = if path.ends_with(".ppm") {
+            render::write_ppm(path, &output_image.to_rgb8()).is_ok()
+        } else {
+            let mut output_file = File::create(path).unwrap();
+            output_image
+                .write_to(&mut output_file, output_format)
+                .is_ok()
+        };
+        if !written {
+            println!("{}", style("Outputting image fails.").red());
+        }
+    }
+
+    if write_aovs {
+        for aov in [Aov::Normal, Aov::Albedo, Aov::Depth].iter() {
+            let aov_path = format!("output/{}.png", aov.name());
+            println!(
+                "Ouput {} AOV as \"{}\"",
+                aov.name(),
+                style(&aov_path).yellow()
+            );
+            let aov_image = aov::render_aov(&main_world, cam, &settings, *aov);
+            if aov_image.save(&aov_path).is_err() {
+                println!("{}", style("Outputting AOV fails.").red());
+            }
+        }
+    }
+
+    if write_metadata {
+        if let Err(e) = metadata::write_metadata(
+            path,
+            &settings,
+            &cam,
+            scene_file.map_or("cornell_box", |s| s.as_str()),
+            begin_time.elapsed(),
+        ) {
+            println!("{} {}", style("Writing metadata fails:").red(), e);
+        }
     }
 
     println!(
@@ -229,71 +379,3 @@ fn main() {
 
     exit(0);
 }
-
-fn ray_color(
-    r: Ray,
-    background: Color,
-    world: &BvhNode,
-    lights: &HittableList,
-    depth: i32,
-) -> Color {
-    if depth <= 0 {
-        return Color::new(0., 0., 0.);
-    }
-    if let Some(rec) = world.hit(r, 0.001, f64::MAX) {
-        let emitted = rec.mat_ptr.emitted(r, &rec, rec.u, rec.v, rec.p);
-        if let Some(srec) = rec.mat_ptr.scatter(r, &rec) {
-            if let Some(specular) = srec.specular_ray {
-                return srec.attenuation
-                    * ray_color(specular, background, &world, lights, depth - 1);
-            }
-
-            // if srec.pdf_ptr.is_none() {
-            //     return emitted
-            //         + srec.attenuation
-            //             * ray_color(
-            //                 srec.specular_ray.unwrap(),
-            //                 background,
-            //                 &world,
-            //                 lights,
-            //                 depth - 1,
-            //             );
-            // }
-
-            let light_ptr = HittablePdf::new(lights, rec.p);
-            let p = MixturePdf::new(light_ptr, srec.pdf_ptr.unwrap());
-            let scattered = Ray::new(rec.p, p.generate(), r.tm);
-            let pdf_val = p.value(scattered.dir);
-            emitted
-                + srec.attenuation
-                    * rec.mat_ptr.scattering_pdf(r, &rec, scattered)
-                    * ray_color(scattered, background, &world, lights, depth - 1)
-                    / pdf_val
-        } else {
-            emitted
-        }
-    } else {
-        background
-    }
-}
-
-fn write_color(pixel_color: Color, samples_per_pixel: i32) -> [u8; 3] {
-    let mut r = pixel_color.x;
-    let mut g = pixel_color.y;
-    let mut b = pixel_color.z;
-    if r.is_nan() {
-        r = 0.
-    }
-    if g.is_nan() {
-        g = 0.
-    }
-    if b.is_nan() {
-        b = 0.
-    }
-
-    [
-        ((r / samples_per_pixel as f64).sqrt().clamp(0.0, 0.999) * 255.999).floor() as u8,
-        ((g / samples_per_pixel as f64).sqrt().clamp(0.0, 0.999) * 255.999).floor() as u8,
-        ((b / samples_per_pixel as f64).sqrt().clamp(0.0, 0.999) * 255.999).floor() as u8,
-    ]
-}
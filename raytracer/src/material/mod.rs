@@ -1,24 +1,39 @@
 use std::f64::consts::PI;
+use std::sync::Arc;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use crate::{
     basic::ray::Ray,
     basic::{
+        onb::Onb,
         pdf::CosPdf,
-        vec::{random_in_unit_sphere, reflect, refract, Color, Point3, Vec3},
+        vec::{random_in_unit_sphere, refract, Color, Point3, Vec3},
     },
     hittable::HitRecord,
     texture::{SolidColor, Texture},
 };
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, _r_in: Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        _r_in: Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
         None
     }
     fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
         0.
     }
+    /// Whether surfaces with this material should be sampled as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    /// For debug output.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
     fn emitted(&self, _r_in: Ray, _rec: &HitRecord, _u: f64, _v: f64, _p: Point3) -> Color {
         Color::new(0., 0., 0.)
     }
@@ -33,7 +48,6 @@ where
 }
 
 impl<T: Texture + Clone> Lambertian<T> {
-    #[allow(dead_code)]
     pub fn new_arc(albedo: T) -> Self {
         Self { albedo }
     }
@@ -48,7 +62,12 @@ impl Lambertian<SolidColor> {
 }
 
 impl<T: Texture + Clone> Material for Lambertian<T> {
-    fn scatter(&self, _r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        _r_in: Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
         Some(ScatterRecord::new(
             None,
             self.albedo.value(rec.u, rec.v, rec.p),
@@ -65,29 +84,110 @@ impl<T: Texture + Clone> Material for Lambertian<T> {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Qualitative Oren-Nayar: a Lambertian with microfacet roughness `sigma`
+/// (radians), brighter toward grazing angles. `sigma = 0` is `Lambertian`.
+#[derive(Clone)]
+pub struct OrenNayar<T>
+where
+    T: Texture + Clone,
+{
+    albedo: T,
+    a: f64,
+    b: f64,
+}
+
+impl<T: Texture + Clone> OrenNayar<T> {
+    pub fn new_arc(albedo: T, sigma: f64) -> Self {
+        let sigma2 = sigma * sigma;
+        Self {
+            albedo,
+            a: 1. - 0.5 * sigma2 / (sigma2 + 0.33),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+}
+
+impl OrenNayar<SolidColor> {
+    pub fn new(a: Color, sigma: f64) -> Self {
+        Self::new_arc(SolidColor::new(a), sigma)
+    }
+}
+
+impl<T: Texture + Clone> Material for OrenNayar<T> {
+    fn scatter(
+        &self,
+        _r_in: Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::new(
+            None,
+            self.albedo.value(rec.u, rec.v, rec.p),
+            Some(CosPdf::new(rec.normal)),
+        ))
+    }
+    /// The cosine lobe scaled by `A + B max(0, cos(phi_i - phi_o)) sin(alpha) tan(beta)`,
+    /// so that times the albedo it is the BRDF times the cosine.
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let n = rec.normal;
+        let v = -r_in.dir.to_unit();
+        let l = scattered.dir.to_unit();
+        let cos_l = Vec3::dot(n, l);
+        if cos_l < 0. {
+            return 0.;
+        }
+        let cos_v = Vec3::dot(n, v).clamp(0., 1.);
+        let (v_t, l_t) = (v - n * cos_v, l - n * cos_l);
+        let tangent_lengths = v_t.length() * l_t.length();
+        let cos_phi = if tangent_lengths > 1e-12 {
+            (Vec3::dot(v_t, l_t) / tangent_lengths).max(0.)
+        } else {
+            0.
+        };
+        // alpha is the larger of the two polar angles, beta the smaller.
+        let (cos_alpha, cos_beta) = (cos_l.min(cos_v), cos_l.max(cos_v));
+        let sin_alpha = (1. - cos_alpha * cos_alpha).sqrt();
+        let tan_beta = (1. - cos_beta * cos_beta).sqrt() / cos_beta.max(1e-6);
+        cos_l / PI * (self.a + self.b * cos_phi * sin_alpha * tan_beta)
+    }
+}
+
+#[derive(Clone)]
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    // Per-hit fuzz from the red channel, replacing `fuzz` when set.
+    fuzz_texture: Option<Arc<dyn Texture>>,
 }
 
 impl Metal {
-    #[allow(dead_code)]
     pub fn new(a: Color, f: f64) -> Self {
         Self {
             albedo: a,
             fuzz: if f < 1. { f } else { 1. },
+            fuzz_texture: None,
+        }
+    }
+    pub fn new_textured(albedo: Color, fuzz_texture: Arc<dyn Texture>) -> Self {
+        Self {
+            albedo,
+            fuzz: 0.,
+            fuzz_texture: Some(fuzz_texture),
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let reflected = reflect(r_in.dir.to_unit(), rec.normal);
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let reflected = r_in.dir.to_unit().reflect_about(rec.normal);
+        let fuzz = match &self.fuzz_texture {
+            Some(texture) => texture.value(rec.u, rec.v, rec.p).x.clamp(0., 1.),
+            None => self.fuzz,
+        };
         Some(ScatterRecord::new(
             Some(Ray::new(
                 rec.p,
-                reflected + random_in_unit_sphere() * self.fuzz,
+                reflected + random_in_unit_sphere(rng) * fuzz,
                 0.,
             )),
             self.albedo,
@@ -96,18 +196,174 @@ impl Material for Metal {
     }
 }
 
+/// Microfacet BRDF: GGX distribution, Smith shadowing and Schlick Fresnel,
+/// over a Lambertian base that fades out as `metalness` goes to 1.
+#[derive(Clone, Copy)]
+pub struct CookTorrance {
+    base_color: Color,
+    roughness: f64,
+    metalness: f64,
+}
+
+impl CookTorrance {
+    pub fn new(base_color: Color, roughness: f64, metalness: f64) -> Self {
+        Self {
+            base_color,
+            roughness: roughness.clamp(0., 1.),
+            metalness: metalness.clamp(0., 1.),
+        }
+    }
+
+    fn alpha(&self) -> f64 {
+        (self.roughness * self.roughness).max(1e-4)
+    }
+
+    // Chance of sampling the GGX lobe rather than the cosine one.
+    fn specular_probability(&self) -> f64 {
+        0.5 + 0.5 * self.metalness
+    }
+
+    fn ggx_d(alpha: f64, n_dot_h: f64) -> f64 {
+        let a2 = alpha * alpha;
+        let denom = n_dot_h * n_dot_h * (a2 - 1.) + 1.;
+        a2 / (PI * denom * denom)
+    }
+
+    fn smith_g1(alpha: f64, n_dot_x: f64) -> f64 {
+        let a2 = alpha * alpha;
+        2. * n_dot_x / (n_dot_x + (a2 + (1. - a2) * n_dot_x * n_dot_x).sqrt())
+    }
+
+    /// BRDF times the cosine at `to_light`, for unit directions pointing away
+    /// from the surface.
+    fn eval(&self, normal: Vec3, to_eye: Vec3, to_light: Vec3) -> Color {
+        let n_dot_v = Vec3::dot(normal, to_eye);
+        let n_dot_l = Vec3::dot(normal, to_light);
+        if n_dot_v <= 0. || n_dot_l <= 0. {
+            return Color::new(0., 0., 0.);
+        }
+        let half = (to_eye + to_light).to_unit();
+        let n_dot_h = Vec3::dot(normal, half).max(0.);
+        let v_dot_h = Vec3::dot(to_eye, half).max(0.);
+        let alpha = self.alpha();
+
+        let f0 =
+            Color::new(0.04, 0.04, 0.04) * (1. - self.metalness) + self.base_color * self.metalness;
+        let fresnel = f0 + (Color::new(1., 1., 1.) - f0) * (1. - v_dot_h).powi(5);
+        let shadowing =
+            CookTorrance::smith_g1(alpha, n_dot_v) * CookTorrance::smith_g1(alpha, n_dot_l);
+        let specular = fresnel * (CookTorrance::ggx_d(alpha, n_dot_h) * shadowing / (4. * n_dot_v));
+        let diffuse = self.base_color * ((1. - self.metalness) * n_dot_l / PI);
+        specular + diffuse
+    }
+
+    /// Density of the directions `scatter` draws.
+    fn pdf(&self, normal: Vec3, to_eye: Vec3, to_light: Vec3) -> f64 {
+        let n_dot_l = Vec3::dot(normal, to_light);
+        if n_dot_l <= 0. {
+            return 0.;
+        }
+        let half = (to_eye + to_light).to_unit();
+        let n_dot_h = Vec3::dot(normal, half).max(0.);
+        let v_dot_h = Vec3::dot(to_eye, half);
+        let specular = if v_dot_h > 0. {
+            CookTorrance::ggx_d(self.alpha(), n_dot_h) * n_dot_h / (4. * v_dot_h)
+        } else {
+            0.
+        };
+        let chance = self.specular_probability();
+        chance * specular + (1. - chance) * n_dot_l / PI
+    }
+}
+
+impl Material for CookTorrance {
+    /// Picks the GGX or the cosine lobe, then weights by the BRDF of both over
+    /// the pdf of both, so the `specular_ray` attenuation is `f * cos / pdf`.
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let unit_direction = r_in.dir.to_unit();
+        let uvw = Onb::build_from_w(rec.normal);
+        let (r1, r2): (f64, f64) = (rng.gen(), rng.gen());
+        let phi = 2. * PI * r2;
+        let to_light = if rng.gen::<f64>() < self.specular_probability() {
+            // Half vector from the GGX distribution of normals.
+            let cos_theta = ((1. - r1) / (1. + (self.alpha().powi(2) - 1.) * r1)).sqrt();
+            let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+            let half = uvw.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            unit_direction.reflect_about(half)
+        } else {
+            let sin_theta = r1.sqrt();
+            uvw.local(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                (1. - r1).sqrt(),
+            )
+        };
+        let pdf = self.pdf(rec.normal, -unit_direction, to_light);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(ScatterRecord::new(
+            Some(Ray::new(rec.p, to_light, r_in.tm)),
+            self.eval(rec.normal, -unit_direction, to_light) / pdf,
+            None,
+        ))
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.pdf(rec.normal, -r_in.dir.to_unit(), scattered.dir.to_unit())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Lobe {
+    Reflection,
+    Transmission,
+}
+
+/// Picks reflection with probability equal to the Fresnel `reflectance` and
+/// transmission otherwise. Also returns the probability of the chosen lobe, so
+/// layered materials can divide that lobe's contribution by it.
+pub fn sample_fresnel_lobe(reflectance: f64, rng: &mut dyn RngCore) -> (Lobe, f64) {
+    let reflectance = reflectance.clamp(0., 1.);
+    if rng.gen_range(0.0..1.0) < reflectance {
+        (Lobe::Reflection, reflectance)
+    } else {
+        (Lobe::Transmission, 1. - reflectance)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Dielectric {
     pub ir: f64,
+    // Frosted glass: jitters the scattered direction like `Metal`'s fuzz.
+    pub roughness: f64,
+    // Beer-Lambert absorption per unit of path inside the glass. The color is
+    // what gets absorbed, so (0, 1, 1) tints red.
+    pub attenuation_color: Color,
+    pub absorption: f64,
 }
 
+/// Jitters `Dielectric` draws for a rough direction on the right side of the
+/// surface before falling back to the smooth one.
+const ROUGH_GLASS_TRIES: usize = 8;
+
 impl Dielectric {
-    #[allow(dead_code)]
     pub fn new(index_of_refraction: f64) -> Self {
         Self {
             ir: index_of_refraction,
+            roughness: 0.,
+            attenuation_color: Color::new(0., 0., 0.),
+            absorption: 0.,
         }
     }
+    pub fn with_absorption(mut self, attenuation_color: Color, absorption: f64) -> Self {
+        self.attenuation_color = attenuation_color;
+        self.absorption = absorption.max(0.);
+        self
+    }
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = roughness.clamp(0., 1.);
+        self
+    }
 
     fn reflectance(cos: f64, ref_idx: f64) -> f64 {
         let mut r0 = (1. - ref_idx) / (1. + ref_idx);
@@ -117,25 +373,118 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face {
             1. / self.ir
         } else {
             self.ir
         };
         let unit_direction = r_in.dir.to_unit();
+        // Entering/exiting is decided by the geometric normal (front_face); the
+        // shading normal only bends the lobe, unless it faces away from the ray.
+        let normal = if Vec3::dot(-unit_direction, rec.normal) > 0. {
+            rec.normal
+        } else {
+            rec.geometric_normal
+        };
         // let refracted = refract(unit_direction, rec.normal, refraction_ratio);
-        let cos_theta = f64::min(Vec3::dot(-unit_direction, rec.normal), 1.);
+        let cos_theta = f64::min(Vec3::dot(-unit_direction, normal), 1.);
         let sin_theta = (1. - cos_theta.powi(2)).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.;
 
-        let mut rng = rand::thread_rng();
-        let random_double: f64 = rng.gen_range(0.0..1.0);
-        let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > random_double
-        {
-            reflect(unit_direction, rec.normal)
+        let lobe = if cannot_refract {
+            Lobe::Reflection
+        } else {
+            // Fresnel weight and selection pdf cancel out for a bare dielectric.
+            sample_fresnel_lobe(Dielectric::reflectance(cos_theta, refraction_ratio), rng).0
+        };
+        let direction = match lobe {
+            Lobe::Reflection => unit_direction.reflect_about(normal),
+            Lobe::Transmission => refract(unit_direction, normal, refraction_ratio),
+        };
+        // Smooth glass draws nothing here, so its sample sequence is unchanged.
+        let direction = if self.roughness > 0. {
+            // Reflections stay on the incoming side of the surface and
+            // transmissions cross it; redraw jitter that would swap them.
+            let incoming_side = Vec3::dot(-unit_direction, rec.geometric_normal).signum();
+            let wanted_side = match lobe {
+                Lobe::Reflection => incoming_side,
+                Lobe::Transmission => -incoming_side,
+            };
+            (0..ROUGH_GLASS_TRIES)
+                .map(|_| (direction + random_in_unit_sphere(rng) * self.roughness).to_unit())
+                .find(|jittered| Vec3::dot(*jittered, rec.geometric_normal) * wanted_side > 0.)
+                .unwrap_or(direction)
+        } else {
+            direction
+        };
+        // Leaving the glass: r_in started where the ray entered it.
+        let attenuation = if !rec.front_face && self.absorption > 0. {
+            let distance = rec.t * r_in.dir.length();
+            let optical_depth = self.attenuation_color * (self.absorption * distance);
+            Color::new(
+                (-optical_depth.x).exp(),
+                (-optical_depth.y).exp(),
+                (-optical_depth.z).exp(),
+            )
+        } else {
+            Color::new(1., 1., 1.)
+        };
+        Some(ScatterRecord::new(
+            Some(Ray::new(rec.p, direction, r_in.tm)),
+            attenuation,
+            None,
+        ))
+    }
+}
+
+/// Debug material: always reflects, with no fuzz and no Fresnel term.
+#[derive(Clone, Copy)]
+pub struct PerfectMirror;
+
+impl Material for PerfectMirror {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, _rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        Some(ScatterRecord::new(
+            Some(Ray::new(
+                rec.p,
+                r_in.dir.to_unit().reflect_about(rec.normal),
+                r_in.tm,
+            )),
+            Color::new(1., 1., 1.),
+            None,
+        ))
+    }
+}
+
+/// Debug material: always refracts, reflecting only on total internal
+/// reflection. Isolates the refraction branch of `Dielectric`.
+#[derive(Clone, Copy)]
+pub struct PerfectRefractor {
+    pub ir: f64,
+}
+
+impl PerfectRefractor {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            ir: index_of_refraction,
+        }
+    }
+}
+
+impl Material for PerfectRefractor {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, _rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        let refraction_ratio = if rec.front_face {
+            1. / self.ir
+        } else {
+            self.ir
+        };
+        let unit_direction = r_in.dir.to_unit();
+        let cos_theta = f64::min(Vec3::dot(-unit_direction, rec.normal), 1.);
+        let sin_theta = (1. - cos_theta.powi(2)).sqrt();
+
+        let direction = if refraction_ratio * sin_theta > 1. {
+            unit_direction.reflect_about(rec.normal)
         } else {
             refract(unit_direction, rec.normal, refraction_ratio)
         };
@@ -153,27 +502,47 @@ where
     T: Texture + Clone,
 {
     emit: T,
+    // Emit from the back face as well.
+    pub two_sided: bool,
+    // Scales the emitted texture value.
+    pub intensity: f64,
 }
 
 impl<T: Texture + Clone> DiffuseLight<T> {
-    #[allow(dead_code)]
     pub fn new_arc(emit: T) -> Self {
-        Self { emit }
+        Self {
+            emit,
+            two_sided: false,
+            intensity: 1.,
+        }
     }
 }
 
 impl DiffuseLight<SolidColor> {
     pub fn new(c: Color) -> Self {
+        Self::new_arc(SolidColor::new(c))
+    }
+    pub fn new_two_sided(c: Color) -> Self {
         Self {
-            emit: SolidColor::new(c),
+            two_sided: true,
+            ..Self::new(c)
+        }
+    }
+    pub fn with_intensity(c: Color, intensity: f64) -> Self {
+        Self {
+            intensity,
+            ..Self::new(c)
         }
     }
 }
 
 impl<T: Texture + Clone> Material for DiffuseLight<T> {
+    fn is_emissive(&self) -> bool {
+        true
+    }
     fn emitted(&self, _r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
-        if rec.front_face {
-            self.emit.value(u, v, p)
+        if rec.front_face || self.two_sided {
+            self.emit.value(u, v, p) * self.intensity
         } else {
             Color::new(0., 0., 0.)
         }
@@ -185,7 +554,6 @@ pub struct Isotropic<T>
 where
     T: Texture + Clone + Copy,
 {
-    #[allow(dead_code)]
     albedo: T,
 }
 
@@ -204,15 +572,152 @@ impl Isotropic<SolidColor> {
 }
 
 impl<T: Texture + Clone + Copy> Material for Isotropic<T> {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
         Some(ScatterRecord::new(
-            Some(Ray::new(rec.p, random_in_unit_sphere(), r_in.tm)),
+            Some(Ray::new(rec.p, random_in_unit_sphere(rng), r_in.tm)),
             self.albedo.value(rec.u, rec.v, rec.p),
             None,
         ))
     }
 }
 
+/// Shades back-facing hits exactly like front-facing ones, for thin
+/// single-sided geometry such as leaves or paper. The wrapped material always
+/// sees `front_face` set and a normal facing the incoming ray.
+#[derive(Clone, Copy)]
+pub struct DoubleSided<M>
+where
+    M: Material,
+{
+    mat: M,
+}
+
+impl<M: Material> DoubleSided<M> {
+    pub fn new(mat: M) -> Self {
+        Self { mat }
+    }
+
+    fn facing<'a>(r_in: Ray, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        let flip = |n: Vec3| {
+            if Vec3::dot(r_in.dir, n) > 0. {
+                -n
+            } else {
+                n
+            }
+        };
+        HitRecord {
+            p: rec.p,
+            normal: flip(rec.normal),
+            geometric_normal: flip(rec.geometric_normal),
+            t: rec.t,
+            u: rec.u,
+            v: rec.v,
+            tangent: rec.tangent,
+            bitangent: rec.bitangent,
+            front_face: true,
+            mat_ptr: rec.mat_ptr,
+        }
+    }
+}
+
+impl<M: Material> Material for DoubleSided<M> {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        self.mat
+            .scatter(r_in, &DoubleSided::<M>::facing(r_in, rec), rng)
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.mat
+            .scattering_pdf(r_in, &DoubleSided::<M>::facing(r_in, rec), scattered)
+    }
+    fn is_emissive(&self) -> bool {
+        self.mat.is_emissive()
+    }
+    fn name(&self) -> &'static str {
+        self.mat.name()
+    }
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.mat
+            .emitted(r_in, &DoubleSided::<M>::facing(r_in, rec), u, v, p)
+    }
+}
+
+/// Bends the shading normal by a tangent-space normal map, decoded from
+/// `[0, 1]` colors to `[-1, 1]` with blue along the surface normal, before the
+/// wrapped material sees the hit. A flat (0.5, 0.5, 1) map changes nothing.
+/// Red follows the surface's dp/du and green its dp/dv, so the map lines up
+/// with its texture; on shapes that don't record them the frame around the
+/// normal is arbitrary.
+#[derive(Clone)]
+pub struct NormalMapped {
+    mat: Arc<dyn Material>,
+    normal_map: Arc<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new(mat: Arc<dyn Material>, normal_map: Arc<dyn Texture>) -> Self {
+        Self { mat, normal_map }
+    }
+
+    fn mapped<'a>(&self, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        let texel = self.normal_map.value(rec.u, rec.v, rec.p);
+        let local = Vec3::new(2. * texel.x - 1., 2. * texel.y - 1., 2. * texel.z - 1.);
+        let mut mapped = HitRecord {
+            p: rec.p,
+            normal: rec.normal,
+            geometric_normal: rec.geometric_normal,
+            t: rec.t,
+            u: rec.u,
+            v: rec.v,
+            tangent: rec.tangent,
+            bitangent: rec.bitangent,
+            front_face: rec.front_face,
+            mat_ptr: rec.mat_ptr,
+        };
+        if local.length_squared() > 0. {
+            let (tangent, bitangent) = tangent_frame(rec);
+            let normal = (tangent * local.x + bitangent * local.y + rec.normal * local.z).to_unit();
+            mapped.set_shading_normal(normal);
+        }
+        mapped
+    }
+}
+
+/// Unit tangent and bitangent around `rec.normal`, along dp/du and dp/dv
+/// where the hit has them. Mirrored UVs flip the bitangent.
+fn tangent_frame(rec: &HitRecord) -> (Vec3, Vec3) {
+    let n = rec.normal;
+    let tangent = rec.tangent - n * Vec3::dot(n, rec.tangent);
+    if tangent.length_squared() <= 1e-12 * rec.tangent.length_squared() {
+        let uvw = Onb::build_from_w(n);
+        return (uvw.u(), uvw.v());
+    }
+    let tangent = tangent.to_unit();
+    let bitangent = Vec3::cross(n, tangent);
+    if Vec3::dot(bitangent, rec.bitangent) < 0. {
+        (tangent, -bitangent)
+    } else {
+        (tangent, bitangent)
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        self.mat.scatter(r_in, &self.mapped(rec), rng)
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.mat.scattering_pdf(r_in, &self.mapped(rec), scattered)
+    }
+    fn is_emissive(&self) -> bool {
+        self.mat.is_emissive()
+    }
+    fn name(&self) -> &'static str {
+        self.mat.name()
+    }
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.mat.emitted(r_in, rec, u, v, p)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct ScatterRecord {
     pub specular_ray: Option<Ray>,
@@ -229,3 +734,459 @@ impl ScatterRecord {
         }
     }
 }
+
+#[derive(Clone)]
+pub struct MaterialHandle {
+    ptr: Arc<dyn Material>,
+}
+
+impl MaterialHandle {
+    pub fn new<M: Material + 'static>(mat: M) -> Self {
+        Self { ptr: Arc::new(mat) }
+    }
+    pub fn from_arc(ptr: Arc<dyn Material>) -> Self {
+        Self { ptr }
+    }
+}
+
+impl Material for MaterialHandle {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord, rng: &mut dyn RngCore) -> Option<ScatterRecord> {
+        self.ptr.scatter(r_in, rec, rng)
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.ptr.scattering_pdf(r_in, rec, scattered)
+    }
+    fn is_emissive(&self) -> bool {
+        self.ptr.is_emissive()
+    }
+    fn name(&self) -> &'static str {
+        self.ptr.name()
+    }
+    fn emitted(&self, r_in: Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.ptr.emitted(r_in, rec, u, v, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{pdf::Pdf, sampler::SampleSequence};
+    use crate::hittable::{aarect::XYRect, boxes::Boxes, triangle::Triangle, Hittable};
+
+    /// The same tangent-space normal everywhere.
+    #[derive(Clone, Copy)]
+    struct Tilt(Vec3);
+
+    impl Texture for Tilt {
+        fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+            (self.0 + 1.) / 2.
+        }
+    }
+
+    fn mapped_normal<H: Hittable>(object: &H, tilt: Vec3) -> Vec3 {
+        let r = Ray::new(Point3::new(0.25, 0.25, 1.), Vec3::new(0., 0., -1.), 0.);
+        let rec = object.hit(r, 0.001, f64::INFINITY).unwrap();
+        let gray = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        NormalMapped::new(gray, Arc::new(Tilt(tilt)))
+            .mapped(&rec)
+            .normal
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn normal_maps_follow_the_uvs() {
+        let gray = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let rect = XYRect::new(0., 1., 0., 1., 0., gray.clone());
+        let s = 0.5f64.sqrt();
+        assert_close(
+            mapped_normal(&rect, Vec3::new(0., 0., 1.)),
+            Vec3::new(0., 0., 1.),
+        );
+        // Red leans toward +u, which runs along x; green toward +v, along y.
+        assert_close(
+            mapped_normal(&rect, Vec3::new(s, 0., s)),
+            Vec3::new(s, 0., s),
+        );
+        assert_close(
+            mapped_normal(&rect, Vec3::new(0., s, s)),
+            Vec3::new(0., s, s),
+        );
+
+        // A triangle whose u runs along -y and v along -x.
+        let triangle = Triangle::new(
+            Point3::new(1., 1., 0.),
+            Point3::new(1., -1., 0.),
+            Point3::new(-1., 1., 0.),
+            gray,
+        )
+        .with_uv([(0., 0.), (1., 0.), (0., 1.)]);
+        assert_close(
+            mapped_normal(&triangle, Vec3::new(s, 0., s)),
+            Vec3::new(0., -s, s),
+        );
+        assert_close(
+            mapped_normal(&triangle, Vec3::new(0., s, s)),
+            Vec3::new(-s, 0., s),
+        );
+    }
+
+    #[test]
+    fn fresnel_lobes_follow_reflectance() {
+        let n = 20000;
+        for &cos in &[1., 0.3, 0.05] {
+            let reflectance = Dielectric::reflectance(cos, 1. / 1.5);
+            let (mut reflected, mut weight) = (0, 0.);
+            for i in 0..n {
+                let mut rng = SampleSequence::new(i, 0, 0, 1);
+                let (lobe, prob) = sample_fresnel_lobe(reflectance, &mut rng);
+                if lobe == Lobe::Reflection {
+                    reflected += 1;
+                    weight += reflectance / prob;
+                }
+            }
+            // Within four standard deviations of a binomial.
+            let fraction = reflected as f64 / n as f64;
+            let sigma = (reflectance * (1. - reflectance) / n as f64).sqrt();
+            assert!(
+                (fraction - reflectance).abs() < 4. * sigma + 1e-9,
+                "{} {}",
+                fraction,
+                reflectance
+            );
+            // Dividing by the lobe's probability gives back the reflectance.
+            assert!((weight / n as f64 - reflectance).abs() < 4. * sigma + 1e-9);
+        }
+        assert!(Dielectric::reflectance(0.05, 1. / 1.5) > 0.6);
+    }
+
+    #[test]
+    fn cook_torrance_pdf_integrates_to_what_scatter_keeps() {
+        let rect = |mat| XYRect::new(-1., 1., -1., 1., 0., mat);
+        let r = Ray::new(Point3::new(0.6, 0., 0.8), Vec3::new(-0.6, 0., -0.8), 0.);
+        let n = 200_000;
+        for &(roughness, metalness) in &[(0.3, 0.), (0.6, 1.), (1., 0.5)] {
+            let mat = CookTorrance::new(Color::new(0.8, 0.8, 0.8), roughness, metalness);
+            let object = rect(mat);
+            let rec = object.hit(r, 0.001, f64::INFINITY).unwrap();
+            let (mut integral, mut kept) = (0., 0);
+            for i in 0..n {
+                let mut rng = SampleSequence::new(i, 0, 0, 3);
+                let dir = Vec3::random_unit_vector(&mut rng);
+                integral += mat.pdf(rec.normal, -r.dir, dir) * 4. * PI / n as f64;
+                if mat.scatter(r, &rec, &mut rng).is_some() {
+                    kept += 1;
+                }
+            }
+            // GGX reflections that would go under the surface are dropped, so
+            // the density over the upper hemisphere is what's left.
+            let kept = kept as f64 / n as f64;
+            assert!(integral < 1.02, "{} {}: {}", roughness, metalness, integral);
+            assert!(
+                (integral - kept).abs() < 0.02,
+                "{} {}: {} against {}",
+                roughness,
+                metalness,
+                integral,
+                kept
+            );
+        }
+    }
+
+    #[test]
+    fn white_rough_metal_passes_the_furnace_test() {
+        // Under a uniform white sky, a white surface can only lose light.
+        let mat = CookTorrance::new(Color::new(1., 1., 1.), 0.5, 1.);
+        let rect = XYRect::new(-1., 1., -1., 1., 0., mat);
+        let r = Ray::new(Point3::new(0.3, 0., 1.), Vec3::new(-0.3, 0., -1.), 0.);
+        let rec = rect.hit(r, 0.001, f64::INFINITY).unwrap();
+        let n = 20000;
+        let reflected = (0..n)
+            .map(|i| {
+                let mut rng = SampleSequence::new(i, 0, 0, 5);
+                mat.scatter(r, &rec, &mut rng)
+                    .map_or(0., |srec| srec.attenuation.x)
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!(reflected > 0.85 && reflected < 1.01, "{}", reflected);
+    }
+
+    /// `scattering_pdf` at the origin of a floor facing +z, seen from `to_eye`
+    /// and lit from `to_light`.
+    fn floor_pdf<M: Material + Clone>(mat: &M, to_eye: Vec3, to_light: Vec3) -> f64 {
+        let floor = XYRect::new(-1., 1., -1., 1., 0., mat.clone());
+        let r_in = Ray::new(to_eye, -to_eye, 0.);
+        let rec = floor.hit(r_in, 0.001, f64::INFINITY).unwrap();
+        mat.scattering_pdf(r_in, &rec, Ray::new(rec.p, to_light, 0.))
+    }
+
+    /// The direction `degrees` from +z, toward `azimuth` in the floor.
+    fn polar(degrees: f64, azimuth: f64) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (sin_phi, cos_phi) = azimuth.sin_cos();
+        Vec3::new(sin * cos_phi, sin * sin_phi, cos)
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let color = Color::new(0.6, 0.5, 0.4);
+        let smooth = OrenNayar::new(color, 0.);
+        let lambertian = Lambertian::new(color);
+        for &(eye, light, azimuth) in &[(0., 30., 0.), (60., 20., 1.), (80., 85., 3.)] {
+            let (to_eye, to_light) = (polar(eye, 0.), polar(light, azimuth));
+            let expected = floor_pdf(&lambertian, to_eye, to_light);
+            assert!((floor_pdf(&smooth, to_eye, to_light) - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rough_oren_nayar_brightens_toward_grazing() {
+        let rough = OrenNayar::new(Color::new(0.5, 0.5, 0.5), 0.5);
+        // Lit from behind the eye: the BRDF, the pdf over the cosine, grows
+        // as both tilt away from the normal.
+        let brdf = |degrees: f64| {
+            let direction = polar(degrees, 0.);
+            floor_pdf(&rough, direction, direction) / direction.z
+        };
+        let angles = [0., 20., 40., 60., 80.];
+        for pair in angles.windows(2) {
+            assert!(brdf(pair[1]) > brdf(pair[0]), "{:?}", pair);
+        }
+        assert!(brdf(0.) < 1. / PI && brdf(80.) > 1. / PI);
+    }
+
+    #[test]
+    fn rough_glass_keeps_reflections_above_the_surface() {
+        let glass = Dielectric::new(1.5).with_roughness(0.8);
+        let floor = XYRect::new(-1., 1., -1., 1., 0., glass);
+        // 80 degrees from the normal, where jitter easily crosses the surface.
+        let r = Ray::new(polar(80., 0.), -polar(80., 0.), 0.);
+        let rec = floor.hit(r, 0.001, f64::INFINITY).unwrap();
+        let n = 4000;
+        let (mut reflected, mut spread) = (0, 0.);
+        for i in 0..n {
+            let mut rng = SampleSequence::new(i, 0, 0, 2);
+            let dir = glass
+                .scatter(r, &rec, &mut rng)
+                .unwrap()
+                .specular_ray
+                .unwrap()
+                .dir;
+            if dir.z > 0. {
+                reflected += 1;
+                spread += (dir - r.dir.reflect_about(rec.normal)).length();
+            }
+        }
+        // Directions above the surface are exactly the Fresnel reflections;
+        // none were pushed under it, nor transmissions pushed out.
+        let fresnel = Dielectric::reflectance(polar(80., 0.).z, 1. / 1.5);
+        let fraction = reflected as f64 / n as f64;
+        let sigma = (fresnel * (1. - fresnel) / n as f64).sqrt();
+        assert!(
+            (fraction - fresnel).abs() < 4. * sigma,
+            "{} against {}",
+            fraction,
+            fresnel
+        );
+        // And roughness does spread them out.
+        assert!(spread / reflected as f64 > 0.1);
+    }
+
+    #[test]
+    fn glass_falls_back_to_the_geometric_normal_when_the_mapped_one_faces_away() {
+        let glass = Dielectric::new(1.5);
+        let floor = XYRect::new(-1., 1., -1., 1., 0., glass);
+        let from = polar(70., 0.);
+        let r_in = Ray::new(from, -from, 0.);
+        let mut rec = floor.hit(r_in, 0.001, f64::INFINITY).unwrap();
+        // Tilted away from the ray, as a strong normal map might leave it.
+        rec.normal = polar(70., PI);
+        assert!(Vec3::dot(from, rec.normal) < 0.);
+
+        let reflected = Vec3::new(-from.x, -from.y, from.z).to_unit();
+        let refracted = refract(-from.to_unit(), Vec3::new(0., 0., 1.), 1. / 1.5);
+        let (mut reflections, mut refractions) = (0, 0);
+        for i in 0..200 {
+            let mut rng = SampleSequence::new(i, 0, 0, 6);
+            let out = glass.scatter(r_in, &rec, &mut rng).unwrap();
+            let dir = out.specular_ray.unwrap().dir;
+            if (dir - reflected).length() < 1e-9 {
+                reflections += 1;
+            } else {
+                assert_close(dir, refracted);
+                refractions += 1;
+            }
+        }
+        // About 17% reflect at 70 degrees.
+        assert!(
+            reflections > 10 && refractions > 100,
+            "{} {}",
+            reflections,
+            refractions
+        );
+    }
+
+    #[test]
+    fn absorbing_slabs_follow_beer_lambert() {
+        let tint = Color::new(0., 0.5, 1.);
+        let (density, thickness) = (0.8, 2.5);
+        let glass = Dielectric::new(1.5).with_absorption(tint, density);
+        let slab = Boxes::new(
+            Point3::new(-5., -5., -thickness),
+            Point3::new(5., 5., 0.),
+            glass,
+        );
+        // From where the ray entered the top, straight through to the bottom.
+        let inside = Ray::new(Point3::new(0.5, 0.5, 0.), Vec3::new(0., 0., -2.), 0.);
+        let rec = slab.hit(inside, 0.001, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        let mut rng = SampleSequence::new(0, 0, 0, 1);
+        let attenuation = glass.scatter(inside, &rec, &mut rng).unwrap().attenuation;
+        let expected = |c: f64| (-c * density * thickness).exp();
+        assert!(
+            (attenuation - Color::new(expected(0.), expected(0.5), expected(1.))).length() < 1e-9
+        );
+
+        // Entering costs nothing.
+        let outside = Ray::new(Point3::new(0.5, 0.5, 1.), Vec3::new(0., 0., -1.), 0.);
+        let rec = slab.hit(outside, 0.001, f64::INFINITY).unwrap();
+        let attenuation = glass.scatter(outside, &rec, &mut rng).unwrap().attenuation;
+        assert!((attenuation - Color::new(1., 1., 1.)).length() < 1e-12);
+    }
+
+    /// What `light` gives a ray hitting the floor at z = 0 from above, then
+    /// from below.
+    fn front_and_back<M: Material + Clone>(light: &M) -> (Color, Color) {
+        let floor = XYRect::new(-1., 1., -1., 1., 0., light.clone());
+        let emitted = |from_z: f64| {
+            let r = Ray::new(
+                Point3::new(0.2, 0.3, from_z),
+                Vec3::new(0., 0., -from_z),
+                0.,
+            );
+            let rec = floor.hit(r, 0.001, f64::INFINITY).unwrap();
+            light.emitted(r, &rec, rec.u, rec.v, rec.p)
+        };
+        (emitted(1.), emitted(-1.))
+    }
+
+    #[test]
+    fn only_two_sided_lights_shine_from_the_back() {
+        let white = Color::new(2., 2., 2.);
+        let (front, back) = front_and_back(&DiffuseLight::new(white));
+        assert_close(front, white);
+        assert_close(back, Color::new(0., 0., 0.));
+        let (front, back) = front_and_back(&DiffuseLight::new_two_sided(white));
+        assert_close(front, white);
+        assert_close(back, white);
+    }
+
+    #[test]
+    fn intensity_scales_emission() {
+        let color = Color::new(1., 0.5, 0.25);
+        let (front, back) = front_and_back(&DiffuseLight::with_intensity(color, 8.));
+        assert_close(front, Color::new(8., 4., 2.));
+        assert_close(back, Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn double_sided_backs_shade_like_fronts() {
+        let white = Color::new(2., 2., 2.);
+        let (front, back) = front_and_back(&DoubleSided::new(DiffuseLight::new(white)));
+        assert_close(front, white);
+        assert_close(back, white);
+
+        // Mirrored through the floor, the same path scatters the same.
+        let mat = DoubleSided::new(OrenNayar::new(Color::new(0.5, 0.5, 0.5), 0.4));
+        let floor = XYRect::new(-1., 1., -1., 1., 0., mat.clone());
+        let below = |v: Vec3| Vec3::new(v.x, v.y, -v.z);
+        let (to_eye, to_light) = (polar(50., 0.), polar(30., 2.));
+        let pdf = |to_eye: Vec3, to_light: Vec3| {
+            let r_in = Ray::new(to_eye, -to_eye, 0.);
+            let rec = floor.hit(r_in, 0.001, f64::INFINITY).unwrap();
+            let mut rng = SampleSequence::new(0, 0, 0, 1);
+            let scattered = mat.scatter(r_in, &rec, &mut rng).unwrap();
+            // The cosine lobe opens toward the eye's side, either way up.
+            assert!(scattered.pdf_ptr.unwrap().value(to_light) > 0.);
+            mat.scattering_pdf(r_in, &rec, Ray::new(rec.p, to_light, 0.))
+        };
+        let front = pdf(to_eye, to_light);
+        assert!(front > 0.);
+        assert!((pdf(below(to_eye), below(to_light)) - front).abs() < 1e-12);
+    }
+
+    /// Where `mat` sends a ray arriving at the floor at z = 0 from `from`.
+    fn bounce<M: Material + Clone>(mat: &M, from: Vec3) -> Vec3 {
+        let floor = XYRect::new(-1., 1., -1., 1., 0., mat.clone());
+        let r_in = Ray::new(from, -from, 0.);
+        let rec = floor.hit(r_in, 0.001, f64::INFINITY).unwrap();
+        let mut rng = SampleSequence::new(0, 0, 0, 1);
+        let srec = mat.scatter(r_in, &rec, &mut rng).unwrap();
+        srec.specular_ray.unwrap().dir
+    }
+
+    #[test]
+    fn perfect_mirrors_reflect_about_the_normal() {
+        for &degrees in &[0., 30., 85.] {
+            let from = polar(degrees, 0.7);
+            assert_close(
+                bounce(&PerfectMirror, from),
+                Vec3::new(-from.x, -from.y, from.z),
+            );
+        }
+    }
+
+    #[test]
+    fn perfect_refractors_reflect_only_past_the_critical_angle() {
+        let glass = PerfectRefractor::new(1.5);
+        let below = |v: Vec3| Vec3::new(v.x, v.y, -v.z);
+        // Entering always refracts, bending toward the normal.
+        for &degrees in &[0., 45., 89.] {
+            let out = bounce(&glass, polar(degrees, 0.));
+            assert!(out.z < 0. && out.x <= 1e-12);
+            assert!((-out.x).asin().to_degrees() <= degrees + 1e-9);
+        }
+        // Leaving, past the critical angle of about 41.8 degrees, it reflects.
+        let out = bounce(&glass, below(polar(30., 0.)));
+        assert!(out.z > 0.);
+        assert!((out.x.abs() - 1.5 * 0.5).abs() < 1e-9);
+        let from = below(polar(45., 0.));
+        assert_close(bounce(&glass, from), Vec3::new(-from.x, -from.y, from.z));
+    }
+
+    /// Red is 0 on the left half of a unit square's UVs and `u` on the right.
+    struct FuzzRight;
+
+    impl Texture for FuzzRight {
+        fn value(&self, u: f64, _v: f64, _p: Point3) -> Color {
+            let red = if u < 0.5 { 0. } else { u };
+            Color::new(red, 0.3, 0.3)
+        }
+    }
+
+    #[test]
+    fn textured_fuzz_follows_the_red_channel() {
+        let metal = Metal::new_textured(Color::new(0.9, 0.9, 0.9), Arc::new(FuzzRight));
+        let floor = XYRect::new(0., 1., 0., 1., 0., metal.clone());
+        // Mean distance of reflections at `x` from the perfect one.
+        let spread = |x: f64| {
+            let r_in = Ray::new(Point3::new(x - 0.3, 0.5, 1.), Vec3::new(0.3, 0., -1.), 0.);
+            let rec = floor.hit(r_in, 0.001, f64::INFINITY).unwrap();
+            let mirror = r_in.dir.to_unit().reflect_about(rec.normal);
+            (0..500)
+                .map(|i| {
+                    let mut rng = SampleSequence::new(i, 0, 0, 4);
+                    let srec = metal.scatter(r_in, &rec, &mut rng).unwrap();
+                    (srec.specular_ray.unwrap().dir - mirror).length()
+                })
+                .sum::<f64>()
+                / 500.
+        };
+        assert!(spread(0.1) < 1e-12 && spread(0.3) < 1e-12);
+        let (some, more) = (spread(0.6), spread(0.95));
+        assert!(some > 0.1 && more > some * 1.3, "{} {}", some, more);
+    }
+}
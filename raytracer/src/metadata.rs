@@ -0,0 +1,166 @@
+use std::{fs, io, path::Path, process::Command, time::Duration};
+
+use crate::basic::{camera::Camera, vec::Vec3};
+use crate::render::RenderSettings;
+
+/// Writes `<image>.json` next to the image, describing how it was rendered.
+pub fn write_metadata(
+    image_path: &str,
+    settings: &RenderSettings,
+    cam: &Camera,
+    scene: &str,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let cam = cam.params();
+    let git_hash = match git_hash() {
+        Some(hash) => json_string(&hash),
+        None => "null".to_string(),
+    };
+
+    let seed = match settings.seed {
+        Some(seed) => seed.to_string(),
+        None => "null".to_string(),
+    };
+
+    let mut json = String::new();
+    json += "{\n";
+    json += &format!("  \"image\": {},\n", json_string(image_path));
+    json += &format!("  \"scene\": {},\n", json_string(scene));
+    json += &format!("  \"width\": {},\n", settings.image_width);
+    json += &format!("  \"height\": {},\n", settings.image_height);
+    json += &format!("  \"samples_per_pixel\": {},\n", settings.samples_per_pixel);
+    json += &format!("  \"max_depth\": {},\n", settings.max_depth);
+    json += &format!("  \"threads\": {},\n", settings.thread_number);
+    json += &format!("  \"seed\": {},\n", seed);
+    // Straight up; exact for solid backgrounds.
+    let zenith = settings.background.sample(Vec3::new(0., 1., 0.));
+    json += &format!("  \"background\": {},\n", json_vec(zenith));
+    json += "  \"camera\": {\n";
+    json += &format!("    \"lookfrom\": {},\n", json_vec(cam.lookfrom));
+    json += &format!("    \"lookat\": {},\n", json_vec(cam.lookat));
+    json += &format!("    \"vup\": {},\n", json_vec(cam.vup));
+    json += &format!("    \"vfov\": {},\n", cam.vfov);
+    json += &format!("    \"aspect_ratio\": {},\n", cam.aspect_ratio);
+    json += &format!("    \"aperture\": {},\n", cam.aperture);
+    json += &format!("    \"focus_dist\": {},\n", cam.focus_dist);
+    json += &format!("    \"focus_dist1\": {},\n", cam.focus_dist1);
+    json += &format!("    \"time0\": {},\n", cam.time0);
+    json += &format!("    \"time1\": {}\n", cam.time1);
+    json += "  },\n";
+    json += &format!("  \"git_hash\": {},\n", git_hash);
+    json += &format!("  \"elapsed_seconds\": {}\n", elapsed.as_secs_f64());
+    json += "}\n";
+
+    fs::write(Path::new(image_path).with_extension("json"), json)
+}
+
+fn git_hash() -> Option<String> {
+    let output = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn json_vec(v: Vec3) -> String {
+    format!("[{}, {}, {}]", v.x, v.y, v.z)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        basic::vec::{Color, Point3},
+        cli::Options,
+        hittable::{bvh::BvhNode, sphere::Sphere, HittableList},
+        json::Json,
+        material::Lambertian,
+        render::{render, write_ppm},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn sidecar_records_samples_and_seed() {
+        let args: Vec<String> = [
+            "raytracer",
+            "--write-metadata",
+            "--seed",
+            "42",
+            "--samples",
+            "3",
+            "--width",
+            "8",
+            "--height",
+            "6",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let options = Options::parse(&args).unwrap();
+        assert!(options.write_metadata);
+        let settings = RenderSettings {
+            image_width: options.width,
+            image_height: options.height,
+            samples_per_pixel: options.samples,
+            thread_number: 1,
+            seed: options.seed,
+            ..Default::default()
+        };
+
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 0., -1.),
+            0.5,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )));
+        let cam = Camera::new(
+            Point3::new(0., 0., 1.),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            60.,
+            8. / 6.,
+            0.,
+            2.,
+            0.,
+            1.,
+        );
+        let bvh = BvhNode::new_list(&world, 0., 1.);
+        // Light sampling needs at least one target.
+        let img = render(&bvh, &world, cam, &settings);
+
+        let dir = std::env::temp_dir().join(format!("raytracer-metadata-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("out.ppm");
+        let image_path = image_path.to_str().unwrap();
+        write_ppm(image_path, &img).unwrap();
+        write_metadata(image_path, &settings, &cam, "test", Duration::from_secs(1)).unwrap();
+
+        let text = fs::read_to_string(dir.join("out.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let doc = Json::parse(&text).unwrap();
+        assert_eq!(
+            doc.get("samples_per_pixel").and_then(Json::as_usize),
+            Some(3)
+        );
+        assert_eq!(doc.get("seed").and_then(Json::as_usize), Some(42));
+        assert_eq!(doc.get("width").and_then(Json::as_usize), Some(8));
+    }
+}
@@ -0,0 +1,36 @@
+//! Per-channel curves applied to the averaged linear color before encoding.
+
+/// Reinhard's operator, `x / (1 + x)`.
+pub fn reinhard(x: f64) -> f64 {
+    x / (1. + x)
+}
+
+/// Narkowicz's fit of the ACES filmic curve, clamped to `[0, 1]`.
+pub fn aces_filmic(x: f64) -> f64 {
+    ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reinhard_halves_one_and_approaches_white() {
+        assert!(reinhard(0.).abs() < 1e-12);
+        assert!((reinhard(1.) - 0.5).abs() < 1e-12);
+        assert!((reinhard(3.) - 0.75).abs() < 1e-12);
+        assert!(reinhard(1e6) < 1.);
+    }
+
+    #[test]
+    fn aces_rises_and_clamps_to_white() {
+        assert!(aces_filmic(0.).abs() < 1e-12);
+        // 2.54 / 3.16 at one.
+        assert!((aces_filmic(1.) - 0.8038).abs() < 1e-4);
+        let curve: Vec<f64> = (0..=400).map(|i| aces_filmic(i as f64 * 0.05)).collect();
+        assert!(curve.windows(2).all(|pair| pair[0] <= pair[1]));
+        // The fit overshoots 1 above about 7.25, which the clamp cuts off.
+        assert!((aces_filmic(20.) - 1.).abs() < 1e-12);
+        assert!((aces_filmic(1e6) - 1.).abs() < 1e-12);
+    }
+}
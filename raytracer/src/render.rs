@@ -0,0 +1,2832 @@
+use console::style;
+use image::{GrayImage, ImageBuffer, ImageResult, RgbImage, RgbaImage};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rand::{Rng, RngCore};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    process::exit,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use crate::aov::{self, Aov};
+use crate::background::Background;
+use crate::basic::sampler::{self, SampleSequence};
+use crate::basic::{camera::Camera, pdf::Pdf};
+use crate::basic::{pdf::HittablePdf, ray::Ray};
+use crate::basic::{
+    pdf::MixturePdf,
+    vec::{Color, Point3, Vec3},
+};
+use crate::denoise::{Denoiser, Framebuffer};
+use crate::hittable::bvh::BvhNode;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::postprocess;
+
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    pub thread_number: u32,
+    pub background: &'static dyn Background,
+    // Fill the alpha channel with the fraction of primary rays hitting the world.
+    pub alpha: bool,
+    pub russian_roulette: RussianRoulette,
+    pub tone_map: ToneMap,
+    // Derive every sample from its pixel and index so renders are reproducible.
+    pub seed: Option<u64>,
+    pub fit: Fit,
+    // Debug preview: albedo under a headlight at the first hit, no bounces or lights.
+    pub flat: bool,
+    pub parallel: Parallel,
+    // Applied to the averaged radiance before tone mapping.
+    pub denoiser: Option<&'static dyn Denoiser>,
+    // Fixed sub-pixel position of every primary ray instead of random jitter,
+    // for one-sample frames accumulated elsewhere (e.g. a TAA pattern).
+    pub jitter_offset: Option<(f64, f64)>,
+    // Paints how many samples each pixel gets instead of `samples_per_pixel`.
+    pub sample_map: Option<&'static SampleMap>,
+    // Caps the largest channel of each path sample's radiance. Applied to the
+    // finished sample, after Russian roulette has divided by its survival
+    // odds, so roulette alone stays unbiased; the clamp itself darkens
+    // bright paths (biased, by at most what exceeded the cap). Survivors of
+    // roulette carry their killed siblings' share, so with both on a cap
+    // near typical sample values takes off much more; keep it well above.
+    pub firefly_clamp: Option<f64>,
+    pub output_colorspace: ColorSpace,
+    // Stop sampling each pixel once it has converged. Replaces
+    // `samples_per_pixel` and `sample_map`; `Parallel::Samples` rejects it.
+    pub adaptive: Option<Adaptive>,
+}
+
+static BLACK: Color = Color {
+    x: 0.,
+    y: 0.,
+    z: 0.,
+};
+
+/// 500x500 at 100 samples and depth 50 on 8 threads, black background,
+/// everything optional off.
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            image_width: 500,
+            image_height: 500,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            thread_number: 8,
+            background: &BLACK,
+            alpha: false,
+            russian_roulette: RussianRoulette::Off,
+            tone_map: ToneMap::None,
+            seed: None,
+            fit: Fit::Stretch,
+            flat: false,
+            parallel: Parallel::Pixels,
+            denoiser: None,
+            jitter_offset: None,
+            sample_map: None,
+            firefly_clamp: None,
+            output_colorspace: ColorSpace::SrgbGamma,
+            adaptive: None,
+        }
+    }
+}
+
+/// What the render threads split between them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Parallel {
+    // Each thread takes a share of the rows.
+    Pixels,
+    // Each thread takes a share of every pixel's samples, for tiny images.
+    Samples,
+    // Threads take square tiles of this size from a shared queue.
+    Tiles(u32),
+}
+
+/// What to do when the image and camera aspect ratios differ.
+#[derive(Clone, Copy)]
+pub enum Fit {
+    // Squeeze the camera's view into the whole image.
+    Stretch,
+    // Keep the camera's aspect and fill the unused border with this color.
+    Letterbox(Color),
+}
+
+/// Per-pixel sample counts from a grayscale image: black pixels get
+/// `min_samples`, white ones `max_samples`, in between linearly. The map is
+/// stretched over the image, so it need not match the render size.
+pub struct SampleMap {
+    importance: GrayImage,
+    pub min_samples: i32,
+    pub max_samples: i32,
+}
+
+impl SampleMap {
+    pub fn new(importance: GrayImage, min_samples: i32, max_samples: i32) -> Self {
+        Self {
+            importance,
+            min_samples,
+            max_samples,
+        }
+    }
+
+    pub fn open(filename: &str, min_samples: i32, max_samples: i32) -> ImageResult<Self> {
+        let importance = image::open(filename)?.to_luma8();
+        Ok(Self::new(importance, min_samples, max_samples))
+    }
+
+    /// Samples for pixel `(x, y)`, counted from the bottom left, of a
+    /// `width` x `height` image.
+    pub fn samples(&self, x: u32, y: u32, width: u32, height: u32) -> i32 {
+        let (map_width, map_height) = self.importance.dimensions();
+        let mx = (x as u64 * map_width as u64 / width as u64) as u32;
+        let my = ((height - y - 1) as u64 * map_height as u64 / height as u64) as u32;
+        let brightness = self.importance.get_pixel(mx, my)[0] as f64 / 255.;
+        let range = (self.max_samples - self.min_samples) as f64;
+        self.min_samples + (brightness * range).round() as i32
+    }
+}
+
+/// Each pixel takes at least `min_samples` and at most `max_samples`, stopping
+/// in between once the standard error of its mean luminance (in linear
+/// radiance) is below `tolerance`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Adaptive {
+    pub min_samples: i32,
+    pub max_samples: i32,
+    pub tolerance: f64,
+}
+
+/// The pixels of the image the camera's view maps onto.
+#[derive(Clone, Copy)]
+struct Viewport {
+    x0: f64,
+    y0: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Viewport {
+    fn new(settings: &RenderSettings, cam: &Camera) -> Self {
+        let image_width = settings.image_width as f64;
+        let image_height = settings.image_height as f64;
+        let (width, height) = match settings.fit {
+            Fit::Stretch => (image_width, image_height),
+            Fit::Letterbox(_) => {
+                let aspect_ratio = cam.params().aspect_ratio;
+                if aspect_ratio > image_width / image_height {
+                    (image_width, (image_width / aspect_ratio).round())
+                } else {
+                    ((image_height * aspect_ratio).round(), image_height)
+                }
+            }
+        };
+        Self {
+            x0: ((image_width - width) / 2.).floor(),
+            y0: ((image_height - height) / 2.).floor(),
+            width,
+            height,
+        }
+    }
+
+    fn contains(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (x as f64, y as f64);
+        x >= self.x0 && x < self.x0 + self.width && y >= self.y0 && y < self.y0 + self.height
+    }
+
+    /// Camera coordinates of the point `(du, dv)` inside pixel `(x, y)`.
+    fn uv(&self, x: u32, y: u32, du: f64, dv: f64) -> (f64, f64) {
+        (
+            (x as f64 - self.x0 + du) / (self.width - 1.),
+            (y as f64 - self.y0 + dv) / (self.height - 1.),
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMap {
+    None,
+    // Compresses highlights instead of clipping them.
+    Reinhard,
+    // Filmic, with a nicer rolloff on bright emitters.
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            ToneMap::None => x,
+            ToneMap::Reinhard => postprocess::reinhard(x),
+            ToneMap::Aces => postprocess::aces_filmic(x),
+        }
+    }
+}
+
+/// Transfer function the 8-bit output is encoded with. sRGB and Rec.709
+/// share primaries, so only the curve differs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorSpace {
+    // The piecewise sRGB curve, linear near black.
+    SrgbGamma,
+    Linear,
+    // The Rec.709 camera OETF.
+    Rec709,
+    // A pure power curve, x^(1 / gamma); 1 is linear, 2.2 approximates sRGB.
+    // Only a positive gamma makes sense, see `ColorSpace::gamma`.
+    Gamma(f64),
+}
+
+impl ColorSpace {
+    /// `Gamma(gamma)`, or an error unless `gamma` is positive and finite.
+    pub fn gamma(gamma: f64) -> Result<Self, String> {
+        if gamma > 0. && gamma.is_finite() {
+            Ok(ColorSpace::Gamma(gamma))
+        } else {
+            Err(format!("gamma must be positive, not {}", gamma))
+        }
+    }
+
+    /// Encodes a linear value in `[0, 1]`.
+    pub fn encode(self, x: f64) -> f64 {
+        match self {
+            ColorSpace::SrgbGamma => {
+                if x <= 0.0031308 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1. / 2.4) - 0.055
+                }
+            }
+            ColorSpace::Linear => x,
+            ColorSpace::Rec709 => {
+                if x < 0.018 {
+                    4.5 * x
+                } else {
+                    1.099 * x.powf(0.45) - 0.099
+                }
+            }
+            ColorSpace::Gamma(gamma) => x.powf(1. / gamma),
+        }
+    }
+}
+
+/// How paths are randomly terminated once their throughput gets small.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RussianRoulette {
+    Off,
+    // Survive with the throughput's luminance.
+    Luminance,
+    // Survive with the throughput's largest channel, keeps saturated colors alive.
+    MaxComponent,
+}
+
+impl RussianRoulette {
+    pub fn survival(self, throughput: Color) -> f64 {
+        match self {
+            RussianRoulette::Off => 1.,
+            RussianRoulette::Luminance => {
+                (0.2126 * throughput.x + 0.7152 * throughput.y + 0.0722 * throughput.z)
+                    .clamp(0.05, 1.)
+            }
+            RussianRoulette::MaxComponent => throughput
+                .x
+                .max(throughput.y)
+                .max(throughput.z)
+                .clamp(0.05, 1.),
+        }
+    }
+
+    /// The survival odds if a path carrying `throughput` goes on, `None` if
+    /// it is terminated. Only draws from `rng` when roulette is on.
+    pub fn play(self, throughput: Color, rng: &mut dyn RngCore) -> Option<f64> {
+        if self == RussianRoulette::Off {
+            return Some(1.);
+        }
+        let survival = self.survival(throughput);
+        if rng.gen::<f64>() < survival {
+            Some(survival)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    BufferSize { expected: usize, actual: usize },
+    Stride { min: usize, actual: usize },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::BufferSize { expected, actual } => write!(
+                f,
+                "buffer too small: need {} bytes, got {}",
+                expected, actual
+            ),
+            RenderError::Stride { min, actual } => {
+                write!(f, "stride too small: need {} bytes, got {}", min, actual)
+            }
+        }
+    }
+}
+
+pub fn render(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+) -> RgbImage {
+    let settings = RenderSettings {
+        alpha: false,
+        ..*settings
+    };
+    let mut img: RgbImage = ImageBuffer::new(settings.image_width, settings.image_height);
+    let stride = 3 * settings.image_width as usize;
+    render_into(&mut img, stride, world, lights, cam, &settings)
+        .expect("an image always fits its own size");
+    img
+}
+
+/// Renders the same world from every camera, sharing the threads and BVH.
+pub fn render_multi(
+    world: &BvhNode,
+    lights: &HittableList,
+    cams: &[Camera],
+    settings: &RenderSettings,
+) -> Vec<RgbImage> {
+    let mut images = Vec::<RgbImage>::new();
+    for pixels in render_pixels(world, lights, cams, settings) {
+        let mut img: RgbImage = ImageBuffer::new(settings.image_width, settings.image_height);
+        for (pixel, color) in img.pixels_mut().zip(pixels) {
+            *pixel = image::Rgb([color[0], color[1], color[2]]);
+        }
+        images.push(img);
+    }
+    images
+}
+
+/// Renders at `settings.samples_per_pixel`, then renders again, with
+/// `edge_samples` samples each, the pixels `edge_pixels` flags. Only
+/// silhouettes and fine detail pay for the extra samples.
+pub fn render_edge_aware(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+    edge_samples: i32,
+    threshold: f64,
+) -> RgbImage {
+    let width = settings.image_width;
+    let height = settings.image_height;
+    let mut pixels = render_pixels(world, lights, &[cam], settings).remove(0);
+    let edges = edge_pixels(&pixels, width, height, threshold);
+
+    let edge_settings = RenderSettings {
+        samples_per_pixel: edge_samples,
+        denoiser: None,
+        ..*settings
+    };
+    let mut rng = rand::thread_rng();
+    for (i, _) in edges.iter().enumerate().filter(|(_, edge)| **edge) {
+        let x = i as u32 % width;
+        let y = height - i as u32 / width - 1;
+        let pixel = sample_pixel(
+            world,
+            lights,
+            &cam,
+            &edge_settings,
+            (x, y),
+            0..edge_samples,
+            &mut rng,
+        );
+        pixels[i] = encode_pixel(pixel, &edge_settings);
+    }
+
+    let mut img: RgbImage = ImageBuffer::new(width, height);
+    for (pixel, color) in img.pixels_mut().zip(pixels) {
+        *pixel = image::Rgb([color[0], color[1], color[2]]);
+    }
+    img
+}
+
+/// The luminance step `--edge-samples` treats as an edge.
+pub const EDGE_THRESHOLD: f64 = 0.1;
+
+/// Flags the pixels (image order) whose luminance differs from one of their
+/// four neighbours by more than `threshold`, in `[0, 1]`.
+pub fn edge_pixels(pixels: &[[u8; 4]], width: u32, height: u32, threshold: f64) -> Vec<bool> {
+    let luminance =
+        |p: [u8; 4]| (0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64) / 255.;
+    let (width, height) = (width as usize, height as usize);
+    let mut edges = vec![false; pixels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let l = luminance(pixels[y * width + x]);
+            let differs =
+                |nx: usize, ny: usize| (luminance(pixels[ny * width + nx]) - l).abs() > threshold;
+            edges[y * width + x] = (x > 0 && differs(x - 1, y))
+                || (x + 1 < width && differs(x + 1, y))
+                || (y > 0 && differs(x, y - 1))
+                || (y + 1 < height && differs(x, y + 1));
+        }
+    }
+    edges
+}
+
+/// Like `render`, with the alpha channel set from primary-ray coverage.
+pub fn render_rgba(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+) -> RgbaImage {
+    let settings = RenderSettings {
+        alpha: true,
+        ..*settings
+    };
+    let pixels = render_pixels(world, lights, &[cam], &settings).remove(0);
+    let mut img: RgbaImage = ImageBuffer::new(settings.image_width, settings.image_height);
+    for (pixel, color) in img.pixels_mut().zip(pixels) {
+        *pixel = image::Rgba(color);
+    }
+    img
+}
+
+/// Renders straight into a caller-owned buffer, `stride` bytes per row. Pixels
+/// are RGBA when `settings.alpha` is set and RGB otherwise.
+pub fn render_into(
+    buffer: &mut [u8],
+    stride: usize,
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+) -> Result<(), RenderError> {
+    let width = settings.image_width as usize;
+    let height = settings.image_height as usize;
+    let channels = if settings.alpha { 4 } else { 3 };
+    let row_len = width * channels;
+    if stride < row_len {
+        return Err(RenderError::Stride {
+            min: row_len,
+            actual: stride,
+        });
+    }
+    let expected = if height == 0 {
+        0
+    } else {
+        stride * (height - 1) + row_len
+    };
+    if buffer.len() < expected {
+        return Err(RenderError::BufferSize {
+            expected,
+            actual: buffer.len(),
+        });
+    }
+
+    let pixels = render_pixels(world, lights, &[cam], settings).remove(0);
+    for (y, row) in pixels.chunks(width).enumerate() {
+        let line = &mut buffer[y * stride..y * stride + row_len];
+        for (dst, color) in line.chunks_mut(channels).zip(row) {
+            dst.copy_from_slice(&color[..channels]);
+        }
+    }
+    Ok(())
+}
+
+/// Output pixels of each camera in image order, top row first.
+fn render_pixels(
+    world: &BvhNode,
+    lights: &HittableList,
+    cams: &[Camera],
+    settings: &RenderSettings,
+) -> Vec<Vec<[u8; 4]>> {
+    match settings.parallel {
+        Parallel::Samples => return render_pixels_by_samples(world, lights, cams, settings),
+        Parallel::Tiles(tile_size) => {
+            return render_pixels_by_tiles(world, lights, cams, settings, tile_size)
+        }
+        Parallel::Pixels => {}
+    }
+    let settings = *settings;
+    let image_width = settings.image_width;
+    let image_height = settings.image_height;
+    let section_line_num = image_height / settings.thread_number;
+
+    // Random line
+    let mut random_line_id: Vec<u32> = (0..image_height).collect();
+    let mut rng = rand::thread_rng();
+    for i in 0..image_height {
+        let target = rng.gen_range(0..i + 1);
+        random_line_id.swap(i as usize, target as usize);
+    }
+    let random_line_id = Arc::new(random_line_id);
+
+    // Progress bar
+    let multiprogress = Arc::new(MultiProgress::new());
+    multiprogress.set_move_cursor(true);
+
+    // Thread
+    let mut output_pixel_color = vec![Vec::<(Color, i32)>::new(); cams.len()];
+    let mut thread_pool = Vec::<_>::new();
+    let samples_taken = Arc::new(AtomicU64::new(0));
+
+    for thread_id in 0..settings.thread_number {
+        // line
+        let line_id = random_line_id.clone();
+        let line_beg = thread_id * section_line_num;
+        let mut line_end = line_beg + section_line_num;
+        if thread_id == settings.thread_number - 1 {
+            line_end = image_height;
+        }
+
+        // world & lights
+        let world = world.clone();
+        let lights = lights.clone();
+        let cams = cams.to_vec();
+        let samples_taken = samples_taken.clone();
+
+        //progress
+        let mp = multiprogress.clone();
+        let progress_bar = mp.add(ProgressBar::new(
+            (line_end - line_beg) as u64 * cams.len() as u64,
+        ));
+        progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] [{pos}/{len}] ({eta})")
+        .progress_chars("#>-"));
+
+        // thread code
+        let (tx, rx) = mpsc::channel();
+
+        thread_pool.push((
+            thread::spawn(move || {
+                let mut progress = 0;
+                progress_bar.set_position(progress);
+
+                let mut section_pixel_color = Vec::<Vec<(Color, i32)>>::new();
+
+                let mut rng = rand::thread_rng();
+                for cam in cams {
+                    let mut cam_pixel_color = Vec::<(Color, i32)>::new();
+                    for y_id in line_beg..line_end {
+                        for x in 0..image_width {
+                            let y = line_id[y_id as usize];
+                            let (pixel, samples) =
+                                render_pixel(&world, &lights, &cam, &settings, (x, y), &mut rng);
+                            samples_taken.fetch_add(samples as u64, Ordering::Relaxed);
+                            cam_pixel_color.push(rescale_samples(pixel, samples, &settings));
+                        }
+                        progress += 1;
+                        progress_bar.set_position(progress);
+                    }
+                    section_pixel_color.push(cam_pixel_color);
+                }
+                tx.send(section_pixel_color).unwrap();
+                progress_bar.finish_with_message("Finished.");
+            }),
+            rx,
+        ));
+    }
+    multiprogress.join().unwrap();
+
+    println!(
+        "{} 🚛 {}",
+        style("[3/5]").bold().dim(),
+        style("Collecting Threads Results...").green(),
+    );
+
+    for _thread_id in 0..settings.thread_number {
+        let thread = thread_pool.remove(0);
+        match thread.0.join() {
+            Ok(_) => {
+                let received = thread.1.recv().unwrap();
+                for (output, mut section) in output_pixel_color.iter_mut().zip(received) {
+                    output.append(&mut section);
+                }
+            }
+            Err(_) => {
+                println!("Thread error");
+                exit(0);
+            }
+        }
+    }
+
+    report_samples(&settings, cams.len(), &samples_taken);
+
+    println!(
+        "{} 🏭 {}",
+        style("[4/5]").bold().dim(),
+        style("Generating Image...").green()
+    );
+
+    let mut images = Vec::<Vec<[u8; 4]>>::new();
+    for (cam, cam_pixel_color) in cams.iter().zip(output_pixel_color) {
+        let mut frame = vec![(Color::new(0., 0., 0.), 0); (image_width * image_height) as usize];
+        let mut pixel_id = 0;
+        for y_id in 0..image_height {
+            for x in 0..image_width {
+                let y = random_line_id[y_id as usize];
+                let row = image_height - y - 1;
+                frame[(row * image_width + x) as usize] = cam_pixel_color[pixel_id];
+                pixel_id += 1;
+            }
+        }
+        images.push(finish_image(world, *cam, &settings, frame));
+    }
+    images
+}
+
+/// `Parallel::Tiles`: tiles are handed out to the threads one at a time,
+/// each sampled into its own buffer and then copied into the image. Seeded
+/// renders come out the same as with `Parallel::Pixels`.
+fn render_pixels_by_tiles(
+    world: &BvhNode,
+    lights: &HittableList,
+    cams: &[Camera],
+    settings: &RenderSettings,
+    tile_size: u32,
+) -> Vec<Vec<[u8; 4]>> {
+    let settings = *settings;
+    let image_width = settings.image_width;
+    let image_height = settings.image_height;
+    let tile_size = tile_size.max(1);
+    let tiles_x = (image_width + tile_size - 1) / tile_size;
+    let tiles_y = (image_height + tile_size - 1) / tile_size;
+    let tiles_per_cam = (tiles_x * tiles_y) as usize;
+    let tile_count = tiles_per_cam * cams.len();
+
+    let progress_bar = ProgressBar::new(tile_count as u64);
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] [{pos}/{len}] ({eta})")
+        .progress_chars("#>-"));
+
+    let next_tile = Arc::new(AtomicUsize::new(0));
+    let samples_taken = Arc::new(AtomicU64::new(0));
+    let (tx, rx) = mpsc::channel();
+    let mut thread_pool = Vec::<_>::new();
+    for _thread_id in 0..settings.thread_number {
+        let world = world.clone();
+        let lights = lights.clone();
+        let cams = cams.to_vec();
+        let next_tile = next_tile.clone();
+        let samples_taken = samples_taken.clone();
+        let progress_bar = progress_bar.clone();
+        let tx = tx.clone();
+        thread_pool.push(thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            loop {
+                let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                if tile >= tile_count {
+                    break;
+                }
+                let cam = &cams[tile / tiles_per_cam];
+                let tile_id = (tile % tiles_per_cam) as u32;
+                // Tile origin in output rows, top row first.
+                let x0 = tile_id % tiles_x * tile_size;
+                let row0 = tile_id / tiles_x * tile_size;
+                let mut buffer = Vec::<(Color, i32)>::new();
+                for row in row0..(row0 + tile_size).min(image_height) {
+                    for x in x0..(x0 + tile_size).min(image_width) {
+                        let y = image_height - row - 1;
+                        let (pixel, samples) =
+                            render_pixel(&world, &lights, cam, &settings, (x, y), &mut rng);
+                        samples_taken.fetch_add(samples as u64, Ordering::Relaxed);
+                        buffer.push(rescale_samples(pixel, samples, &settings));
+                    }
+                }
+                tx.send((tile, x0, row0, buffer)).unwrap();
+                progress_bar.inc(1);
+            }
+        }));
+    }
+    drop(tx);
+
+    let mut frames =
+        vec![vec![(Color::new(0., 0., 0.), 0); (image_width * image_height) as usize]; cams.len()];
+    for (tile, x0, row0, buffer) in rx {
+        let tile_width = (x0 + tile_size).min(image_width) - x0;
+        let frame = &mut frames[tile / tiles_per_cam];
+        for (i, pixel) in buffer.into_iter().enumerate() {
+            let (dx, dy) = (i as u32 % tile_width, i as u32 / tile_width);
+            frame[((row0 + dy) * image_width + x0 + dx) as usize] = pixel;
+        }
+    }
+    for thread in thread_pool {
+        if thread.join().is_err() {
+            println!("Thread error");
+            exit(0);
+        }
+    }
+    progress_bar.finish_with_message("Finished.");
+    report_samples(&settings, cams.len(), &samples_taken);
+
+    println!(
+        "{} 🏭 {}",
+        style("[4/5]").bold().dim(),
+        style("Generating Image...").green()
+    );
+    cams.iter()
+        .zip(frames)
+        .map(|(cam, frame)| finish_image(world, *cam, &settings, frame))
+        .collect()
+}
+
+/// Prints how many samples adaptive sampling ended up taking per pixel.
+fn report_samples(settings: &RenderSettings, cam_count: usize, samples_taken: &AtomicU64) {
+    if settings.adaptive.is_none() {
+        return;
+    }
+    let pixels = settings.image_width as u64 * settings.image_height as u64 * cam_count as u64;
+    println!(
+        "AVERAGE SAMPLES PER PIXEL: {}",
+        style(format!(
+            "{:.1}",
+            samples_taken.load(Ordering::Relaxed) as f64 / pixels as f64
+        ))
+        .yellow()
+    );
+}
+
+/// Splits `samples_per_pixel` across the threads instead of the rows. Every
+/// thread adds its share of each pixel straight into one shared framebuffer.
+/// Pays off for tiny images with huge sample counts. Seeded renders match
+/// `Parallel::Pixels` up to float rounding, since the shares arrive in any
+/// order. Adaptive sampling decides per pixel when to stop, which a share of
+/// the samples can't, so it is rejected.
+fn render_pixels_by_samples(
+    world: &BvhNode,
+    lights: &HittableList,
+    cams: &[Camera],
+    settings: &RenderSettings,
+) -> Vec<Vec<[u8; 4]>> {
+    assert!(
+        settings.adaptive.is_none(),
+        "Parallel::Samples can't sample adaptively, use Parallel::Pixels or Parallel::Tiles"
+    );
+    let settings = *settings;
+    let image_width = settings.image_width;
+    let image_height = settings.image_height;
+    let thread_number = settings.thread_number as i32;
+    let pixel_number = (image_width * image_height) as usize;
+
+    let progress_bar =
+        ProgressBar::new(image_height as u64 * cams.len() as u64 * settings.thread_number as u64);
+    progress_bar.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] [{pos}/{len}] ({eta})")
+        .progress_chars("#>-"));
+
+    let frames: Arc<Vec<AtomicFrame>> = Arc::new(
+        cams.iter()
+            .map(|_| AtomicFrame::new(pixel_number))
+            .collect(),
+    );
+    let mut thread_pool = Vec::<_>::new();
+    for thread_id in 0..thread_number {
+        let world = world.clone();
+        let lights = lights.clone();
+        let cams = cams.to_vec();
+        let frames = frames.clone();
+        let progress_bar = progress_bar.clone();
+        thread_pool.push(thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            for (cam, frame) in cams.iter().zip(frames.iter()) {
+                for y in 0..image_height {
+                    let row = image_height - y - 1;
+                    for x in 0..image_width {
+                        let spp = pixel_samples(&settings, x, y);
+                        let pixel = sample_pixel(
+                            &world,
+                            &lights,
+                            cam,
+                            &settings,
+                            (x, y),
+                            spp * thread_id / thread_number..spp * (thread_id + 1) / thread_number,
+                            &mut rng,
+                        );
+                        let i = (row * image_width + x) as usize;
+                        frame.add(i, rescale_samples(pixel, spp, &settings));
+                    }
+                    progress_bar.inc(1);
+                }
+            }
+        }));
+    }
+    for thread in thread_pool {
+        if thread.join().is_err() {
+            println!("Thread error");
+            exit(0);
+        }
+    }
+    progress_bar.finish_with_message("Finished.");
+
+    println!(
+        "{} 🏭 {}",
+        style("[4/5]").bold().dim(),
+        style("Generating Image...").green()
+    );
+
+    cams.iter()
+        .zip(frames.iter())
+        .map(|(cam, frame)| finish_image(world, *cam, &settings, frame.sums()))
+        .collect()
+}
+
+/// Summed radiance and coverage per pixel that several threads add to at
+/// once. Colors are kept as `f64` bits.
+struct AtomicFrame {
+    pixels: Vec<[AtomicU64; 4]>,
+}
+
+impl AtomicFrame {
+    fn new(len: usize) -> Self {
+        Self {
+            pixels: (0..len).map(|_| Default::default()).collect(),
+        }
+    }
+
+    fn add(&self, i: usize, (color, coverage): (Color, i32)) {
+        let [r, g, b, covered] = &self.pixels[i];
+        for (sum, x) in [(r, color.x), (g, color.y), (b, color.z)].iter() {
+            sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + x).to_bits())
+            })
+            .unwrap();
+        }
+        covered.fetch_add(coverage as u64, Ordering::Relaxed);
+    }
+
+    fn sums(&self) -> Vec<(Color, i32)> {
+        self.pixels
+            .iter()
+            .map(|[r, g, b, covered]| {
+                let channel = |sum: &AtomicU64| f64::from_bits(sum.load(Ordering::Relaxed));
+                (
+                    Color::new(channel(r), channel(g), channel(b)),
+                    covered.load(Ordering::Relaxed) as i32,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Averages the summed samples of `frame` (image order), denoises them if
+/// asked to, then tone maps and encodes.
+fn finish_image(
+    world: &BvhNode,
+    cam: Camera,
+    settings: &RenderSettings,
+    frame: Vec<(Color, i32)>,
+) -> Vec<[u8; 4]> {
+    let spp = settings.samples_per_pixel;
+    let denoiser = match settings.denoiser {
+        Some(denoiser) => denoiser,
+        None => {
+            return frame
+                .into_iter()
+                .map(|p| encode_pixel(p, settings))
+                .collect()
+        }
+    };
+    let color = Framebuffer {
+        width: settings.image_width,
+        height: settings.image_height,
+        pixels: frame.iter().map(|(c, _)| *c / spp as f64).collect(),
+    };
+    let albedo = aov::aov_framebuffer(world, cam, settings, Aov::Albedo);
+    let normal = aov::aov_framebuffer(world, cam, settings, Aov::Normal);
+    let denoised = denoiser.denoise(&color, Some(&albedo), Some(&normal));
+    frame
+        .iter()
+        .zip(denoised.pixels)
+        .map(|((_, coverage), c)| encode_pixel((c * spp as f64, *coverage), settings))
+        .collect()
+}
+
+/// Summed radiance and primary-ray coverage of the given samples of pixel
+/// `(x, y)`, counted from the bottom left. Letterbox bars count as bar color.
+fn sample_pixel(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: &Camera,
+    settings: &RenderSettings,
+    (x, y): (u32, u32),
+    samples: Range<i32>,
+    rng: &mut dyn RngCore,
+) -> (Color, i32) {
+    let viewport = Viewport::new(settings, cam);
+    if let Fit::Letterbox(bar) = settings.fit {
+        if !viewport.contains(x, y) {
+            return (bar * samples.len() as f64, 0);
+        }
+    }
+    let mut pixel_color = Color::new(0., 0., 0.);
+    let mut coverage = 0;
+    for i in samples {
+        let (color, hit) = match settings.seed {
+            Some(seed) => {
+                let seq = SampleSequence::new(x, y, i as u32, seed);
+                sample(world, lights, cam, settings, (x, y), i, seq)
+            }
+            None => {
+                let (du, dv) = match settings.jitter_offset {
+                    Some(offset) => offset,
+                    None => stratify((rng.gen(), rng.gen()), i, pixel_samples(settings, x, y)),
+                };
+                let (u, v) = viewport.uv(x, y, du, dv);
+                trace(world, lights, settings, cam.get_ray(u, v, rng), rng)
+            }
+        };
+        if hit {
+            coverage += 1;
+        }
+        pixel_color += color;
+    }
+    (pixel_color, coverage)
+}
+
+/// Samples of pixel `(x, y)` as `sample_map` or `adaptive` ask, summed like
+/// `sample_pixel`, and how many there were.
+fn render_pixel(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: &Camera,
+    settings: &RenderSettings,
+    (x, y): (u32, u32),
+    rng: &mut dyn RngCore,
+) -> ((Color, i32), i32) {
+    match settings.adaptive {
+        Some(adaptive) => {
+            sample_pixel_adaptive(world, lights, cam, settings, (x, y), adaptive, rng)
+        }
+        None => {
+            let samples = pixel_samples(settings, x, y);
+            let pixel = sample_pixel(world, lights, cam, settings, (x, y), 0..samples, rng);
+            (pixel, samples)
+        }
+    }
+}
+
+/// Samples pixel `(x, y)` one sample at a time until `adaptive` says it has
+/// converged. Returns the sums like `sample_pixel` and how many were taken.
+fn sample_pixel_adaptive(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: &Camera,
+    settings: &RenderSettings,
+    (x, y): (u32, u32),
+    adaptive: Adaptive,
+    rng: &mut dyn RngCore,
+) -> ((Color, i32), i32) {
+    let max_samples = adaptive.max_samples.max(1);
+    let min_samples = adaptive.min_samples.clamp(1, max_samples);
+    let mut pixel_color = Color::new(0., 0., 0.);
+    let mut coverage = 0;
+    let (mut sum, mut sum_sqr) = (0., 0.);
+    let mut n = 0;
+    while n < max_samples {
+        let (color, covered) = sample_pixel(world, lights, cam, settings, (x, y), n..n + 1, rng);
+        pixel_color += color;
+        coverage += covered;
+        let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+        sum += luminance;
+        sum_sqr += luminance * luminance;
+        n += 1;
+        if n >= min_samples && n > 1 {
+            let mean = sum / n as f64;
+            let variance = ((sum_sqr - sum * mean) / (n - 1) as f64).max(0.);
+            if (variance / n as f64).sqrt() < adaptive.tolerance {
+                break;
+            }
+        }
+    }
+    ((pixel_color, coverage), n)
+}
+
+/// Moves a uniform sub-pixel offset into cell `index` of an `n x n` grid when
+/// the pixel gets `samples = n * n` samples, so they cover it evenly. Other
+/// counts keep the offset as it is.
+fn stratify((du, dv): (f64, f64), index: i32, samples: i32) -> (f64, f64) {
+    let n = (samples as f64).sqrt().round() as i32;
+    if n * n != samples || !(0..samples).contains(&index) {
+        return (du, dv);
+    }
+    (
+        ((index % n) as f64 + du) / n as f64,
+        ((index / n) as f64 + dv) / n as f64,
+    )
+}
+
+/// How many samples pixel `(x, y)` gets, counted from the bottom left.
+fn pixel_samples(settings: &RenderSettings, x: u32, y: u32) -> i32 {
+    match settings.sample_map {
+        Some(map) => map.samples(x, y, settings.image_width, settings.image_height),
+        None => settings.samples_per_pixel,
+    }
+}
+
+/// Scales sums over `samples` samples to look like sums over
+/// `samples_per_pixel`, which is what the averaging downstream divides by.
+fn rescale_samples(
+    (pixel_color, coverage): (Color, i32),
+    samples: i32,
+    settings: &RenderSettings,
+) -> (Color, i32) {
+    if samples == settings.samples_per_pixel || samples == 0 {
+        return (pixel_color, coverage);
+    }
+    let scale = settings.samples_per_pixel as f64 / samples as f64;
+    (
+        pixel_color * scale,
+        (coverage as f64 * scale).round() as i32,
+    )
+}
+
+fn encode_pixel((pixel_color, coverage): (Color, i32), settings: &RenderSettings) -> [u8; 4] {
+    let rgb = write_color(
+        pixel_color,
+        settings.samples_per_pixel,
+        settings.tone_map,
+        settings.output_colorspace,
+    );
+    let alpha = if settings.alpha {
+        (coverage as f64 / settings.samples_per_pixel as f64 * 255.).round() as u8
+    } else {
+        255
+    };
+    [rgb[0], rgb[1], rgb[2], alpha]
+}
+
+/// Radiance of sample `index` of pixel `(x, y)`, a pure function of `seq` and
+/// the scene. Also reports whether the primary ray hit the world.
+pub fn sample(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: &Camera,
+    settings: &RenderSettings,
+    (x, y): (u32, u32),
+    index: i32,
+    mut seq: SampleSequence,
+) -> (Color, bool) {
+    let r = primary_ray(cam, settings, (x, y), index, &mut seq);
+    sampler::with_sequence(seq, || {
+        trace(world, lights, settings, r, &mut sampler::rng())
+    })
+}
+
+/// The camera ray of sample `index` of pixel `(x, y)`, drawing its sub-pixel
+/// offset, lens point and time from `seq`.
+fn primary_ray(
+    cam: &Camera,
+    settings: &RenderSettings,
+    (x, y): (u32, u32),
+    index: i32,
+    seq: &mut SampleSequence,
+) -> Ray {
+    let offset = stratify(seq.pixel_offset(), index, pixel_samples(settings, x, y));
+    let (rand_u, rand_v) = settings.jitter_offset.unwrap_or(offset);
+    let lens = seq.lens();
+    let time = seq.time();
+    let (u, v) = Viewport::new(settings, cam).uv(x, y, rand_u, rand_v);
+    cam.get_ray_at(u, v, lens, time)
+}
+
+fn trace(
+    world: &BvhNode,
+    lights: &HittableList,
+    settings: &RenderSettings,
+    r: Ray,
+    rng: &mut dyn RngCore,
+) -> (Color, bool) {
+    let (color, hit) = trace_path(
+        r,
+        world,
+        lights,
+        settings,
+        settings.max_depth,
+        Color::new(1., 1., 1.),
+        rng,
+        None,
+    );
+    (clamp_firefly(color, settings.firefly_clamp), hit)
+}
+
+/// Scales `color` down so that no channel exceeds `limit`, keeping its hue.
+fn clamp_firefly(color: Color, limit: Option<f64>) -> Color {
+    match limit {
+        Some(limit) => {
+            let max = color.x.max(color.y).max(color.z);
+            if max > limit {
+                color * (limit / max)
+            } else {
+                color
+            }
+        }
+        None => color,
+    }
+}
+
+/// Estimates the samples per pixel needed for the per-pixel RMSE of the linear
+/// radiance to drop below `target_rmse`. Pairs of independent low resolution
+/// passes give the noise at a few sample counts; a line fitted through
+/// `ln(rmse)` against `ln(spp)` is then followed down to the target. Monte
+/// Carlo noise falls off as 1 / sqrt(spp), a slope of -0.5, which is assumed
+/// when the probes don't show the error falling. Per-pixel noise is taken to
+/// be the same at full resolution. Seeded settings give the same estimate
+/// every time.
+pub fn probe_samples(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+    target_rmse: f64,
+) -> u32 {
+    const PROBE_WIDTH: u32 = 64;
+    const PROBE_SAMPLES: [i32; 3] = [4, 8, 16];
+
+    let width = PROBE_WIDTH.min(settings.image_width).max(2);
+    let height =
+        ((width as f64 * settings.image_height as f64 / settings.image_width as f64) as u32).max(2);
+
+    // (ln spp, ln rmse) of every probe that saw any noise.
+    let mut points = Vec::<(f64, f64)>::new();
+    // Seeded passes number their samples on from the previous pass's.
+    let mut first_sample = 0;
+    for &spp in PROBE_SAMPLES.iter() {
+        let size = (width, height);
+        let pass0 = probe_pass(world, lights, cam, settings, size, spp, first_sample);
+        let pass1 = probe_pass(world, lights, cam, settings, size, spp, first_sample + spp);
+        first_sample += 2 * spp;
+        let mut sum = 0.;
+        for (c0, c1) in pass0.iter().zip(pass1.iter()) {
+            sum += (*c0 - *c1).length_squared() / 3.;
+        }
+        // Difference of two independent passes carries twice the variance.
+        let rmse = (sum / pass0.len() as f64 / 2.).sqrt();
+        if rmse > 0. && rmse.is_finite() {
+            points.push(((spp as f64).ln(), rmse.ln()));
+        }
+    }
+    if points.is_empty() {
+        return 1;
+    }
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let var_x: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let cov: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let slope = if var_x > 0. && cov / var_x < -0.1 {
+        cov / var_x
+    } else {
+        -0.5
+    };
+    let estimate = ((target_rmse.ln() - mean_y) / slope + mean_x).exp();
+    estimate.ceil().clamp(1., u32::MAX as f64) as u32
+}
+
+/// Mean linear radiance of every pixel of a `width` x `height` render at `spp`.
+/// Seeded, sample `i` of a pixel draws from sequence `first_sample + i`.
+fn probe_pass(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+    (width, height): (u32, u32),
+    spp: i32,
+    first_sample: i32,
+) -> Vec<Color> {
+    let mut thread_rng = rand::thread_rng();
+    let mut colors = Vec::<Color>::new();
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel_color = Color::new(0., 0., 0.);
+            for i in 0..spp {
+                let mut seq;
+                let rng: &mut dyn RngCore = match settings.seed {
+                    Some(seed) => {
+                        seq = SampleSequence::new(x, y, (first_sample + i) as u32, seed);
+                        &mut seq
+                    }
+                    None => &mut thread_rng,
+                };
+                let u = (x as f64 + rng.gen::<f64>()) / (width - 1) as f64;
+                let v = (y as f64 + rng.gen::<f64>()) / (height - 1) as f64;
+                let r = cam.get_ray(u, v, rng);
+                let color = ray_color(
+                    r,
+                    world,
+                    lights,
+                    settings,
+                    settings.max_depth,
+                    Color::new(1., 1., 1.),
+                    rng,
+                    None,
+                );
+                pixel_color += clamp_firefly(color, settings.firefly_clamp);
+            }
+            colors.push(pixel_color / spp as f64);
+        }
+    }
+    colors
+}
+
+/// Scattered directions drawn with a lower density end the path instead.
+const MIN_PDF: f64 = 1e-8;
+
+/// One step of a path, as reported to `ray_color`'s logging hook.
+#[derive(Clone, Copy)]
+pub struct Bounce {
+    pub depth: i32,
+    pub event: BounceEvent,
+    // Hit point, or the ray direction on a miss.
+    pub point: Point3,
+    pub material: &'static str,
+    pub attenuation: Color,
+    // Density the scattered direction was drawn with; none for specular lobes.
+    pub pdf: Option<f64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BounceEvent {
+    Miss,
+    // Hit a surface that only emits.
+    Absorb,
+    Reflect,
+    Refract,
+    Diffuse,
+    // Killed by Russian roulette.
+    Terminate,
+}
+
+/// Radiance arriving along `r`, followed for at most `depth` bounces in a
+/// loop. `throughput` is what the caller's path already carries, for Russian
+/// roulette; the result doesn't include it.
+#[allow(clippy::too_many_arguments)]
+pub fn ray_color(
+    r: Ray,
+    world: &BvhNode,
+    lights: &HittableList,
+    settings: &RenderSettings,
+    depth: i32,
+    throughput: Color,
+    rng: &mut dyn RngCore,
+    log: Option<&mut dyn FnMut(&Bounce)>,
+) -> Color {
+    trace_path(r, world, lights, settings, depth, throughput, rng, log).0
+}
+
+/// `ray_color`, also telling whether `r` itself hit the world, for coverage.
+#[allow(clippy::too_many_arguments)]
+fn trace_path(
+    r: Ray,
+    world: &BvhNode,
+    lights: &HittableList,
+    settings: &RenderSettings,
+    depth: i32,
+    throughput: Color,
+    rng: &mut dyn RngCore,
+    mut log: Option<&mut dyn FnMut(&Bounce)>,
+) -> (Color, bool) {
+    if depth <= 0 {
+        return (Color::new(0., 0., 0.), false);
+    }
+    if settings.flat {
+        return flat_color(r, world, settings, rng);
+    }
+    let primary_depth = depth;
+    let mut covered = false;
+    let mut radiance = Color::new(0., 0., 0.);
+    // Product of the scattering weights over the survival odds so far.
+    let mut weight = Color::new(1., 1., 1.);
+    let mut throughput = throughput;
+    let mut r = r;
+    for depth in (1..=depth).rev() {
+        let mut report = |event, point, material, attenuation, pdf| {
+            if let Some(log) = log.as_deref_mut() {
+                log(&Bounce {
+                    depth,
+                    event,
+                    point,
+                    material,
+                    attenuation,
+                    pdf,
+                });
+            }
+        };
+        let rec = match world.hit(r, 0.001, f64::MAX) {
+            Some(rec) => rec,
+            None => {
+                let background = settings.background.sample(r.dir);
+                report(BounceEvent::Miss, r.dir, "", background, None);
+                radiance += weight * background;
+                break;
+            }
+        };
+        covered |= depth == primary_depth;
+        let emitted = rec.mat_ptr.emitted(r, &rec, rec.u, rec.v, rec.p);
+        let material = rec.mat_ptr.name();
+        let srec = match rec.mat_ptr.scatter(r, &rec, rng) {
+            Some(srec) => srec,
+            None => {
+                report(BounceEvent::Absorb, rec.p, material, emitted, None);
+                radiance += weight * emitted;
+                break;
+            }
+        };
+
+        if let Some(specular) = srec.specular_ray {
+            let event = if Vec3::dot(specular.dir, rec.geometric_normal) < 0. {
+                BounceEvent::Refract
+            } else {
+                BounceEvent::Reflect
+            };
+            report(event, rec.p, material, srec.attenuation, None);
+            throughput *= srec.attenuation;
+            let survival = match settings.russian_roulette.play(throughput, rng) {
+                Some(survival) => survival,
+                None => {
+                    report(BounceEvent::Terminate, rec.p, material, throughput, None);
+                    break;
+                }
+            };
+            weight = weight * srec.attenuation / survival;
+            r = specular;
+        } else {
+            let light_ptr = HittablePdf::new(lights, rec.p, r.tm);
+            let p = MixturePdf::new(light_ptr, srec.pdf_ptr.unwrap());
+            let scattered = Ray::new(rec.p, p.generate(rng), r.tm);
+            let pdf_val = p.value(scattered.dir);
+            radiance += weight * emitted;
+            // A direction the mixture can barely produce would blow up the weight.
+            if pdf_val < MIN_PDF {
+                report(BounceEvent::Absorb, rec.p, material, emitted, Some(pdf_val));
+                break;
+            }
+            report(
+                BounceEvent::Diffuse,
+                rec.p,
+                material,
+                srec.attenuation,
+                Some(pdf_val),
+            );
+            let bounce_weight =
+                srec.attenuation * rec.mat_ptr.scattering_pdf(r, &rec, scattered) / pdf_val;
+            throughput *= bounce_weight;
+            let survival = match settings.russian_roulette.play(throughput, rng) {
+                Some(survival) => survival,
+                None => {
+                    report(BounceEvent::Terminate, rec.p, material, throughput, None);
+                    break;
+                }
+            };
+            weight = weight * bounce_weight / survival;
+            r = scattered;
+        }
+        // Nothing further along can contribute.
+        if weight.x.max(weight.y).max(weight.z) <= 0. {
+            break;
+        }
+    }
+    (radiance, covered)
+}
+
+/// A path in flight in `render_wavefront`.
+struct WavefrontPath {
+    // Index into the frame, image order.
+    pixel: usize,
+    ray: Ray,
+    // What `ray_color` gets as `throughput`, for Russian roulette.
+    throughput: Color,
+    // Product of the scattering weights over the survival odds so far.
+    weight: Color,
+    radiance: Color,
+    seq: Option<SampleSequence>,
+}
+
+/// Runs `f` with the path's own sequence installed, or `thread_rng` for
+/// unseeded renders.
+fn in_sequence<R>(seq: &mut Option<SampleSequence>, f: impl FnOnce(&mut dyn RngCore) -> R) -> R {
+    match seq {
+        Some(seq) => sampler::with_sequence_mut(seq, || f(&mut sampler::rng())),
+        None => f(&mut rand::thread_rng()),
+    }
+}
+
+/// Breadth-first version of `render`: every path of one sample index is
+/// intersected as a batch, then shaded as a batch, wave after wave until
+/// `max_depth`. Each path draws from its own sequence, in the same order as
+/// `ray_color`, so seeded renders match the recursive tracer up to float
+/// rounding. Single threaded; flat previews fall back to `render`. Every
+/// pixel takes `samples_per_pixel`, so `sample_map` and `adaptive` are
+/// rejected.
+pub fn render_wavefront(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+) -> RgbImage {
+    assert!(
+        settings.sample_map.is_none() && settings.adaptive.is_none(),
+        "render_wavefront takes the same samples in every pixel, without sample_map or adaptive"
+    );
+    if settings.flat {
+        return render(world, lights, cam, settings);
+    }
+    let width = settings.image_width;
+    let height = settings.image_height;
+    let viewport = Viewport::new(settings, &cam);
+    let mut frame = vec![(Color::new(0., 0., 0.), 0); (width * height) as usize];
+    let mut rng = rand::thread_rng();
+
+    for i in 0..settings.samples_per_pixel {
+        // Primary rays
+        let mut paths = Vec::<WavefrontPath>::new();
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = ((height - y - 1) * width + x) as usize;
+                if let Fit::Letterbox(bar) = settings.fit {
+                    if !viewport.contains(x, y) {
+                        frame[pixel].0 += bar;
+                        continue;
+                    }
+                }
+                let (ray, seq) = match settings.seed {
+                    Some(seed) => {
+                        let mut seq = SampleSequence::new(x, y, i as u32, seed);
+                        let offset = stratify(seq.pixel_offset(), i, settings.samples_per_pixel);
+                        let (du, dv) = settings.jitter_offset.unwrap_or(offset);
+                        let lens = seq.lens();
+                        let time = seq.time();
+                        let (u, v) = viewport.uv(x, y, du, dv);
+                        (cam.get_ray_at(u, v, lens, time), Some(seq))
+                    }
+                    None => {
+                        let (du, dv) = match settings.jitter_offset {
+                            Some(offset) => offset,
+                            None => stratify((rng.gen(), rng.gen()), i, settings.samples_per_pixel),
+                        };
+                        let (u, v) = viewport.uv(x, y, du, dv);
+                        (cam.get_ray(u, v, &mut rng), None)
+                    }
+                };
+                paths.push(WavefrontPath {
+                    pixel,
+                    ray,
+                    throughput: Color::new(1., 1., 1.),
+                    weight: Color::new(1., 1., 1.),
+                    radiance: Color::new(0., 0., 0.),
+                    seq,
+                });
+            }
+        }
+
+        let mut finished = Vec::<WavefrontPath>::new();
+        for depth in 0..settings.max_depth {
+            if paths.is_empty() {
+                break;
+            }
+            // Intersect
+            let hits: Vec<_> = paths
+                .iter_mut()
+                .map(|path| {
+                    let ray = path.ray;
+                    in_sequence(&mut path.seq, |_| world.hit(ray, 0.001, f64::MAX))
+                })
+                .collect();
+            // Coverage comes from the primary rays' hits
+            if depth == 0 && settings.alpha {
+                for (path, hit) in paths.iter().zip(&hits) {
+                    if hit.is_some() {
+                        frame[path.pixel].1 += 1;
+                    }
+                }
+            }
+            // Shade and spawn the next wave
+            let mut next = Vec::with_capacity(paths.len());
+            for (mut path, hit) in paths.into_iter().zip(hits) {
+                let mut seq = path.seq.take();
+                let alive = in_sequence(&mut seq, |rng| {
+                    shade_wavefront(&mut path, hit, lights, settings, rng)
+                });
+                path.seq = seq;
+                if alive {
+                    next.push(path);
+                } else {
+                    finished.push(path);
+                }
+            }
+            paths = next;
+        }
+        // Paths still going at max depth gather nothing more, like `ray_color`.
+        for path in finished.into_iter().chain(paths) {
+            frame[path.pixel].0 += clamp_firefly(path.radiance, settings.firefly_clamp);
+        }
+    }
+
+    let pixels = finish_image(world, cam, settings, frame);
+    let mut img: RgbImage = ImageBuffer::new(width, height);
+    for (pixel, color) in img.pixels_mut().zip(pixels) {
+        *pixel = image::Rgb([color[0], color[1], color[2]]);
+    }
+    img
+}
+
+/// One bounce of `ray_color`, unrolled: adds what `path` gathers at `hit` and
+/// points it at its next ray. Returns whether the path goes on.
+fn shade_wavefront(
+    path: &mut WavefrontPath,
+    hit: Option<HitRecord>,
+    lights: &HittableList,
+    settings: &RenderSettings,
+    rng: &mut dyn RngCore,
+) -> bool {
+    let r = path.ray;
+    let rec = match hit {
+        Some(rec) => rec,
+        None => {
+            path.radiance += path.weight * settings.background.sample(r.dir);
+            return false;
+        }
+    };
+    let emitted = rec.mat_ptr.emitted(r, &rec, rec.u, rec.v, rec.p);
+    let srec = match rec.mat_ptr.scatter(r, &rec, rng) {
+        Some(srec) => srec,
+        None => {
+            path.radiance += path.weight * emitted;
+            return false;
+        }
+    };
+    let (next, weight) = match srec.specular_ray {
+        Some(specular) => (specular, srec.attenuation),
+        None => {
+            let light_ptr = HittablePdf::new(lights, rec.p, r.tm);
+            let p = MixturePdf::new(light_ptr, srec.pdf_ptr.unwrap());
+            let scattered = Ray::new(rec.p, p.generate(rng), r.tm);
+            let pdf_val = p.value(scattered.dir);
+            path.radiance += path.weight * emitted;
+            if pdf_val < MIN_PDF {
+                return false;
+            }
+            (
+                scattered,
+                srec.attenuation * rec.mat_ptr.scattering_pdf(r, &rec, scattered) / pdf_val,
+            )
+        }
+    };
+    let throughput = path.throughput * weight;
+    let survival = match settings.russian_roulette.play(throughput, rng) {
+        Some(survival) => survival,
+        None => return false,
+    };
+    path.ray = next;
+    path.throughput = throughput;
+    path.weight = path.weight * weight / survival;
+    true
+}
+
+/// Traces one sample through the output image's pixel `(x, y)`, printing every
+/// bounce to stderr. For chasing black or NaN pixels. Panics if the pixel is
+/// outside the image.
+pub fn debug_pixel(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+    x: u32,
+    y: u32,
+) -> Color {
+    assert!(
+        x < settings.image_width && y < settings.image_height,
+        "pixel ({}, {}) is outside the image",
+        x,
+        y
+    );
+    let mut rng = rand::thread_rng();
+    let row = settings.image_height - y - 1;
+    let (u, v) = Viewport::new(settings, &cam).uv(x, row, rng.gen(), rng.gen());
+    let mut log = |b: &Bounce| {
+        eprintln!(
+            "depth {}: {:?} at {} on {}, attenuation {}, pdf {:?}",
+            b.depth, b.event, b.point, b.material, b.attenuation, b.pdf
+        )
+    };
+    let color = ray_color(
+        cam.get_ray(u, v, &mut rng),
+        world,
+        lights,
+        settings,
+        settings.max_depth,
+        Color::new(1., 1., 1.),
+        &mut rng,
+        Some(&mut log),
+    );
+    eprintln!("pixel ({}, {}) = {}", x, y, color);
+    color
+}
+
+/// Colors each pixel by how many times one of its paths scattered before it
+/// ended, from blue (one bounce) to red (`max_depth`). Pixels whose primary
+/// ray escapes stay black. Shows where paths run into the depth limit.
+pub fn render_bounce_heatmap(
+    world: &BvhNode,
+    lights: &HittableList,
+    cam: Camera,
+    settings: &RenderSettings,
+) -> RgbImage {
+    let mut rng = rand::thread_rng();
+    let viewport = Viewport::new(settings, &cam);
+    let mut img: RgbImage = ImageBuffer::new(settings.image_width, settings.image_height);
+    for (x, row, pixel) in img.enumerate_pixels_mut() {
+        let y = settings.image_height - row - 1;
+        let (u, v) = viewport.uv(x, y, rng.gen(), rng.gen());
+        let mut bounces = 0;
+        let mut log = |b: &Bounce| match b.event {
+            BounceEvent::Reflect | BounceEvent::Refract | BounceEvent::Diffuse => bounces += 1,
+            _ => {}
+        };
+        ray_color(
+            cam.get_ray(u, v, &mut rng),
+            world,
+            lights,
+            settings,
+            settings.max_depth,
+            Color::new(1., 1., 1.),
+            &mut rng,
+            Some(&mut log),
+        );
+        let color = if bounces == 0 {
+            Color::new(0., 0., 0.)
+        } else {
+            heat_color((bounces - 1) as f64 / (settings.max_depth - 1).max(1) as f64)
+        };
+        *pixel = image::Rgb([
+            (color.x * 255.).round() as u8,
+            (color.y * 255.).round() as u8,
+            (color.z * 255.).round() as u8,
+        ]);
+    }
+    img
+}
+
+/// Blue, cyan, green, yellow, red as `t` goes from 0 to 1.
+fn heat_color(t: f64) -> Color {
+    let stops = [
+        Color::new(0., 0., 1.),
+        Color::new(0., 1., 1.),
+        Color::new(0., 1., 0.),
+        Color::new(1., 1., 0.),
+        Color::new(1., 0., 0.),
+    ];
+    let x = t.clamp(0., 1.) * (stops.len() - 1) as f64;
+    let i = (x as usize).min(stops.len() - 2);
+    let f = x - i as f64;
+    stops[i] * (1. - f) + stops[i + 1] * f
+}
+
+/// The first hit's albedo (or emission, for lights) scaled by how directly the
+/// surface faces the camera.
+fn flat_color(
+    r: Ray,
+    world: &BvhNode,
+    settings: &RenderSettings,
+    rng: &mut dyn RngCore,
+) -> (Color, bool) {
+    match world.hit(r, 0.001, f64::MAX) {
+        Some(rec) => {
+            let albedo = match rec.mat_ptr.scatter(r, &rec, rng) {
+                Some(srec) => srec.attenuation,
+                None => rec.mat_ptr.emitted(r, &rec, rec.u, rec.v, rec.p),
+            };
+            (
+                albedo * Vec3::dot(rec.normal, -r.dir.to_unit()).max(0.),
+                true,
+            )
+        }
+        None => (settings.background.sample(r.dir), false),
+    }
+}
+
+/// Writes `img` as a binary PPM (P6): a short text header, then raw RGB bytes.
+pub fn write_ppm(path: &str, img: &RgbImage) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", img.width(), img.height())?;
+    file.write_all(img.as_raw())?;
+    file.flush()
+}
+
+/// Beauty pass only: averages the samples, tone maps, then encodes for
+/// `color_space`.
+pub fn write_color(
+    pixel_color: Color,
+    samples_per_pixel: i32,
+    tone_map: ToneMap,
+    color_space: ColorSpace,
+) -> [u8; 3] {
+    // NaN and negative radiance become black before the tone curve, which
+    // could otherwise map a negative value to something bright (Reinhard
+    // takes -2 to 2).
+    let sanitize = |x: f64| if x.is_nan() { 0. } else { x.max(0.) };
+    let r = sanitize(pixel_color.x);
+    let g = sanitize(pixel_color.y);
+    let b = sanitize(pixel_color.z);
+
+    let r = tone_map.apply(r / samples_per_pixel as f64);
+    let g = tone_map.apply(g / samples_per_pixel as f64);
+    let b = tone_map.apply(b / samples_per_pixel as f64);
+
+    let encode =
+        |x: f64| (color_space.encode(x.clamp(0.0, 1.0)).clamp(0.0, 0.999) * 255.999).floor() as u8;
+    [encode(r), encode(g), encode(b)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::camera::DEFAULT_VUP;
+    use crate::hittable::{
+        aarect::XZRect, constantmedium::ConstantMedium, sphere::Sphere, Hittable,
+    };
+    use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+    use crate::scene;
+
+    /// A sphere on a ground sphere under a square light.
+    fn small_scene() -> (BvhNode, HittableList, Camera) {
+        small_scene_with(Lambertian::new(Color::new(0.7, 0.3, 0.3)))
+    }
+
+    /// `small_scene` with the middle sphere made of `mat`.
+    fn small_scene_with<M: Material + Clone + 'static>(mat: M) -> (BvhNode, HittableList, Camera) {
+        small_scene_around(Arc::new(Sphere::new(Point3::new(0., 0., -1.), 0.5, mat)))
+    }
+
+    /// `small_scene` with `middle` in place of the middle sphere.
+    fn small_scene_around(middle: Arc<dyn Hittable>) -> (BvhNode, HittableList, Camera) {
+        let mut world = HittableList::default();
+        let mut lights = HittableList::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., -100.5, -1.),
+            100.,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )));
+        world.add(middle);
+        let light = Arc::new(XZRect::new(
+            -1.,
+            1.,
+            -2.,
+            0.,
+            2.,
+            DiffuseLight::new_two_sided(Color::new(4., 4., 4.)),
+        ));
+        world.add(light.clone());
+        lights.add(light);
+
+        let cam = Camera::new(
+            Point3::new(0., 0.5, 2.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            8. / 6.,
+            0.,
+            3.,
+            0.,
+            1.,
+        );
+        (BvhNode::new_list(&world, 0., 1.), lights, cam)
+    }
+
+    fn small_settings() -> RenderSettings {
+        RenderSettings {
+            image_width: 8,
+            image_height: 6,
+            samples_per_pixel: 4,
+            max_depth: 4,
+            thread_number: 2,
+            seed: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn wavefront_matches_the_recursive_tracer() {
+        let (world, lights, cam) = small_scene();
+        let settings = small_settings();
+        let recursive = render(&world, &lights, cam, &settings);
+        let wavefront = render_wavefront(&world, &lights, cam, &settings);
+        for (a, b) in recursive.as_raw().iter().zip(wavefront.as_raw()) {
+            assert!((*a as i32 - *b as i32).abs() <= 1, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "without sample_map or adaptive")]
+    fn wavefront_rejects_adaptive() {
+        let (world, lights, cam) = small_scene();
+        let settings = RenderSettings {
+            adaptive: Some(Adaptive {
+                min_samples: 1,
+                max_samples: 4,
+                tolerance: 0.1,
+            }),
+            ..small_settings()
+        };
+        render_wavefront(&world, &lights, cam, &settings);
+    }
+
+    #[test]
+    fn sample_maps_spend_samples_where_they_are_white() {
+        let mut importance = GrayImage::new(2, 1);
+        importance.put_pixel(0, 0, image::Luma([255]));
+        importance.put_pixel(1, 0, image::Luma([0]));
+        let map = Box::leak(Box::new(SampleMap::new(importance, 2, 32)));
+        let (world, lights, cam) = small_scene();
+        let settings = RenderSettings {
+            sample_map: Some(map),
+            ..small_settings()
+        };
+        for y in 0..settings.image_height {
+            for x in 0..settings.image_width {
+                let mut rng = SampleSequence::new(x, y, 0, 1);
+                let (_, samples) = render_pixel(&world, &lights, &cam, &settings, (x, y), &mut rng);
+                let expected = if x < settings.image_width / 2 { 32 } else { 2 };
+                assert_eq!(samples, expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn letterboxes_bar_the_top_and_bottom_of_wide_views() {
+        let (world, lights, _) = small_scene();
+        let cam = Camera::new(
+            Point3::new(0., 0.5, 2.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            16. / 9.,
+            0.,
+            3.,
+            0.,
+            1.,
+        );
+        let bar = Color::new(0.2, 0.9, 0.4);
+        let settings = RenderSettings {
+            image_width: 16,
+            image_height: 16,
+            fit: Fit::Letterbox(bar),
+            ..small_settings()
+        };
+        let expected = encode_pixel((bar * settings.samples_per_pixel as f64, 0), &settings);
+        let img = render(&world, &lights, cam, &settings);
+        let is_bar = |y: u32| (0..16).all(|x| img.get_pixel(x, y).0 == expected[..3]);
+        // 16 / (16 / 9) rounds to 9 rows of picture above 3 rows of bar, so
+        // the 4 rows left over go on top.
+        for y in 0..16 {
+            assert_eq!(is_bar(y), !(4..13).contains(&y), "row {}", y);
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_only_where_pixels_are_flat() {
+        let (world, lights, cam) = small_scene();
+        let adaptive = Adaptive {
+            min_samples: 4,
+            max_samples: 64,
+            tolerance: 1e-6,
+        };
+        let settings = small_settings();
+        let samples_at = |cam: &Camera, pixel| {
+            let mut rng = SampleSequence::new(0, 0, 0, 1);
+            sample_pixel_adaptive(&world, &lights, cam, &settings, pixel, adaptive, &mut rng).1
+        };
+        // Facing away from everything, every sample is the black background.
+        let away = Camera::new(
+            Point3::new(0., 0.5, 2.),
+            Point3::new(0., 0.5, 10.),
+            DEFAULT_VUP,
+            40.,
+            8. / 6.,
+            0.,
+            3.,
+            0.,
+            1.,
+        );
+        assert_eq!(samples_at(&away, (4, 3)), 4);
+        // The lit diffuse sphere under the middle of the view never gets that
+        // sure of itself.
+        assert_eq!(samples_at(&cam, (4, 2)), 64);
+    }
+
+    #[test]
+    fn jitter_offsets_move_primary_rays_by_their_difference() {
+        let (_, _, cam) = small_scene();
+        let at = |offset| {
+            let settings = RenderSettings {
+                jitter_offset: Some(offset),
+                ..small_settings()
+            };
+            let mut seq = SampleSequence::new(3, 2, 0, 1);
+            primary_ray(&cam, &settings, (3, 2), 0, &mut seq)
+        };
+        let (near, far) = (at((0.25, 0.25)), at((0.75, 0.75)));
+
+        // The viewport spans `image_width - 1` by `image_height - 1` pixels.
+        let settings = small_settings();
+        let du = 0.5 / (settings.image_width - 1) as f64;
+        let dv = 0.5 / (settings.image_height - 1) as f64;
+        let step =
+            cam.get_ray_at(du, dv, (0., 0.), 0.).dir - cam.get_ray_at(0., 0., (0., 0.), 0.).dir;
+        assert!((far.orig - near.orig).length() < 1e-12);
+        assert!((far.dir - near.dir - step).length() < 1e-12);
+        assert!(step.length() > 0.01);
+    }
+
+    #[test]
+    fn glass_logs_refractions() {
+        let (world, lights, _) = small_scene_with(Dielectric::new(1.5));
+        let settings = small_settings();
+        let r = Ray::new(Point3::new(0., 0., 1.), Vec3::new(0., 0., -1.), 0.);
+        let mut refracted = 0;
+        for i in 0..50 {
+            let mut first = None;
+            let mut log = |b: &Bounce| {
+                first.get_or_insert((b.event, b.point, b.material));
+            };
+            let mut rng = SampleSequence::new(0, 0, i, 1);
+            ray_color(
+                r,
+                &world,
+                &lights,
+                &settings,
+                4,
+                Color::new(1., 1., 1.),
+                &mut rng,
+                Some(&mut log),
+            );
+            let (event, point, material) = first.unwrap();
+            assert!((point - Point3::new(0., 0., -0.5)).length() < 1e-9);
+            assert!(material.ends_with("Dielectric"), "{}", material);
+            match event {
+                BounceEvent::Refract => refracted += 1,
+                BounceEvent::Reflect => {}
+                event => panic!("{:?} on glass", event),
+            }
+        }
+        // Head on, glass of index 1.5 reflects 4% of the light.
+        assert!(refracted >= 40, "{} of 50 refracted", refracted);
+    }
+
+    #[test]
+    fn mirrors_are_hotter_than_diffuse_walls() {
+        let (world, lights, cam) = small_scene_with(Metal::new(Color::new(0.9, 0.9, 0.9), 0.));
+        let settings = RenderSettings {
+            image_width: 32,
+            image_height: 24,
+            max_depth: 8,
+            ..small_settings()
+        };
+        let heatmap = render_bounce_heatmap(&world, &lights, cam, &settings);
+        // The step of `heat_color` closest to a pixel, 0 for one bounce.
+        let heat = |pixel: &image::Rgb<u8>| {
+            let color = Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64) / 255.;
+            let steps = settings.max_depth - 1;
+            (0..=steps)
+                .map(|i| (color - heat_color(i as f64 / steps as f64)).length())
+                .enumerate()
+                .fold(
+                    (0, f64::INFINITY),
+                    |best, (i, d)| if d < best.1 { (i, d) } else { best },
+                )
+                .0 as f64
+        };
+        let (mut mirror, mut wall) = (Vec::new(), Vec::new());
+        for (x, row, pixel) in heatmap.enumerate_pixels() {
+            let s = (x as f64 + 0.5) / (settings.image_width - 1) as f64;
+            let t = (settings.image_height - row) as f64 - 0.5;
+            let ray = cam.get_ray_at(s, t / (settings.image_height - 1) as f64, (0., 0.), 0.);
+            let rec = match world.hit(ray, 0.001, f64::INFINITY) {
+                Some(rec) => rec,
+                None => continue,
+            };
+            let on_ground = |p: Point3| p.y < -0.4;
+            if on_ground(rec.p) {
+                wall.push(heat(pixel));
+            } else if (rec.p - Point3::new(0., 0., -1.)).length() < 0.5 + 1e-9 {
+                // Mirror pixels that show the ground.
+                let bounced = Ray::new(rec.p, ray.dir.reflect_about(rec.normal), 0.);
+                if let Some(seen) = world.hit(bounced, 0.001, f64::INFINITY) {
+                    if on_ground(seen.p) {
+                        mirror.push(heat(pixel));
+                    }
+                }
+            }
+        }
+        assert!(
+            mirror.len() > 10 && wall.len() > 50,
+            "{} {}",
+            mirror.len(),
+            wall.len()
+        );
+        let average = |heats: &[f64]| heats.iter().sum::<f64>() / heats.len() as f64;
+        let (mirror, wall) = (average(&mirror), average(&wall));
+        assert!(mirror > wall + 0.5, "mirror {} wall {}", mirror, wall);
+    }
+
+    #[test]
+    fn flat_shading_is_base_color_times_facing() {
+        let spheres = [
+            (Point3::new(-3., 0., -5.), Color::new(0.9, 0.1, 0.1)),
+            (Point3::new(-1., 0., -5.), Color::new(0.1, 0.9, 0.1)),
+            (Point3::new(1., 0., -5.), Color::new(0.1, 0.1, 0.9)),
+            (Point3::new(3., 0., -5.), Color::new(0.8, 0.8, 0.2)),
+        ];
+        let mut list = HittableList::default();
+        for &(center, albedo) in &spheres {
+            list.add(Arc::new(Sphere::new(center, 0.8, Lambertian::new(albedo))));
+        }
+        let world = BvhNode::new_list(&list, 0., 1.);
+        let settings = small_settings();
+        let mut rng = SampleSequence::new(0, 0, 0, 1);
+        for &(center, albedo) in &spheres {
+            for &(dx, dy) in &[(0., 0.), (0.5, 0.), (0., -0.6), (0.3, 0.7)] {
+                let dir = (center + Vec3::new(dx, dy, 0.)).to_unit();
+                let (color, hit) = flat_color(
+                    Ray::new(Point3::new(0., 0., 0.), dir, 0.),
+                    &world,
+                    &settings,
+                    &mut rng,
+                );
+                assert!(hit);
+                // Nearest root of |t dir - center| = 0.8.
+                let b = Vec3::dot(dir, center);
+                let t = b - (b * b - center.length_squared() + 0.64).sqrt();
+                let normal = (dir * t - center) / 0.8;
+                let expected = albedo * Vec3::dot(normal, -dir);
+                assert!((color - expected).length() < 1e-9);
+            }
+        }
+        let (sky, hit) = flat_color(
+            Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., 1., 0.), 0.),
+            &world,
+            &settings,
+            &mut rng,
+        );
+        assert!(!hit && (sky - settings.background.sample(Vec3::new(0., 1., 0.))).length() < 1e-12);
+    }
+
+    #[test]
+    fn stratified_offsets_have_lower_variance() {
+        // Coverage of a pixel by a slanted edge, a quarter of it.
+        let covered = |(du, dv): (f64, f64)| if du + 0.5 * dv < 0.5 { 1. } else { 0. };
+        let samples = 16;
+        let variance = |stratified: bool| {
+            let estimates: Vec<f64> = (0..2000)
+                .map(|pixel| {
+                    (0..samples)
+                        .map(|i| {
+                            let mut seq = SampleSequence::new(pixel, 0, i as u32, 1);
+                            let offset = seq.pixel_offset();
+                            covered(if stratified {
+                                stratify(offset, i, samples)
+                            } else {
+                                offset
+                            })
+                        })
+                        .sum::<f64>()
+                        / samples as f64
+                })
+                .collect();
+            let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+            assert!((mean - 0.25).abs() < 0.01, "{}", mean);
+            estimates
+                .iter()
+                .map(|e| (e - mean) * (e - mean))
+                .sum::<f64>()
+                / estimates.len() as f64
+        };
+        let (stratified, uniform) = (variance(true), variance(false));
+        assert!(
+            stratified < uniform / 2.,
+            "{} against {}",
+            stratified,
+            uniform
+        );
+    }
+
+    /// `cargo test --release -- --ignored --nocapture` prints single threaded
+    /// render times of `scene::random_scene` for both tracers.
+    #[test]
+    #[ignore]
+    fn bench_wavefront_against_recursive() {
+        static SKY: Color = Color {
+            x: 0.7,
+            y: 0.8,
+            z: 1.,
+        };
+        let world = BvhNode::new_list(&crate::scene::random_scene(), 0., 1.);
+        let mut lights = HittableList::default();
+        lights.add(Arc::new(Sphere::new(
+            Point3::new(0., 1., 0.),
+            1.,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        )));
+        let cam = Camera::new(
+            Point3::new(13., 2., 3.),
+            Point3::new(0., 0., 0.),
+            DEFAULT_VUP,
+            20.,
+            1.5,
+            0.,
+            10.,
+            0.,
+            1.,
+        );
+        let settings = RenderSettings {
+            image_width: 150,
+            image_height: 100,
+            samples_per_pixel: 16,
+            max_depth: 16,
+            thread_number: 1,
+            background: &SKY,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        render(&world, &lights, cam, &settings);
+        println!("recursive: {:?}", start.elapsed());
+        let start = std::time::Instant::now();
+        render_wavefront(&world, &lights, cam, &settings);
+        println!("wavefront: {:?}", start.elapsed());
+    }
+
+    /// Mean column of the pixels where red clearly beats green, i.e. the
+    /// middle sphere of `small_scene`.
+    fn red_column(img: &RgbImage) -> f64 {
+        let columns: Vec<f64> = img
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p[0] as i32 > p[1] as i32 + 20)
+            .map(|(x, _, _)| x as f64)
+            .collect();
+        assert!(!columns.is_empty());
+        columns.iter().sum::<f64>() / columns.len() as f64
+    }
+
+    #[test]
+    fn stereo_pairs_shift_the_object_between_eyes() {
+        let (world, lights, _) = small_scene();
+        let center = Camera::new(
+            Point3::new(0., 0.5, 2.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            4. / 3.,
+            0.,
+            3.,
+            0.,
+            1.,
+        );
+        let eye = |x: f64| center.moved_right(x);
+        let settings = RenderSettings {
+            image_width: 32,
+            image_height: 24,
+            flat: true,
+            ..small_settings()
+        };
+        let images = render_multi(&world, &lights, &[eye(-0.2), eye(0.2)], &settings);
+        assert_eq!(images.len(), 2);
+        // From the left eye the sphere sits right of center, and vice versa.
+        let (left, right) = (red_column(&images[0]), red_column(&images[1]));
+        assert!(left > 15.5 + 1. && right < 15.5 - 1., "{} {}", left, right);
+
+        // Each image is what rendering that camera alone gives.
+        for (image, x) in images.iter().zip([-0.2, 0.2].iter()) {
+            let alone = render(&world, &lights, eye(*x), &settings);
+            assert_eq!(image.as_raw(), alone.as_raw());
+        }
+    }
+
+    #[test]
+    fn only_silhouettes_are_edges() {
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., 0., -1.),
+            0.5,
+            Lambertian::new(Color::new(0.9, 0.9, 0.9)),
+        )));
+        let world = BvhNode::new_list(&world, 0., 1.);
+        let cam = Camera::new(
+            Point3::new(0., 0., 1.),
+            Point3::new(0., 0., -1.),
+            DEFAULT_VUP,
+            40.,
+            1.,
+            0.,
+            2.,
+            0.,
+            1.,
+        );
+        let (width, height) = (32, 32);
+        let settings = RenderSettings {
+            image_width: width,
+            image_height: height,
+            flat: true,
+            ..small_settings()
+        };
+        let pixels = render_pixels(&world, &HittableList::default(), &[cam], &settings).remove(0);
+        let edges = edge_pixels(&pixels, width, height, 0.25);
+
+        // Whether each pixel's center sees the sphere, in image order.
+        let inside: Vec<bool> = (0..width * height)
+            .map(|i| {
+                let s = (i % width) as f64 + 0.5;
+                let t = (i / width) as f64 + 0.5;
+                let ray = cam.get_ray_at(s / width as f64, 1. - t / height as f64, (0., 0.), 0.);
+                world.hit(ray, 0.001, f64::INFINITY).is_some()
+            })
+            .collect();
+        let near_silhouette = |x: i64, y: i64| {
+            let at = |x: i64, y: i64| inside[(y * width as i64 + x) as usize];
+            (-2..=2).any(|dy| {
+                (-2..=2).any(|dx| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    (0..width as i64).contains(&nx)
+                        && (0..height as i64).contains(&ny)
+                        && at(nx, ny) != at(x, y)
+                })
+            })
+        };
+        let mut flagged = 0;
+        for (i, &edge) in edges.iter().enumerate() {
+            let (x, y) = (i as i64 % width as i64, i as i64 / width as i64);
+            if edge {
+                assert!(near_silhouette(x, y), "({}, {}) is flagged", x, y);
+                flagged += 1;
+            }
+        }
+        // The silhouette is about 2 pi 8 pixels around, counted on both sides.
+        assert!(flagged > 40, "{}", flagged);
+
+        // A second pass resamples the flagged pixels and nothing else.
+        let more = render_edge_aware(&world, &HittableList::default(), cam, &settings, 16, 0.25);
+        for (i, p) in more.pixels().enumerate() {
+            if !edges[i] {
+                assert_eq!(p.0[..], pixels[i][..3]);
+            }
+        }
+    }
+
+    #[test]
+    fn render_into_matches_render() {
+        let (world, lights, cam) = small_scene();
+        let settings = small_settings();
+        let img = render(&world, &lights, cam, &settings);
+
+        let mut buffer = vec![0; 8 * 6 * 3];
+        render_into(&mut buffer, 8 * 3, &world, &lights, cam, &settings).unwrap();
+        assert_eq!(buffer.as_slice(), img.as_raw());
+
+        // Padding at the end of each row is left alone.
+        let stride = 8 * 3 + 5;
+        let mut padded = vec![7; stride * 6];
+        render_into(&mut padded, stride, &world, &lights, cam, &settings).unwrap();
+        for (y, row) in padded.chunks(stride).enumerate() {
+            assert_eq!(&row[..8 * 3], &img.as_raw()[y * 8 * 3..(y + 1) * 8 * 3]);
+            assert!(row[8 * 3..].iter().all(|&b| b == 7));
+        }
+    }
+
+    #[test]
+    fn render_into_rejects_small_buffers() {
+        let (world, lights, cam) = small_scene();
+        let settings = small_settings();
+
+        let mut short = vec![0; 8 * 6 * 3 - 1];
+        match render_into(&mut short, 8 * 3, &world, &lights, cam, &settings) {
+            Err(RenderError::BufferSize { expected, actual }) => {
+                assert_eq!((expected, actual), (8 * 6 * 3, 8 * 6 * 3 - 1));
+            }
+            _ => panic!("expected a buffer size error"),
+        }
+
+        let mut buffer = vec![0; 8 * 6 * 4];
+        let rgba = RenderSettings {
+            alpha: true,
+            ..settings
+        };
+        assert!(matches!(
+            render_into(&mut buffer, 8 * 3, &world, &lights, cam, &rgba),
+            Err(RenderError::Stride {
+                min: 32,
+                actual: 24
+            })
+        ));
+    }
+
+    #[test]
+    fn probe_samples_wants_more_for_caustics() {
+        let settings = RenderSettings {
+            image_width: 16,
+            image_height: 12,
+            max_depth: 6,
+            ..small_settings()
+        };
+        let (world, lights, cam) = small_scene();
+        let smooth = probe_samples(&world, &lights, cam, &settings, 0.05);
+        let (world, lights, cam) = small_scene_with(Dielectric::new(1.5));
+        let caustic = probe_samples(&world, &lights, cam, &settings, 0.05);
+        assert!(smooth >= 1);
+        assert!(caustic > smooth, "caustic {} <= smooth {}", caustic, smooth);
+    }
+
+    #[test]
+    fn coverage_leaves_seeded_colors_alone() {
+        // The medium draws a free path on every intersection, so testing the
+        // primary ray a second time for coverage would shift the samples.
+        let boundary = Sphere::new(
+            Point3::new(0., 0., -1.),
+            0.5,
+            Lambertian::new(Color::new(0., 0., 0.)),
+        );
+        let medium = ConstantMedium::new(boundary, 2., Color::new(0.8, 0.8, 0.8));
+        let (world, lights, cam) = small_scene_around(Arc::new(medium));
+        let settings = small_settings();
+        let rgb = render(&world, &lights, cam, &settings);
+        let rgba = render_rgba(&world, &lights, cam, &settings);
+        for (p, q) in rgb.pixels().zip(rgba.pixels()) {
+            assert_eq!(p.0[..], q.0[..3]);
+        }
+        assert!(rgba.pixels().any(|p| p.0[3] == 255));
+    }
+
+    #[test]
+    fn srgb_follows_the_piecewise_curve() {
+        let srgb = |x: f64| ColorSpace::SrgbGamma.encode(x);
+        // Mid gray lands on 1.055 * 0.18^(1 / 2.4) - 0.055, above a pure 2.2 power.
+        assert!((srgb(0.18) - 0.461_356).abs() < 1e-6, "{}", srgb(0.18));
+        assert!(srgb(0.18) - ColorSpace::Gamma(2.2).encode(0.18) > 0.002);
+        // Linear near black, where a power curve would be far steeper.
+        assert!((srgb(0.001) - 0.012_92).abs() < 1e-9);
+        // The two pieces meet.
+        let knee = 0.003_130_8;
+        assert!((srgb(knee) - srgb(knee + 1e-12)).abs() < 1e-6);
+        assert!(srgb(1.) > 0.999_999 && srgb(1.) < 1.000_001);
+
+        let gray = Color::new(0.18, 0.18, 0.18);
+        assert_eq!(
+            write_color(gray, 1, ToneMap::None, ColorSpace::SrgbGamma),
+            [118, 118, 118]
+        );
+        assert_eq!(
+            write_color(gray, 1, ToneMap::None, ColorSpace::Gamma(2.2)),
+            [117, 117, 117]
+        );
+    }
+
+    #[test]
+    fn write_color_blacks_out_nan_and_negatives() {
+        let bad = Color::new(f64::NAN, -1., 0.5);
+        for &tone_map in [ToneMap::None, ToneMap::Reinhard, ToneMap::Aces].iter() {
+            let [r, g, b] = write_color(bad, 1, tone_map, ColorSpace::SrgbGamma);
+            assert_eq!((r, g), (0, 0));
+            assert!(b > 0);
+        }
+        // Averaged the same way as any other sample.
+        let [r, g, b] = write_color(
+            Color::new(f64::NAN, f64::NEG_INFINITY, 2.),
+            4,
+            ToneMap::None,
+            ColorSpace::Linear,
+        );
+        assert_eq!([r, g, b], [0, 0, 127]);
+    }
+
+    /// Fails the test on any draw.
+    struct NoDraws;
+
+    impl RngCore for NoDraws {
+        fn next_u32(&mut self) -> u32 {
+            panic!("drew from the rng")
+        }
+        fn next_u64(&mut self) -> u64 {
+            panic!("drew from the rng")
+        }
+        fn fill_bytes(&mut self, _dest: &mut [u8]) {
+            panic!("drew from the rng")
+        }
+        fn try_fill_bytes(&mut self, _dest: &mut [u8]) -> Result<(), rand::Error> {
+            panic!("drew from the rng")
+        }
+    }
+
+    #[test]
+    fn roulette_off_never_draws() {
+        let dim = Color::new(0.01, 0.01, 0.01);
+        assert_eq!(RussianRoulette::Off.play(dim, &mut NoDraws), Some(1.));
+
+        let mut rng = rand::thread_rng();
+        let played = (0..1000)
+            .filter_map(|_| RussianRoulette::Luminance.play(dim, &mut rng))
+            .count();
+        // Survives with the 0.05 floor.
+        assert!(played > 20 && played < 90, "{}", played);
+    }
+
+    /// Per-sample luminance of the center pixel, and how far each sample's
+    /// largest channel went past `limit`.
+    /// Red channel of rays traced from the middle of a deep red room lit by
+    /// one small panel, with `roulette`.
+    fn red_cavity_samples(roulette: RussianRoulette) -> Vec<f64> {
+        let red = Lambertian::new(Color::new(0.9, 0.05, 0.05));
+        let light = Arc::new(XZRect::new(
+            -0.5,
+            0.5,
+            -0.5,
+            0.5,
+            2.9,
+            DiffuseLight::new_two_sided(Color::new(4., 4., 4.)),
+        ));
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::new(0., 0., 0.), 3., red)));
+        world.add(light.clone());
+        let mut lights = HittableList::default();
+        lights.add(light);
+        let world = BvhNode::new_list(&world, 0., 1.);
+        let settings = RenderSettings {
+            max_depth: 30,
+            russian_roulette: roulette,
+            ..small_settings()
+        };
+        let mut rng = SampleSequence::new(1, 2, 0, 9);
+        (0..20000)
+            .map(|_| {
+                let dir = Vec3::random_unit_vector(&mut rng);
+                let r = Ray::new(Point3::new(0., -1., 0.), dir, 0.);
+                trace(&world, &lights, &settings, r, &mut rng).0.x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn max_component_roulette_keeps_red_paths_alive() {
+        let stats = |samples: &[f64]| {
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let variance =
+                samples.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+            (mean, variance)
+        };
+        let (reference, reference_variance) = stats(&red_cavity_samples(RussianRoulette::Off));
+        let (luminance, luminance_variance) =
+            stats(&red_cavity_samples(RussianRoulette::Luminance));
+        let (max, max_variance) = stats(&red_cavity_samples(RussianRoulette::MaxComponent));
+
+        // Both stay unbiased...
+        let n = 20000.;
+        for &(mean, variance) in [(luminance, luminance_variance), (max, max_variance)].iter() {
+            let stderr = ((variance + reference_variance) / n).sqrt();
+            assert!(
+                (mean - reference).abs() < 4. * stderr,
+                "{} vs {} (stderr {})",
+                mean,
+                reference,
+                stderr
+            );
+        }
+        // ...but luminance, low for red throughput, kills the paths carrying
+        // the color and leaves it much noisier.
+        assert!(
+            max_variance * 2. < luminance_variance,
+            "{} vs {}",
+            max_variance,
+            luminance_variance
+        );
+    }
+
+    fn center_samples(settings: &RenderSettings, limit: f64) -> Vec<(f64, f64)> {
+        let (world, lights, cam) = small_scene();
+        let mut rng = SampleSequence::new(4, 3, 0, 7);
+        (0..20000)
+            .map(|_| {
+                let r = cam.get_ray(0.5, 0.5, &mut rng);
+                let (c, _) = trace(&world, &lights, settings, r, &mut rng);
+                let luminance = 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+                (luminance, (c.x.max(c.y).max(c.z) - limit).max(0.))
+            })
+            .collect()
+    }
+
+    fn mean(samples: &[(f64, f64)], f: impl Fn(&(f64, f64)) -> f64) -> f64 {
+        samples.iter().map(f).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn roulette_stays_unbiased_and_clamp_bias_is_bounded() {
+        let limit = 1.;
+        let base = RenderSettings {
+            max_depth: 8,
+            ..small_settings()
+        };
+        let roulette = RenderSettings {
+            russian_roulette: RussianRoulette::Luminance,
+            ..base
+        };
+        let neither = center_samples(&base, limit);
+        let rr = center_samples(&roulette, limit);
+        let clamped = center_samples(
+            &RenderSettings {
+                firefly_clamp: Some(limit),
+                ..base
+            },
+            limit,
+        );
+        let both = center_samples(
+            &RenderSettings {
+                firefly_clamp: Some(limit),
+                ..roulette
+            },
+            limit,
+        );
+
+        // Roulette alone: same mean up to noise.
+        let reference = mean(&neither, |s| s.0);
+        let variance = |v: &[(f64, f64)], m: f64| mean(v, |s| (s.0 - m).powi(2));
+        let rr_mean = mean(&rr, |s| s.0);
+        let stderr = ((variance(&neither, reference) + variance(&rr, rr_mean))
+            / neither.len() as f64)
+            .sqrt();
+        assert!(
+            (rr_mean - reference).abs() < 4. * stderr,
+            "roulette {} vs reference {} (stderr {})",
+            rr_mean,
+            reference,
+            stderr
+        );
+
+        // The clamp doesn't draw, so each configuration sees the same paths
+        // as its unclamped one; it only takes off what went past the cap.
+        for (unclamped, clamped) in [(&neither, &clamped), (&rr, &both)] {
+            let bias = mean(unclamped, |s| s.0) - mean(clamped, |s| s.0);
+            assert!(bias > 0., "the clamp never bit");
+            assert!(bias <= mean(unclamped, |s| s.1) + 1e-12);
+        }
+        // Roulette spreads the same energy over fewer, brighter samples, so
+        // more of it gets clamped.
+        let clamp_bias = reference - mean(&clamped, |s| s.0);
+        let both_bias = reference - mean(&both, |s| s.0);
+        assert!(both_bias > clamp_bias, "{} <= {}", both_bias, clamp_bias);
+        // Barely any plain sample goes past the cap.
+        assert!(
+            clamp_bias < 0.01 * reference,
+            "{} of {}",
+            clamp_bias,
+            reference
+        );
+    }
+
+    /// `trace_path` as it was written before the loop, one call per bounce.
+    fn recursive_color(
+        r: Ray,
+        world: &BvhNode,
+        lights: &HittableList,
+        settings: &RenderSettings,
+        depth: i32,
+        throughput: Color,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        if depth <= 0 {
+            return Color::new(0., 0., 0.);
+        }
+        let rec = match world.hit(r, 0.001, f64::MAX) {
+            Some(rec) => rec,
+            None => return settings.background.sample(r.dir),
+        };
+        let emitted = rec.mat_ptr.emitted(r, &rec, rec.u, rec.v, rec.p);
+        let srec = match rec.mat_ptr.scatter(r, &rec, rng) {
+            Some(srec) => srec,
+            None => return emitted,
+        };
+        if let Some(specular) = srec.specular_ray {
+            let throughput = throughput * srec.attenuation;
+            return match settings.russian_roulette.play(throughput, rng) {
+                Some(survival) => {
+                    srec.attenuation
+                        * recursive_color(
+                            specular,
+                            world,
+                            lights,
+                            settings,
+                            depth - 1,
+                            throughput,
+                            rng,
+                        )
+                        / survival
+                }
+                None => Color::new(0., 0., 0.),
+            };
+        }
+        let light_ptr = HittablePdf::new(lights, rec.p, r.tm);
+        let p = MixturePdf::new(light_ptr, srec.pdf_ptr.unwrap());
+        let scattered = Ray::new(rec.p, p.generate(rng), r.tm);
+        let pdf_val = p.value(scattered.dir);
+        if pdf_val < MIN_PDF {
+            return emitted;
+        }
+        let weight = srec.attenuation * rec.mat_ptr.scattering_pdf(r, &rec, scattered) / pdf_val;
+        let throughput = throughput * weight;
+        match settings.russian_roulette.play(throughput, rng) {
+            Some(survival) => {
+                emitted
+                    + weight
+                        * recursive_color(
+                            scattered,
+                            world,
+                            lights,
+                            settings,
+                            depth - 1,
+                            throughput,
+                            rng,
+                        )
+                        / survival
+            }
+            None => emitted,
+        }
+    }
+
+    #[test]
+    fn iterative_paths_match_recursive_ones() {
+        // The default scene's room, with glass in place of the OBJ.
+        let (mut world, lights) = scene::cornell_room();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(190., 90., 190.),
+            90.,
+            Dielectric::new(1.5),
+        )));
+        let world = BvhNode::new_list(&world, 0., 1.);
+        let cam = Camera::new(
+            Point3::new(278., 278., -800.),
+            Point3::new(278., 278., 0.),
+            DEFAULT_VUP,
+            40.,
+            1.,
+            0.,
+            10.,
+            0.,
+            1.,
+        );
+        let throughput = Color::new(1., 1., 1.);
+        for &russian_roulette in [RussianRoulette::Off, RussianRoulette::Luminance].iter() {
+            let settings = RenderSettings {
+                image_width: 8,
+                image_height: 8,
+                max_depth: 10,
+                russian_roulette,
+                ..small_settings()
+            };
+            for (x, y) in (0..8).flat_map(|y| (0..8).map(move |x| (x, y))) {
+                for i in 0..4 {
+                    let mut seq = SampleSequence::new(x, y, i as u32, 1);
+                    let r = primary_ray(&cam, &settings, (x, y), i, &mut seq);
+                    let looped = ray_color(
+                        r, &world, &lights, &settings, 10, throughput, &mut seq, None,
+                    );
+                    let mut seq = SampleSequence::new(x, y, i as u32, 1);
+                    let r = primary_ray(&cam, &settings, (x, y), i, &mut seq);
+                    let recursed =
+                        recursive_color(r, &world, &lights, &settings, 10, throughput, &mut seq);
+                    // Only the order of the additions differs.
+                    let scale = 1. + recursed.length();
+                    assert!(
+                        (looped - recursed).length() < 1e-9 * scale,
+                        "pixel ({}, {}) sample {}: {} vs {}",
+                        x,
+                        y,
+                        i,
+                        looped,
+                        recursed
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ppms_round_trip_through_a_file() {
+        let mut img: RgbImage = ImageBuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                img.put_pixel(
+                    x,
+                    y,
+                    image::Rgb([x as u8 * 100, y as u8 * 200, 10 + x as u8]),
+                );
+            }
+        }
+        let path = std::env::temp_dir().join(format!("raytracer-ppm-{}.ppm", std::process::id()));
+        write_ppm(path.to_str().unwrap(), &img).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert!(bytes.starts_with(header));
+        let pixels = &bytes[header.len()..];
+        assert_eq!(pixels, img.as_raw());
+        // Rows run top to bottom, each pixel as R, G, B.
+        assert_eq!(&pixels[..3], &[0, 0, 10]);
+        assert_eq!(&pixels[3 * 5..], &[200, 200, 12]);
+    }
+
+    #[test]
+    fn fresh_sequences_give_a_pixel_the_same_radiance() {
+        let (world, lights, cam) = small_scene();
+        // Unseeded, so everything the pixel draws comes from the sequence.
+        let settings = RenderSettings {
+            seed: None,
+            ..small_settings()
+        };
+        let radiance = || {
+            let mut seq = SampleSequence::new(4, 2, 0, 9);
+            render_pixel(&world, &lights, &cam, &settings, (4, 2), &mut seq)
+                .0
+                 .0
+        };
+        let (first, second) = (radiance(), radiance());
+        assert!(first.length() > 0.);
+        assert!((first - second).length() < 1e-12, "{} vs {}", first, second);
+    }
+
+    #[test]
+    fn seeded_glass_renders_repeat() {
+        let (world, lights, cam) = small_scene_with(Dielectric::new(1.5).with_roughness(0.2));
+        let settings = small_settings();
+        let first = render(&world, &lights, cam, &settings);
+        let second = render(&world, &lights, cam, &settings);
+        assert!(first.as_raw() == second.as_raw());
+        // Another seed takes other refraction and reflection choices.
+        let settings = RenderSettings {
+            seed: Some(2),
+            ..settings
+        };
+        assert!(render(&world, &lights, cam, &settings).as_raw() != first.as_raw());
+    }
+
+    #[test]
+    fn seeded_renders_repeat_across_thread_counts() {
+        let (world, lights, cam) = small_scene();
+        let settings = small_settings();
+        let image = render(&world, &lights, cam, &settings);
+        // Tiles of 3 leave partial tiles along both edges of the 8x6 image.
+        for &parallel in [Parallel::Pixels, Parallel::Tiles(3)].iter() {
+            for &thread_number in [1, 2, 3].iter() {
+                let settings = RenderSettings {
+                    thread_number,
+                    parallel,
+                    ..settings
+                };
+                assert!(render(&world, &lights, cam, &settings).as_raw() == image.as_raw());
+            }
+        }
+    }
+
+    #[test]
+    fn sample_parallel_matches_pixel_parallel() {
+        let (world, lights, cam) = small_scene();
+        let settings = RenderSettings {
+            thread_number: 3,
+            samples_per_pixel: 9,
+            ..small_settings()
+        };
+        let by_pixels = render(&world, &lights, cam, &settings);
+        let settings = RenderSettings {
+            parallel: Parallel::Samples,
+            ..settings
+        };
+        let by_samples = render(&world, &lights, cam, &settings);
+        // Only the order of the additions differs.
+        for (a, b) in by_pixels.as_raw().iter().zip(by_samples.as_raw()) {
+            assert!((*a as i32 - *b as i32).abs() <= 1);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't sample adaptively")]
+    fn sample_parallel_rejects_adaptive() {
+        let (world, lights, cam) = small_scene();
+        let settings = RenderSettings {
+            parallel: Parallel::Samples,
+            adaptive: Some(Adaptive {
+                min_samples: 4,
+                max_samples: 16,
+                tolerance: 0.01,
+            }),
+            ..small_settings()
+        };
+        render(&world, &lights, cam, &settings);
+    }
+}
@@ -3,12 +3,17 @@ use std::sync::Arc;
 use rand::Rng;
 
 use crate::{
-    basic::vec::{Color, Point3, Vec3},
+    basic::{
+        convention::CoordinateConvention,
+        vec::{Color, Point3, Vec3},
+    },
+    builder::SceneBuilder,
     hittable::{
         aarect::{XYRect, XZRect, YZRect},
         boxes::Boxes,
         bvh::BvhNode,
         constantmedium::ConstantMedium,
+        mesh::{self, Mesh},
         sphere::{MovingSphere, Sphere},
         triangle::Triangle,
         FlipFace, HittableList, RotateY, Translate, Zoom,
@@ -38,9 +43,9 @@ pub fn random_scene() -> HittableList {
                 b as f64 + 0.9 * rng.gen::<f64>(),
             );
 
-            if (center - Point3::new(4., 0.2, 0.)).length() > 0.9 {
+            if Vec3::distance(center, Point3::new(4., 0.2, 0.)) > 0.9 {
                 if choose_mat < 0.80 {
-                    let albedo = Color::random(0., 1.);
+                    let albedo = Color::random(&mut rng);
                     let center2 = center + Vec3::new(0., rng.gen_range(0.0..0.5), 0.);
                     world.add(Arc::new(MovingSphere::new(
                         center,
@@ -51,7 +56,7 @@ pub fn random_scene() -> HittableList {
                         Lambertian::new(albedo),
                     )));
                 } else if choose_mat < 0.95 {
-                    let albedo = Color::random(0.5, 1.);
+                    let albedo = Color::random_range(&mut rng, 0.5, 1.);
                     let fuzz = rng.gen_range(0.0..0.5);
                     world.add(Arc::new(Sphere::new(center, 0.2, Metal::new(albedo, fuzz))));
                 } else {
@@ -82,6 +87,25 @@ pub fn random_scene() -> HittableList {
     world
 }
 
+#[allow(dead_code)]
+pub fn four_spheres() -> HittableList {
+    let mut builder = SceneBuilder::new();
+
+    let checker = CheckerTexture::new(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    let ground = builder.material_lambertian_texture(checker);
+    let glass = builder.material_dielectric(1.5);
+    let diffuse = builder.material_lambertian(Color::new(0.4, 0.2, 0.1));
+    let metal = builder.material_metal(Color::new(0.7, 0.6, 0.5), 0.);
+
+    builder
+        .add_sphere(Point3::new(0., -1000., 0.), 1000., &ground)
+        .add_sphere(Point3::new(0., 1., 0.), 1., &glass)
+        .add_sphere(Point3::new(-4., 1., 0.), 1., &diffuse)
+        .add_sphere(Point3::new(4., 1., 0.), 1., &metal);
+
+    builder.build().0
+}
+
 #[allow(dead_code)]
 pub fn two_spheres() -> HittableList {
     let mut world: HittableList = Default::default();
@@ -122,9 +146,17 @@ pub fn two_perlin_spheres() -> HittableList {
     world
 }
 
+/// The image at `path`, or after saying why it didn't load, a cyan stand-in.
+fn image_or_cyan(path: &str) -> ImageTexture {
+    ImageTexture::new(path).unwrap_or_else(|e| {
+        eprintln!("Could not load {}: {}", path, e);
+        ImageTexture::default()
+    })
+}
+
 #[allow(dead_code)]
 pub fn earth() -> HittableList {
-    let earth_texture = ImageTexture::new("input/earthmap.jpg");
+    let earth_texture = image_or_cyan("input/earthmap.jpg");
     let earth_surface = Lambertian::new_arc(earth_texture);
 
     let mut world: HittableList = Default::default();
@@ -160,8 +192,9 @@ pub fn simple_light() -> HittableList {
     world
 }
 
-#[allow(dead_code)]
-pub fn cornell_box() -> (HittableList, HittableList) {
+/// The Cornell box's walls and light with nothing inside, and the light to
+/// sample.
+pub fn cornell_room() -> (HittableList, HittableList) {
     let mut world: HittableList = Default::default();
 
     let red = Lambertian::new(Color::new(0.65, 0.05, 0.05));
@@ -185,6 +218,23 @@ pub fn cornell_box() -> (HittableList, HittableList) {
     )));
     world.add(Arc::new(XYRect::new(0., 555., 0., 555., 555., white)));
 
+    let mut lights = HittableList::default();
+    lights.add(Arc::new(XZRect::new(
+        213.,
+        343.,
+        227.,
+        332.,
+        554.,
+        Dielectric::new(0.),
+    )));
+
+    (world, lights)
+}
+
+#[allow(dead_code)]
+pub fn cornell_box() -> (HittableList, HittableList) {
+    let (mut world, lights) = cornell_room();
+
     //    let box1 = Boxes::new(
     //        Point3::new(0., 0., 0.),
     //        Point3::new(165., 330., 165.),
@@ -218,17 +268,7 @@ pub fn cornell_box() -> (HittableList, HittableList) {
     // )));
 
     // objects
-    get_object(&mut world);
-
-    let mut lights = HittableList::default();
-    lights.add(Arc::new(XZRect::new(
-        213.,
-        343.,
-        227.,
-        332.,
-        554.,
-        Dielectric::new(0.),
-    )));
+    get_object(&mut world, CoordinateConvention::YUpRightHanded, Some(1e-6));
 
     (world, lights)
 }
@@ -366,7 +406,7 @@ pub fn final_scene() -> HittableList {
         Color::new(1., 1., 1.),
     )));
 
-    let emat = Lambertian::new_arc(ImageTexture::new("source/earthmap.jpg"));
+    let emat = Lambertian::new_arc(image_or_cyan("source/earthmap.jpg"));
     world.add(Arc::new(Sphere::new(
         Point3::new(400., 200., 400.),
         100.,
@@ -385,7 +425,7 @@ pub fn final_scene() -> HittableList {
     let ns = 1000;
     for _i in 0..ns {
         box2.add(Arc::new(Sphere::new(
-            Point3::random(0., 165.),
+            Point3::random_range(&mut rng, 0., 165.),
             10.,
             white.clone(),
         )));
@@ -399,7 +439,27 @@ pub fn final_scene() -> HittableList {
     world
 }
 
-fn get_object(world: &mut HittableList) {
+/// World and lights of the scene called `name`, e.g. `"cornell_box"`. Scenes
+/// without importance sampled lights get an empty light list.
+pub fn by_name(name: &str) -> Option<(HittableList, HittableList)> {
+    let world = match name {
+        "random_scene" => random_scene(),
+        "four_spheres" => four_spheres(),
+        "two_spheres" => two_spheres(),
+        "two_perlin_spheres" => two_perlin_spheres(),
+        "earth" => earth(),
+        "simple_light" => simple_light(),
+        "cornell_box" => return Some(cornell_box()),
+        "cornell_smoke" => cornell_smoke(),
+        "final_scene" => final_scene(),
+        _ => return None,
+    };
+    Some((world, HittableList::default()))
+}
+
+/// Loads the OBJ into `world`. With `weld`, vertices closer than it are merged
+/// into one shared, smoothly shaded vertex buffer.
+fn get_object(world: &mut HittableList, convention: CoordinateConvention, weld: Option<f64>) {
     let obj = tobj::load_obj(
         "source/obj/patrick.obj",
         &tobj::LoadOptions {
@@ -431,17 +491,43 @@ fn get_object(world: &mut HittableList) {
             let x = mesh.positions[3 * v] as f64;
             let y = mesh.positions[3 * v + 1] as f64;
             let z = mesh.positions[3 * v + 2] as f64;
-            vertices.push(Point3::new(x, y, z));
-        }
-        let mut object = HittableList::default();
-
-        for v in 0..mesh.indices.len() / 3 {
-            let x = vertices[mesh.indices[v * 3] as usize];
-            let y = vertices[mesh.indices[v * 3 + 1] as usize];
-            let z = vertices[mesh.indices[v * 3 + 2] as usize];
-            let tri = Triangle::new(x, y, z, Lambertian::new(Color::new(0.78, 0.78, 0.78)));
-            object.add(Arc::new(tri));
+            vertices.push(convention.to_world(Point3::new(x, y, z)));
         }
+        let material = Lambertian::new(Color::new(0.78, 0.78, 0.78));
+        let object = if let Some(epsilon) = weld {
+            let mut faces = Vec::new();
+            for v in 0..mesh.indices.len() / 3 {
+                let mut face = [
+                    mesh.indices[v * 3] as usize,
+                    mesh.indices[v * 3 + 1] as usize,
+                    mesh.indices[v * 3 + 2] as usize,
+                ];
+                if convention.is_mirrored() {
+                    face.swap(1, 2);
+                }
+                faces.push(face);
+            }
+            let welded = Mesh {
+                positions: vertices,
+                normals: None,
+                faces,
+            }
+            .weld(epsilon);
+            mesh::triangles(Arc::new(welded), material)
+        } else {
+            let mut object = HittableList::default();
+            for v in 0..mesh.indices.len() / 3 {
+                let x = vertices[mesh.indices[v * 3] as usize];
+                let mut y = vertices[mesh.indices[v * 3 + 1] as usize];
+                let mut z = vertices[mesh.indices[v * 3 + 2] as usize];
+                if convention.is_mirrored() {
+                    std::mem::swap(&mut y, &mut z);
+                }
+                let tri = Triangle::new(x, y, z, material.clone());
+                object.add(Arc::new(tri));
+            }
+            object
+        };
 
         let object = BvhNode::new_list(&object, 0., 1.);
         let object = Zoom::new(object, 200.);
@@ -0,0 +1,584 @@
+//! Scenes described in JSON instead of Rust:
+//!
+//! ```json
+//! {
+//!   "image": { "width": 500, "height": 500, "samples_per_pixel": 100, "max_depth": 50 },
+//!   "background": [0, 0, 0],
+//!   "camera": { "lookfrom": [278, 278, -800], "lookat": [278, 278, 0], "vfov": 40 },
+//!   "materials": {
+//!     "white": { "type": "lambertian", "albedo": [0.73, 0.73, 0.73] },
+//!     "light": { "type": "diffuse_light", "emit": [15, 15, 15] }
+//!   },
+//!   "objects": [
+//!     { "type": "quad", "axis": "xz", "a": [213, 343], "b": [227, 332], "k": 554,
+//!       "material": "light", "light": true, "flip": true },
+//!     { "type": "sphere", "center": [190, 90, 190], "radius": 90, "material": "white" }
+//!   ]
+//! }
+//! ```
+//!
+//! A `{ "type": "disk", "center": [x, y, z], "normal": [x, y, z], "radius": r }`
+//! is a flat circle, and `{ "type": "plane", "point": [x, y, z], "normal": [x, y, z] }`
+//! an unbounded ground.
+//! Lambertian albedos may also be a texture: `{ "checker": [[r, g, b], [r, g, b]],
+//! "period": 0.6 }`, `{ "noise": scale }`, `{ "image": "path" }` or
+//! `{ "gradient": [[r, g, b], [r, g, b]] }`, bottom to top along v, or along
+//! world height with `"y": [bottom, top]`. So may a metal's `fuzz`, read from
+//! the red channel.
+//! Besides the materials below there are the debug `mirror` and `refractor`
+//! (with an `ir`). Any material takes a `"normal_map"` texture and
+//! `"double_sided": true` for thin surfaces seen from both sides.
+//! The camera's `aperture` and `focus_dist` give depth of field, and a
+//! `focus_dist1` pulls the focus there over the shutter interval. A camera
+//! `aspect` other than the image's is squeezed into it, unless the image has
+//! `"letterbox": [r, g, b]` bars to fill the rest with.
+//! A `"background": { "hdr": "path" }` lights the scene with an
+//! equirectangular sky, which is importance sampled like the other lights.
+
+use std::{collections::HashMap, fs, sync::Arc};
+
+use crate::{
+    background::{EnvMapBackground, EnvMapSampler},
+    basic::{
+        camera::{Camera, DEFAULT_VUP},
+        vec::{Point3, Vec3},
+    },
+    builder::{RectAxis, SceneBuilder},
+    hittable::{FlipFace, Hittable, HittableList},
+    json::Json,
+    material::{
+        CookTorrance, Dielectric, DiffuseLight, DoubleSided, MaterialHandle, Metal, NormalMapped,
+        OrenNayar, PerfectMirror, PerfectRefractor,
+    },
+    render::{Fit, RenderSettings},
+    texture::{
+        CheckerTexture, GradientAxis, GradientTexture, HdrImageTexture, ImageTexture, NoiseTexture,
+        Texture,
+    },
+};
+
+/// Loads world, lights, camera and settings from a JSON scene file. Settings
+/// the file doesn't mention keep their `RenderSettings::default()` values.
+pub fn load_scene(
+    path: &str,
+) -> Result<(HittableList, HittableList, Camera, RenderSettings), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_scene(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// `load_scene` on the file's contents.
+fn parse_scene(text: &str) -> Result<(HittableList, HittableList, Camera, RenderSettings), String> {
+    let doc = Json::parse(text).map_err(|e| e.to_string())?;
+    let err = |what: String| what;
+
+    let mut settings = RenderSettings::default();
+    if let Some(image) = doc.get("image") {
+        if let Some(width) = image.get("width").and_then(Json::as_usize) {
+            settings.image_width = width as u32;
+        }
+        if let Some(height) = image.get("height").and_then(Json::as_usize) {
+            settings.image_height = height as u32;
+        }
+        if let Some(spp) = image.get("samples_per_pixel").and_then(Json::as_usize) {
+            settings.samples_per_pixel = spp as i32;
+        }
+        if let Some(depth) = image.get("max_depth").and_then(Json::as_usize) {
+            settings.max_depth = depth as i32;
+        }
+        if let Some(bars) = image.get("letterbox") {
+            let color = vec3(bars).ok_or_else(|| err("letterbox must be [r, g, b]".to_string()))?;
+            settings.fit = Fit::Letterbox(color);
+        }
+    }
+    if settings.image_width == 0 || settings.image_height == 0 {
+        return Err(err("image size must be positive".to_string()));
+    }
+    // Settings only hold 'static backgrounds; one per loaded scene is fine.
+    let mut sky = None;
+    if let Some(background) = doc.get("background") {
+        if let Some(path) = background.get("hdr").and_then(Json::as_str) {
+            let texture =
+                HdrImageTexture::new(path).map_err(|e| err(format!("background: {}", e)))?;
+            let (width, height) = texture.dimensions();
+            sky = Some(EnvMapSampler::new(
+                &texture,
+                width as usize,
+                height as usize,
+            ));
+            settings.background = Box::leak(Box::new(EnvMapBackground { texture }));
+        } else {
+            let color = vec3(background).ok_or_else(|| err("bad background".to_string()))?;
+            settings.background = Box::leak(Box::new(color));
+        }
+    }
+
+    let cam = camera(doc.get("camera"), &settings).map_err(err)?;
+
+    let mut builder = SceneBuilder::new();
+    let mut materials = HashMap::new();
+    if let Some(members) = doc.get("materials").and_then(Json::as_object) {
+        for (name, mat) in members {
+            let handle = material(&builder, mat)
+                .map_err(|e| err(format!("material \"{}\": {}", name, e)))?;
+            materials.insert(name.as_str(), handle);
+        }
+    }
+
+    let objects = doc
+        .get("objects")
+        .and_then(Json::as_array)
+        .ok_or_else(|| err("missing objects".to_string()))?;
+    for (i, object) in objects.iter().enumerate() {
+        add_object(&mut builder, &materials, object)
+            .map_err(|e| err(format!("object {}: {}", i, e)))?;
+    }
+
+    match (builder.build(), sky) {
+        ((world, Some(mut lights)), Some(sky)) => {
+            lights.add(Arc::new(sky));
+            Ok((world, lights, cam, settings))
+        }
+        ((world, None), Some(sky)) => {
+            let mut lights = HittableList::default();
+            lights.add(Arc::new(sky));
+            Ok((world, lights, cam, settings))
+        }
+        ((world, Some(lights)), None) => Ok((world, lights, cam, settings)),
+        ((_, None), None) => Err(err(
+            "no object is marked \"light\": true; lights are importance sampled".to_string(),
+        )),
+    }
+}
+
+fn vec3(json: &Json) -> Option<Vec3> {
+    Some(Vec3::new(
+        json.at(0)?.as_f64()?,
+        json.at(1)?.as_f64()?,
+        json.at(2)?.as_f64()?,
+    ))
+}
+
+fn pair(json: &Json) -> Option<(f64, f64)> {
+    Some((json.at(0)?.as_f64()?, json.at(1)?.as_f64()?))
+}
+
+fn number(json: &Json, key: &str) -> Result<f64, String> {
+    json.get(key)
+        .and_then(Json::as_f64)
+        .ok_or_else(|| format!("missing number \"{}\"", key))
+}
+
+fn point(json: &Json, key: &str) -> Result<Point3, String> {
+    json.get(key)
+        .and_then(vec3)
+        .ok_or_else(|| format!("missing [x, y, z] \"{}\"", key))
+}
+
+fn camera(json: Option<&Json>, settings: &RenderSettings) -> Result<Camera, String> {
+    let json = json.ok_or("missing camera")?;
+    let lookfrom = point(json, "lookfrom")?;
+    let lookat = point(json, "lookat")?;
+    let vup = json.get("vup").and_then(vec3).unwrap_or(DEFAULT_VUP);
+    let vfov = number(json, "vfov")?;
+    let aperture = json.get("aperture").and_then(Json::as_f64).unwrap_or(0.);
+    let focus_dist = json
+        .get("focus_dist")
+        .and_then(Json::as_f64)
+        .unwrap_or_else(|| (lookfrom - lookat).length());
+    // Pulls focus to this distance over the shutter interval.
+    let focus_dist1 = json
+        .get("focus_dist1")
+        .and_then(Json::as_f64)
+        .unwrap_or(focus_dist);
+    // Squeezed into the image unless it is letterboxed.
+    let aspect = json
+        .get("aspect")
+        .and_then(Json::as_f64)
+        .unwrap_or(settings.image_width as f64 / settings.image_height as f64);
+    if !(aspect > 0. && aspect.is_finite()) {
+        return Err("aspect must be positive".to_string());
+    }
+    Ok(Camera::new(
+        lookfrom, lookat, vup, vfov, aspect, aperture, focus_dist, 0., 1.,
+    )
+    .with_focus_pull(focus_dist1))
+}
+
+fn material(builder: &SceneBuilder, json: &Json) -> Result<MaterialHandle, String> {
+    let mut mat = surface(builder, json)?;
+    if let Some(normal_map) = json.get("normal_map") {
+        let normal_map = texture(normal_map).map_err(|e| format!("normal_map: {}", e))?;
+        mat = builder.material(NormalMapped::new(Arc::new(mat), normal_map));
+    }
+    if json
+        .get("double_sided")
+        .and_then(Json::as_bool)
+        .unwrap_or(false)
+    {
+        mat = builder.material(DoubleSided::new(mat));
+    }
+    Ok(mat)
+}
+
+/// The material `json` names by its type, before any normal map.
+fn surface(builder: &SceneBuilder, json: &Json) -> Result<MaterialHandle, String> {
+    let kind = json
+        .get("type")
+        .and_then(Json::as_str)
+        .ok_or("missing type")?;
+    match kind {
+        "lambertian" => {
+            let albedo = json.get("albedo").ok_or("missing albedo")?;
+            match vec3(albedo) {
+                Some(color) => Ok(builder.material_lambertian(color)),
+                None => Ok(builder.material_lambertian_texture(texture(albedo)?)),
+            }
+        }
+        "oren_nayar" => Ok(builder.material(OrenNayar::new(
+            point(json, "albedo")?,
+            number(json, "sigma")?,
+        ))),
+        "metal" => {
+            let albedo = point(json, "albedo")?;
+            match json.get("fuzz") {
+                Some(fuzz) if fuzz.as_f64().is_none() => {
+                    let fuzz = texture(fuzz).map_err(|e| format!("fuzz: {}", e))?;
+                    Ok(builder.material(Metal::new_textured(albedo, fuzz)))
+                }
+                fuzz => Ok(builder.material_metal(
+                    albedo,
+                    fuzz.and_then(Json::as_f64).unwrap_or(0.),
+                )),
+            }
+        }
+        "dielectric" => {
+            let mut glass = Dielectric::new(number(json, "ir")?)
+                .with_roughness(json.get("roughness").and_then(Json::as_f64).unwrap_or(0.));
+            // The color absorbed per unit of path inside, e.g. [0, 1, 1] for red glass.
+            if let Some(absorb) = json.get("absorb") {
+                let color = vec3(absorb).ok_or("absorb must be [r, g, b]")?;
+                glass = glass.with_absorption(color, number(json, "density")?);
+            }
+            Ok(builder.material(glass))
+        }
+        "cook_torrance" => Ok(builder.material(CookTorrance::new(
+            point(json, "base_color")?,
+            number(json, "roughness")?,
+            json.get("metalness").and_then(Json::as_f64).unwrap_or(1.),
+        ))),
+        "diffuse_light" => {
+            let emit = point(json, "emit")?;
+            let two_sided = json.get("two_sided").and_then(Json::as_bool).unwrap_or(false);
+            match json.get("intensity").and_then(Json::as_f64) {
+                Some(intensity) => {
+                    let mut light = DiffuseLight::with_intensity(emit, intensity);
+                    light.two_sided = two_sided;
+                    Ok(builder.material(light))
+                }
+                None if two_sided => Ok(builder.material(DiffuseLight::new_two_sided(emit))),
+                None => Ok(builder.material_diffuse_light(emit)),
+            }
+        }
+        "mirror" => Ok(builder.material(PerfectMirror)),
+        "refractor" => Ok(builder.material(PerfectRefractor::new(number(json, "ir")?))),
+        _ => Err(format!(
+            "unknown material type \"{}\" (expected lambertian, oren_nayar, metal, cook_torrance, dielectric, diffuse_light, mirror or refractor)",
+            kind
+        )),
+    }
+}
+
+fn texture(json: &Json) -> Result<Arc<dyn Texture>, String> {
+    if let Some(checker) = json.get("checker") {
+        let (odd, even) = match (checker.at(0).and_then(vec3), checker.at(1).and_then(vec3)) {
+            (Some(odd), Some(even)) => (odd, even),
+            _ => return Err("checker needs two colors".to_string()),
+        };
+        let mut texture = CheckerTexture::new(odd, even);
+        if let Some(period) = json.get("period").and_then(Json::as_f64) {
+            texture = texture.with_period(period);
+        }
+        return Ok(Arc::new(texture));
+    }
+    if let Some(scale) = json.get("noise").and_then(Json::as_f64) {
+        return Ok(Arc::new(NoiseTexture::new(scale)));
+    }
+    if let Some(path) = json.get("image").and_then(Json::as_str) {
+        let texture = ImageTexture::new(path).map_err(|e| format!("image \"{}\": {}", path, e))?;
+        return Ok(Arc::new(texture));
+    }
+    if let Some(gradient) = json.get("gradient") {
+        let (bottom, top) = match (gradient.at(0).and_then(vec3), gradient.at(1).and_then(vec3)) {
+            (Some(bottom), Some(top)) => (bottom, top),
+            _ => return Err("gradient needs two colors".to_string()),
+        };
+        let mut texture = GradientTexture::new(bottom, top);
+        if let Some(y) = json.get("y") {
+            let (bottom, top) = pair(y).ok_or("gradient y must be [bottom, top]")?;
+            texture = texture.with_axis(GradientAxis::Y { bottom, top });
+        }
+        return Ok(Arc::new(texture));
+    }
+    Err("texture must be a checker, noise, image or gradient".to_string())
+}
+
+fn add_object(
+    builder: &mut SceneBuilder,
+    materials: &HashMap<&str, MaterialHandle>,
+    json: &Json,
+) -> Result<(), String> {
+    let name = json
+        .get("material")
+        .and_then(Json::as_str)
+        .ok_or("missing material")?;
+    let mat = materials
+        .get(name)
+        .ok_or_else(|| format!("unknown material \"{}\"", name))?;
+    let kind = json
+        .get("type")
+        .and_then(Json::as_str)
+        .ok_or("missing type")?;
+
+    // Built on the side so it can be added as a light too.
+    let mut single = SceneBuilder::new();
+    match kind {
+        "sphere" => single.add_sphere(point(json, "center")?, number(json, "radius")?, mat),
+        "box" => single.add_box(point(json, "min")?, point(json, "max")?, mat),
+        "plane" => single.add_plane(point(json, "point")?, point(json, "normal")?, mat),
+        "disk" => single.add_disk(
+            point(json, "center")?,
+            point(json, "normal")?,
+            number(json, "radius")?,
+            mat,
+        ),
+        "quad" => {
+            let axis = match json.get("axis").and_then(Json::as_str) {
+                Some("xy") => RectAxis::XY,
+                Some("xz") => RectAxis::XZ,
+                Some("yz") => RectAxis::YZ,
+                _ => return Err("quad axis must be \"xy\", \"xz\" or \"yz\"".to_string()),
+            };
+            let a = json
+                .get("a")
+                .and_then(pair)
+                .ok_or("missing [min, max] \"a\"")?;
+            let b = json
+                .get("b")
+                .and_then(pair)
+                .ok_or("missing [min, max] \"b\"")?;
+            single.add_quad(axis, a, b, number(json, "k")?, mat)
+        }
+        _ => {
+            return Err(format!(
+                "unknown object type \"{}\" (expected sphere, box, plane, disk or quad)",
+                kind
+            ))
+        }
+    };
+    let (world, _) = single.build();
+    // Lights only emit from their front face, so a ceiling light needs "flip".
+    let object: Arc<dyn Hittable> = if json.get("flip").and_then(Json::as_bool).unwrap_or(false) {
+        Arc::new(FlipFace::new(world))
+    } else {
+        Arc::new(world)
+    };
+    if json.get("light").and_then(Json::as_bool).unwrap_or(false) {
+        builder.add_light(object);
+    } else {
+        builder.add(object);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::ray::Ray;
+
+    const SCENE: &str = r#"{
+        "image": { "width": 40, "height": 20, "samples_per_pixel": 3, "max_depth": 7 },
+        "camera": { "lookfrom": [0, 0, 5], "lookat": [0, 0, 0], "vfov": 40 },
+        "materials": {
+            "white": { "type": "lambertian", "albedo": [0.73, 0.73, 0.73] },
+            "light": { "type": "diffuse_light", "emit": [3, 3, 3], "intensity": 5 }
+        },
+        "objects": [
+            { "type": "quad", "axis": "xz", "a": [-1, 1], "b": [-1, 1], "k": 3,
+              "material": "light", "light": true, "flip": true },
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "white" },
+            { "type": "box", "min": [2, 2, 2], "max": [3, 3, 3], "material": "white" },
+            { "type": "disk", "center": [0, -1, 0], "normal": [0, 1, 0], "radius": 2,
+              "material": "white" }
+        ]
+    }"#;
+
+    #[test]
+    fn scenes_load_settings_objects_and_lights() {
+        let (world, lights, _, settings) = parse_scene(SCENE).unwrap();
+        assert_eq!((settings.image_width, settings.image_height), (40, 20));
+        assert_eq!(settings.samples_per_pixel, 3);
+        assert_eq!(settings.max_depth, 7);
+        assert_eq!(world.objects.len(), 4);
+        assert_eq!(lights.objects.len(), 1);
+
+        let r = Ray::new(Point3::new(0., 0., 5.), Vec3::new(0., 0., -1.), 0.);
+        let rec = world.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.).abs() < 1e-9);
+        assert!(rec.mat_ptr.name().contains("Lambertian"));
+
+        // The flipped light faces down, towards the sphere.
+        let up = Ray::new(Point3::new(0., 2., 0.), Vec3::new(0., 1., 0.), 0.);
+        let rec = lights.hit(up, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.front_face);
+        let emitted = rec.mat_ptr.emitted(up, &rec, rec.u, rec.v, rec.p);
+        assert!((emitted - Vec3::new(15., 15., 15.)).length() < 1e-9);
+
+        // The disk floors the scene under the sphere.
+        let down = Ray::new(Point3::new(1.5, 0., 0.), Vec3::new(0., -1., 0.), 0.);
+        assert!((world.hit(down, 0.001, f64::INFINITY).unwrap().t - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn materials_take_textures_maps_and_wrappers() {
+        let (world, _, cam, settings) = parse_scene(
+            r#"{
+            "image": { "width": 40, "height": 20, "letterbox": [0.1, 0.2, 0.3] },
+            "camera": { "lookfrom": [0, 0, 5], "lookat": [0, 0, 0], "vfov": 40, "aspect": 1 },
+            "materials": {
+                "sky": { "type": "lambertian",
+                         "albedo": { "gradient": [[0, 0, 0], [1, 1, 1]], "y": [-1, 1] } },
+                "polished": { "type": "metal", "albedo": [1, 1, 1],
+                              "fuzz": { "gradient": [[0, 0, 0], [0, 0, 0]] } },
+                "bent": { "type": "mirror",
+                          "normal_map": { "gradient": [[0.85, 0.5, 0.85], [0.85, 0.5, 0.85]] } },
+                "glass": { "type": "refractor", "ir": 1.5 },
+                "glow": { "type": "diffuse_light", "emit": [1, 1, 1], "double_sided": true }
+            },
+            "objects": [
+                { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "sky" },
+                { "type": "sphere", "center": [4, 0, 0], "radius": 1, "material": "polished" },
+                { "type": "sphere", "center": [8, 0, 0], "radius": 1, "material": "bent" },
+                { "type": "sphere", "center": [12, 0, 0], "radius": 1, "material": "glass" },
+                { "type": "sphere", "center": [16, 0, 0], "radius": 1, "material": "glow",
+                  "light": true }
+            ]
+        }"#,
+        )
+        .unwrap();
+        assert!((cam.params().aspect_ratio - 1.).abs() < 1e-12);
+        match settings.fit {
+            Fit::Letterbox(bars) => assert!((bars - Vec3::new(0.1, 0.2, 0.3)).length() < 1e-12),
+            Fit::Stretch => panic!("not letterboxed"),
+        }
+
+        let mut rng = rand::thread_rng();
+        let down = |x: f64| Ray::new(Point3::new(x, 0., 5.), Vec3::new(0., 0., -1.), 0.);
+        let scatter = |r: Ray, rng: &mut dyn rand::RngCore| {
+            let rec = world.hit(r, 0.001, f64::INFINITY).unwrap();
+            rec.mat_ptr.scatter(r, &rec, rng).unwrap()
+        };
+
+        // The gradient runs up the sphere's height.
+        let top = Ray::new(Point3::new(0., 5., 0.), Vec3::new(0., -1., 0.), 0.);
+        let bottom = Ray::new(Point3::new(0., -5., 0.), Vec3::new(0., 1., 0.), 0.);
+        assert!((scatter(top, &mut rng).attenuation - Vec3::new(1., 1., 1.)).length() < 1e-9);
+        assert!(scatter(bottom, &mut rng).attenuation.length() < 1e-9);
+
+        // A black fuzz map polishes the metal into a mirror.
+        let reflected = scatter(down(4.), &mut rng).specular_ray.unwrap().dir;
+        assert!((reflected.to_unit() - Vec3::new(0., 0., 1.)).length() < 1e-9);
+
+        // The normal map tilts the mirror's normal 45 degrees off the sphere's.
+        let reflected = scatter(down(8.), &mut rng).specular_ray.unwrap().dir;
+        assert!(reflected.to_unit().z.abs() < 1e-9, "{}", reflected);
+
+        let rec = world.hit(down(12.), 0.001, f64::INFINITY).unwrap();
+        assert!(rec.mat_ptr.name().ends_with("PerfectRefractor"));
+
+        // Seen from inside, the double sided light still glows.
+        let out = Ray::new(Point3::new(16., 0., 0.), Vec3::new(0., 0., 1.), 0.);
+        let rec = world.hit(out, 0.001, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        let emitted = rec.mat_ptr.emitted(out, &rec, rec.u, rec.v, rec.p);
+        assert!((emitted - Vec3::new(1., 1., 1.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn bad_scenes_say_what_is_wrong() {
+        let cases = [
+            ("[", "JSON error"),
+            ("{}", "missing camera"),
+            (
+                r#"{ "camera": { "lookfrom": [0, 0, 5], "lookat": [0, 0, 0] }, "objects": [] }"#,
+                "missing number \"vfov\"",
+            ),
+            (r#"{ "camera": CAMERA }"#, "missing objects"),
+            (
+                r#"{ "camera": CAMERA, "objects": [{ "type": "sphere", "material": "none" }] }"#,
+                "object 0: unknown material \"none\"",
+            ),
+            (
+                r#"{ "camera": CAMERA, "materials": { "m": { "type": "plastic" } }, "objects": [] }"#,
+                "material \"m\": unknown material type \"plastic\"",
+            ),
+            (
+                r#"{ "camera": CAMERA, "materials": { "m": { "type": "metal", "albedo": [1, 1, 1] } },
+                     "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "m" }] }"#,
+                "no object is marked",
+            ),
+            (
+                r#"{ "image": { "width": 0 }, "objects": [] }"#,
+                "image size must be positive",
+            ),
+            (
+                r#"{ "background": { "hdr": "no/such/sky.hdr" }, "camera": CAMERA, "objects": [] }"#,
+                "background: ",
+            ),
+            (
+                r#"{ "camera": CAMERA,
+                     "materials": { "m": { "type": "lambertian", "albedo": { "image": "no/such.png" } } },
+                     "objects": [] }"#,
+                "material \"m\": image \"no/such.png\": ",
+            ),
+            (
+                r#"{ "camera": CAMERA,
+                     "materials": { "m": { "type": "lambertian", "albedo": { "gradient": [[0, 0, 0]] } } },
+                     "objects": [] }"#,
+                "material \"m\": gradient needs two colors",
+            ),
+            (
+                r#"{ "camera": CAMERA,
+                     "materials": { "m": { "type": "mirror", "normal_map": { "marble": 1 } } },
+                     "objects": [] }"#,
+                "material \"m\": normal_map: texture must be",
+            ),
+            (
+                r#"{ "camera": CAMERA, "materials": { "m": { "type": "refractor" } }, "objects": [] }"#,
+                "material \"m\": missing number \"ir\"",
+            ),
+            (
+                r#"{ "image": { "letterbox": "black" }, "camera": CAMERA, "objects": [] }"#,
+                "letterbox must be [r, g, b]",
+            ),
+            (
+                r#"{ "camera": { "lookfrom": [0, 0, 5], "lookat": [0, 0, 0], "vfov": 40, "aspect": 0 },
+                     "objects": [] }"#,
+                "aspect must be positive",
+            ),
+        ];
+        let camera = r#"{ "lookfrom": [0, 0, 5], "lookat": [0, 0, 0], "vfov": 40 }"#;
+        for (text, expected) in cases.iter() {
+            let text = text.replace("CAMERA", camera);
+            match parse_scene(&text) {
+                Err(e) => assert!(e.contains(expected), "{} -> {}", text, e),
+                Ok(_) => panic!("{} loaded", text),
+            }
+        }
+    }
+
+    #[test]
+    fn load_scene_names_the_file() {
+        let err = load_scene("no/such/scene.json").err().unwrap();
+        assert!(err.starts_with("no/such/scene.json: "), "{}", err);
+    }
+}
@@ -1,10 +1,14 @@
 pub mod perlin;
 
-use image::GenericImageView;
+use std::{f64::consts::PI, fs::File, io::BufReader, sync::Arc};
+
+use image::{codecs::hdr::HdrDecoder, GenericImageView, ImageResult};
 
 use crate::basic::vec::{Color, Point3};
 use crate::texture::perlin::Perlin;
 
+/// Values are linear and unbounded: emission may exceed 1 and detail maps may
+/// go negative. Only the final image write clamps.
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Point3) -> Color;
 }
@@ -26,6 +30,13 @@ impl Texture for SolidColor {
     }
 }
 
+/// A texture picked at run time, e.g. from a scene file.
+impl Texture for Arc<dyn Texture> {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        (**self).value(u, v, p)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct CheckerTexture<TO, TE>
 where
@@ -34,21 +45,35 @@ where
 {
     odd: TO,
     even: TE,
+    // World units the pattern repeats over along each axis, one odd and one
+    // even check.
+    pub period: f64,
 }
 
-impl CheckerTexture<SolidColor, SolidColor> {
-    #[allow(dead_code)]
-    pub fn new(c1: Color, c2: Color) -> Self {
+impl<TO: Texture + Clone + Copy, TE: Texture + Clone + Copy> CheckerTexture<TO, TE> {
+    pub fn new_arc(odd: TO, even: TE) -> Self {
         Self {
-            odd: SolidColor::new(c1),
-            even: SolidColor::new(c2),
+            odd,
+            even,
+            period: PI / 5.,
         }
     }
+    pub fn with_period(mut self, period: f64) -> Self {
+        self.period = period;
+        self
+    }
+}
+
+impl CheckerTexture<SolidColor, SolidColor> {
+    pub fn new(c1: Color, c2: Color) -> Self {
+        Self::new_arc(SolidColor::new(c1), SolidColor::new(c2))
+    }
 }
 
 impl<TO: Texture + Clone + Copy, TE: Texture + Clone + Copy> Texture for CheckerTexture<TO, TE> {
     fn value(&self, u: f64, v: f64, p: Point3) -> Color {
-        let sines = (p.x * 10.).sin() * (p.y * 10.).sin() * (p.z * 10.).sin();
+        let k = 2. * PI / self.period;
+        let sines = (k * p.x).sin() * (k * p.y).sin() * (k * p.z).sin();
         if sines < 0. {
             self.odd.value(u, v, p)
         } else {
@@ -76,7 +101,50 @@ impl Texture for NoiseTexture {
     }
 }
 
-#[derive(Clone)]
+/// What a `GradientTexture` blends along.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GradientAxis {
+    V,
+    // World-space height, bottom color at `bottom` and top color at `top`.
+    Y { bottom: f64, top: f64 },
+}
+
+/// Linear blend from `bottom` to `top`, e.g. a sky painted on a big sphere.
+#[derive(Clone, Copy)]
+pub struct GradientTexture {
+    bottom: Color,
+    top: Color,
+    pub axis: GradientAxis,
+}
+
+impl GradientTexture {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self {
+            bottom,
+            top,
+            axis: GradientAxis::V,
+        }
+    }
+    pub fn with_axis(mut self, axis: GradientAxis) -> Self {
+        self.axis = axis;
+        self
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, _u: f64, v: f64, p: Point3) -> Color {
+        let t = match self.axis {
+            GradientAxis::V => v,
+            GradientAxis::Y { bottom, top } => (p.y - bottom) / (top - bottom),
+        };
+        let t = t.clamp(0., 1.);
+        self.bottom * (1. - t) + self.top * t
+    }
+}
+
+/// The default, empty texture shows up cyan, e.g. in place of a file that
+/// failed to load.
+#[derive(Clone, Default)]
 pub struct ImageTexture {
     width: u32,
     height: u32,
@@ -84,8 +152,16 @@ pub struct ImageTexture {
 }
 
 impl ImageTexture {
-    pub fn new(filename: &str) -> Self {
-        let img = image::open(filename).unwrap();
+    pub fn new(filename: &str) -> ImageResult<Self> {
+        Ok(Self::from_image(image::open(filename)?))
+    }
+
+    /// Decodes an encoded (PNG, JPEG, ...) image held in memory.
+    pub fn from_memory(bytes: &[u8]) -> ImageResult<Self> {
+        Ok(Self::from_image(image::load_from_memory(bytes)?))
+    }
+
+    fn from_image(img: image::DynamicImage) -> Self {
         let (width, height) = img.dimensions();
         let mut pixel_color: Vec<[u8; 3]> = Default::default();
 
@@ -135,3 +211,113 @@ impl Texture for ImageTexture {
         )
     }
 }
+
+/// Float image, e.g. a Radiance `.hdr`, sampled without any clamping.
+#[derive(Clone)]
+pub struct HdrImageTexture {
+    width: u32,
+    height: u32,
+    // Bottom row first, like `ImageTexture`.
+    pixel_color: Arc<Vec<Color>>,
+}
+
+impl HdrImageTexture {
+    pub fn new(filename: &str) -> ImageResult<Self> {
+        let decoder = HdrDecoder::new(BufReader::new(File::open(filename)?))?;
+        let metadata = decoder.metadata();
+        let pixels: Vec<Color> = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        Ok(Self::from_pixels(metadata.width, metadata.height, pixels))
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// `pixels` in image order, top row first.
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        let mut pixel_color = Vec::with_capacity(pixels.len());
+        for row in pixels.chunks(width.max(1) as usize).rev() {
+            pixel_color.extend_from_slice(row);
+        }
+        Self {
+            width,
+            height,
+            pixel_color: Arc::new(pixel_color),
+        }
+    }
+}
+
+impl Texture for HdrImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        if self.pixel_color.is_empty() {
+            return Color::new(0., 1., 1.);
+        }
+
+        let i = ((u.clamp(0., 1.) * self.width as f64) as usize).min(self.width as usize - 1);
+        let j = ((v.clamp(0., 1.) * self.height as f64) as usize).min(self.height as usize - 1);
+        self.pixel_color[j * self.width as usize + i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_repeat_every_period() {
+        let odd = Color::new(0., 0., 0.);
+        let even = Color::new(1., 1., 1.);
+        let checker = CheckerTexture::new(odd, even).with_period(2.);
+        let at = |x: f64| checker.value(0., 0., Point3::new(x, 0.5, 0.5)).x;
+        // Each check is half a period wide.
+        assert!(at(0.5) > 0.5);
+        assert!(at(1.5) < 0.5);
+        assert!(at(2.5) > 0.5);
+        assert!(at(-0.5) < 0.5);
+    }
+
+    #[test]
+    fn gradients_run_from_bottom_to_top() {
+        let (bottom, top) = (Color::new(0., 0., 1.), Color::new(1., 1., 0.));
+        let middle = Color::new(0.5, 0.5, 0.5);
+        let by_v = GradientTexture::new(bottom, top);
+        let origin = Point3::new(0., 0., 0.);
+        for &(v, expected) in [(0., bottom), (0.5, middle), (1., top)].iter() {
+            assert!(Color::distance(by_v.value(0.3, v, origin), expected) < 1e-12);
+        }
+
+        let by_y = by_v.with_axis(GradientAxis::Y {
+            bottom: -1.,
+            top: 3.,
+        });
+        for &(y, expected) in [(-1., bottom), (1., middle), (3., top), (10., top)].iter() {
+            // `v` no longer matters.
+            let p = Point3::new(0.2, y, -4.);
+            assert!(Color::distance(by_y.value(0.3, 0.9, p), expected) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn hdr_texels_keep_values_above_one() {
+        // Top row first: a 4.0 texel at the top left.
+        let texture = HdrImageTexture::from_pixels(
+            2,
+            2,
+            vec![
+                Color::new(4., 4., 4.),
+                Color::new(0.5, 0.5, 0.5),
+                Color::new(0., 0., 0.),
+                Color::new(1., 2., 3.),
+            ],
+        );
+        let p = Point3::new(0., 0., 0.);
+        let top_left = texture.value(0.25, 0.75, p);
+        assert!(Color::distance(top_left, Color::new(4., 4., 4.)) < 1e-12);
+        let bottom_right = texture.value(0.75, 0.25, p);
+        assert!(Color::distance(bottom_right, Color::new(1., 2., 3.)) < 1e-12);
+    }
+}
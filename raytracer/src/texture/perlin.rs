@@ -15,9 +15,10 @@ pub struct Perlin {
 impl Perlin {
     #[allow(clippy::needless_range_loop)]
     pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
         let mut randvec = [Vec3::new(0., 0., 0.); POINT_COUNT];
         for i in 0..POINT_COUNT {
-            randvec[i] = Vec3::random(-1., 1.).to_unit();
+            randvec[i] = Vec3::random_range(&mut rng, -1., 1.).to_unit();
         }
         let perm_x = Perlin::perlin_generate_perm();
         let perm_y = Perlin::perlin_generate_perm();
@@ -50,13 +51,11 @@ impl Perlin {
     #[allow(clippy::many_single_char_names)]
     #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, p: Point3) -> f64 {
-        let mut u = p.x - p.x.floor();
-        let mut v = p.y - p.y.floor();
-        let mut w = p.z - p.z.floor();
-
-        u = u * u * (3. - 2. * u);
-        v = v * v * (3. - 2. * v);
-        w = w * w * (3. - 2. * w);
+        // Raw offsets in the cell: the gradients need them unsmoothed, and
+        // `trilinear_interp` applies the Hermite fade itself.
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
 
         let i = p.x.floor() as i32;
         let j = p.y.floor() as i32;